@@ -0,0 +1,107 @@
+//! Timings for the core graph operations on large generated graphs.
+//!
+//! Run with `cargo bench`. Set `TODOS_BENCH_NODES` to change the size of the
+//! generated graph (100,000 nodes by default).
+
+use std::env;
+use std::time::{Duration, Instant};
+use todos::{Config, Graph, NodeType};
+
+/// Small deterministic generator so every run measures the same graph.
+struct Lcg(u64);
+
+impl Lcg
+{
+    fn below(&mut self, bound: usize) -> usize
+    {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % bound
+    }
+}
+
+fn report(name: &str, operations: usize, elapsed: Duration)
+{
+    println!(
+        "{:<28} {:>8} ops {:>10.2?} total {:>10.2?}/op",
+        name,
+        operations,
+        elapsed,
+        elapsed / operations.max(1) as u32
+    );
+}
+
+fn time<F: FnMut(&mut Lcg)>(name: &str, operations: usize, rng: &mut Lcg, mut operation: F)
+{
+    let start = Instant::now();
+    for _ in 0..operations
+    {
+        operation(rng);
+    }
+    report(name, operations, start.elapsed());
+}
+
+fn main()
+{
+    let size: usize = env::var("TODOS_BENCH_NODES").ok().and_then(|x| x.parse().ok()).unwrap_or(100_000);
+    let extra = size / 10;
+    let mut rng = Lcg(0x5eed);
+    let mut graph = Graph::new(Config::default());
+
+    println!("generated graph of {} nodes", size);
+
+    // a random tree: every node hangs off an earlier one
+    let start = Instant::now();
+    graph.add_node_to("root".to_string(), NodeType::Goal, None).unwrap();
+    for i in 1..size
+    {
+        let parent = rng.below(i);
+        graph.add_node_to(format!("node {}", i), NodeType::Task, Some(parent)).unwrap();
+    }
+    report("add_node_to", size, start.elapsed());
+
+    // links always point from a lower id to a higher one, so they never
+    // close a loop, but each still needs a reachability check
+    let mut links = Vec::new();
+    time("link", extra, &mut rng, |rng| {
+        let a = rng.below(size);
+        let b = rng.below(size);
+        if a != b
+        {
+            let (parent, child) = (a.min(b), a.max(b));
+            graph.link(&parent, &child).unwrap();
+            links.push((parent, child));
+        }
+    });
+
+    time("link (rejected, cycle)", extra, &mut rng, |rng| {
+        let child = 1 + rng.below(size - 1);
+        let parent = *graph.node(child).unwrap().parents.first().unwrap();
+        assert!(graph.link(&child, &parent).is_err());
+    });
+
+    let mut unlinked = links.iter();
+    time("unlink", links.len(), &mut rng, |_| {
+        let (parent, child) = unlinked.next().unwrap();
+        graph.unlink(parent, child).unwrap();
+    });
+
+    time("move_node", extra, &mut rng, |rng| {
+        let id = 1 + rng.below(graph.nodes().len() - 1);
+        let to = rng.below(id);
+        graph.move_node(id, Some(to)).unwrap();
+    });
+
+    time("remove_node", extra, &mut rng, |rng| {
+        let id = rng.below(graph.nodes().len());
+        graph.remove_node(id, false).unwrap();
+    });
+
+    time("batch_remove (recursive)", 100, &mut rng, |rng| {
+        let id = rng.below(graph.nodes().len());
+        graph.batch_remove(vec![id], true).unwrap();
+    });
+
+    let start = Instant::now();
+    graph.validate().unwrap();
+    report("validate", 1, start.elapsed());
+}
@@ -1,11 +1,29 @@
 extern crate toml;
 extern crate serde;
 
+use crate::error::TodosError;
+
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Version of the config file format written by this build, bumped like
+/// [`SCHEMA_VERSION`](crate::graph::SCHEMA_VERSION), with a step in `migrate`.
+pub const CONFIG_VERSION: i64 = 1;
+
+/// Every setting understood in the config file. Any other key (besides
+/// `version`) is reported as a warning.
+pub const KEYS: [&str; 5] = [
+    "hide_backlog_items",
+    "backlog_name",
+    "goal_color",
+    "condition_color",
+    "task_color"
+];
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub hide_backlog_items: bool,
     pub backlog_name: String,
@@ -24,25 +42,218 @@ impl Default for Config
             condition_color: String::from("01;33"),
             task_color: String::from("0;39"),
             hide_backlog_items: true,
-            backlog_name: String::from("backlog_name")
+            backlog_name: String::from("backlog")
         }
     }
 }
 
+impl Config
+{
+    fn to_table(&self) -> toml::Table
+    {
+        // a struct of plain strings and bools always fits in a table
+        toml::Table::try_from(self).unwrap()
+    }
+
+    /// The value of a setting, formatted the way `todos config set` accepts it.
+    pub fn get(&self, key: &str) -> Option<String>
+    {
+        self.to_table().remove(key).map(|value| display_value(&value))
+    }
+
+    /// Every setting with its value, in `KEYS` order.
+    pub fn list(&self) -> Vec<(&'static str, String)>
+    {
+        KEYS.iter()
+            .map(|key| (*key, self.get(key).unwrap()))
+            .collect()
+    }
+}
+
+fn display_value(value: &toml::Value) -> String
+{
+    match value
+    {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string()
+    }
+}
+
+fn warn(path: &Path, message: String)
+{
+    eprintln!("\x1B[1;42m Config file {}: {}\x1B[00m", path.display(), message);
+}
+
+/// 1-based line and column of a byte offset into `content`.
+fn line_column(content: &str, offset: usize) -> (usize, usize)
+{
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+/// Reads the config file, falling back to the default of each setting that
+/// is missing or invalid. Problems are reported on stderr rather than
+/// treated as errors, so a typo never prevents todos from running.
 pub fn read_config_file(path: PathBuf) -> Config {
-    let config_opt = fs::read_to_string(&path).ok().map(|content| {
-        let config: Option<Config> = toml::from_str(&content).ok();
-        config
-    }).flatten();
+    let (config, warnings) = read_config(&path);
+    for message in warnings
+    {
+        warn(&path, message);
+    }
+    config
+}
+
+/// What `read_config_file` reads, along with the problems it reports.
+pub fn read_config(path: &Path) -> (Config, Vec<String>)
+{
+    let mut warnings = Vec::new();
+    let mut content = match fs::read_to_string(path)
+    {
+        Ok(content) => content,
+        Err(_) => { return (Config::default(), warnings); }
+    };
+
+    let mut table: toml::Table = match content.parse()
+    {
+        Ok(table) => table,
+        Err(error) => {
+            warnings.push(format!("{}Using default settings.", error));
+            return (Config::default(), warnings);
+        }
+    };
+
+    // a newer file is read as far as this build understands it, but never
+    // rewritten
+    let migrated = migrate(path, &mut table).unwrap_or_else(|error| {
+        warnings.push(format!("{} Unknown settings are ignored.", error));
+        false
+    });
+    if migrated
+    {
+        content = toml::to_string(&table).unwrap();
+        // best effort: if the file can't be rewritten, it is simply
+        // migrated again next time
+        let _ = fs::write(path, &content);
+    }
+
+    // parse once more, this time keeping track of where each value is
+    let spanned: BTreeMap<String, toml::Spanned<toml::Value>> = toml::from_str(&content).unwrap();
+
+    let mut settings = Config::default().to_table();
+    for (key, value) in spanned
+    {
+        let (line, column) = line_column(&content, value.span().start);
+        let value = value.into_inner();
+
+        if key == "version"
+        {
+            continue;
+        }
+
+        match settings.get_mut(&key)
+        {
+            None => {
+                warnings.push(format!("unknown setting `{}` at line {}, column {}.", key, line, column));
+            },
+            Some(default) if default.type_str() != value.type_str() => {
+                warnings.push(format!(
+                    "`{}` at line {}, column {} should be of type {}, not {}. Using the default, {}.",
+                    key,
+                    line,
+                    column,
+                    default.type_str(),
+                    value.type_str(),
+                    default
+                ));
+            },
+            Some(setting) => {
+                *setting = value;
+            }
+        }
+    }
+
+    (toml::Value::Table(settings).try_into().unwrap(), warnings)
+}
+
+/// Writes the default config to `path`, creating its directory if needed.
+pub fn write_default_config(path: &Path) -> Result<(), TodosError>
+{
+    let mut table = Config::default().to_table();
+    table.insert("version".to_string(), CONFIG_VERSION.into());
+    write_table(path, &table)
+}
+
+/// Changes a single setting in the config file, creating the file if needed.
+/// `value` is parsed according to the type of the setting.
+pub fn set_config_value(path: &Path, key: &str, value: &str) -> Result<(), TodosError>
+{
+    let default = Config::default().to_table().remove(key).ok_or_else(|| TodosError::UnknownSetting(key.to_string()))?;
 
-    if let Some(config) = config_opt
+    let value = match default
     {
-        config
+        toml::Value::Boolean(_) => toml::Value::Boolean(value.parse().map_err(|_| {
+            TodosError::InvalidSetting { key: key.to_string(), expected: "either true or false".to_string() }
+        })?),
+        _ => toml::Value::String(value.to_string())
+    };
+
+    let mut table = match fs::read_to_string(path)
+    {
+        Ok(content) => {
+            let mut table = content.parse::<toml::Table>().map_err(|error| TodosError::parse(path, error))?;
+            migrate(path, &mut table)?;
+            table
+        },
+        Err(_) => {
+            let mut table = toml::Table::new();
+            table.insert("version".to_string(), CONFIG_VERSION.into());
+            table
+        }
+    };
+    table.insert(key.to_string(), value);
+
+    write_table(path, &table)
+}
+
+fn write_table(path: &Path, table: &toml::Table) -> Result<(), TodosError>
+{
+    if let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir).map_err(|error| TodosError::io(dir, error))?;
+    }
+
+    // a table of plain values always serializes
+    let content = toml::to_string(table).unwrap();
+
+    fs::write(path, content).map_err(|error| TodosError::io(path, error))
+}
+
+/// Upgrades a config file written by an older version of todos, one version
+/// at a time. Returns whether anything changed.
+fn migrate(path: &Path, table: &mut toml::Table) -> Result<bool, TodosError>
+{
+    let starting_version = table.get("version").and_then(|x| x.as_integer()).unwrap_or(0);
+    let mut version = starting_version;
+
+    if version > CONFIG_VERSION
+    {
+        return Err(TodosError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            found: version as u64,
+            supported: CONFIG_VERSION as u64
+        });
     }
-    else
+
+    if version == 0
     {
-        println!("\x1B[1;42m Invalid config file, providing defaults.\x1B[00m");
-        let config = Config::default();
-        config
+        // configs from before 0.1 predate the backlog
+        table.entry("hide_backlog_items").or_insert(true.into());
+        table.entry("backlog_name").or_insert("backlog".into());
+        version = 1;
     }
+
+    table.insert("version".to_string(), version.into());
+    Ok(version != starting_version)
 }
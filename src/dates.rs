@@ -0,0 +1,334 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A due date in the form todos stores it: a day, `YYYY-MM-DD`, or a moment
+/// in UTC, `YYYY-MM-DDTHH:MM:SSZ`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Due
+{
+    Date(NaiveDate),
+    Time(DateTime<Utc>)
+}
+
+impl Due
+{
+    /// Reads a due date as stored on a node, or in any of the forms other
+    /// tools write dates in (`20240201`, `20240201T130000Z`, RFC 3339).
+    pub fn parse(s: &str) -> Option<Due>
+    {
+        let s = s.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d").or_else(|_| NaiveDate::parse_from_str(s, "%Y%m%d"))
+        {
+            return Some(Due::Date(date));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(s)
+        {
+            return Some(Due::from(time.with_timezone(&Utc)));
+        }
+        if let Ok(time) = chrono::NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ")
+        {
+            return Some(Due::from(time.and_utc()));
+        }
+        None
+    }
+
+    /// `YYYYMMDDTHHMMSSZ`, or `YYYYMMDD` for a day, as Taskwarrior and
+    /// iCalendar write them.
+    pub fn to_compact(&self) -> String
+    {
+        match self
+        {
+            Due::Date(date) => date.format("%Y%m%d").to_string(),
+            Due::Time(time) => time.format("%Y%m%dT%H%M%SZ").to_string()
+        }
+    }
+
+    pub fn date(&self) -> NaiveDate
+    {
+        match self
+        {
+            Due::Date(date) => *date,
+            Due::Time(time) => time.date_naive()
+        }
+    }
+}
+
+impl From<DateTime<Utc>> for Due
+{
+    /// Midnight is taken to mean the whole day.
+    fn from(time: DateTime<Utc>) -> Due
+    {
+        if time.time() == NaiveTime::MIN
+        {
+            Due::Date(time.date_naive())
+        }
+        else
+        {
+            Due::Time(time)
+        }
+    }
+}
+
+impl fmt::Display for Due
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Due::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Due::Time(time) => write!(f, "{}", time.format("%Y-%m-%dT%H:%M:%SZ"))
+        }
+    }
+}
+
+/// How a recurring node comes back after it is completed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence
+{
+    /// `daily`
+    Daily,
+    /// `weekly`, on the weekday it was due, or `weekly on mon, thu`.
+    Weekly(Vec<Weekday>),
+    /// `monthly on day 15`; in shorter months, the last day.
+    Monthly(u32),
+    /// `every 3 days`, counted from the day it is completed.
+    AfterCompletion(u32)
+}
+
+impl Recurrence
+{
+    /// Reads a rule as `todos repeat` takes it, ignoring case.
+    pub fn parse(s: &str) -> Option<Recurrence>
+    {
+        let s = s.trim().to_lowercase();
+        let words: Vec<&str> = s.split(|c: char| c.is_whitespace() || c == ',').filter(|x| !x.is_empty()).collect();
+        match &words[..]
+        {
+            ["daily"] | ["every", "day"] => Some(Recurrence::Daily),
+            ["weekly"] | ["every", "week"] => Some(Recurrence::Weekly(Vec::new())),
+            ["weekly", "on", days @ ..] | ["every", days @ ..] if !days.is_empty() && days.iter().all(|x| x.parse::<Weekday>().is_ok()) => {
+                let mut weekdays: Vec<Weekday> = Vec::new();
+                for day in days.iter().map(|x| x.parse::<Weekday>().unwrap())
+                {
+                    if !weekdays.contains(&day)
+                    {
+                        weekdays.push(day);
+                    }
+                }
+                weekdays.sort_by_key(|x| x.num_days_from_monday());
+                Some(Recurrence::Weekly(weekdays))
+            },
+            ["monthly", "on", "day", day] | ["monthly", "on", day] => {
+                day.trim_end_matches(|c: char| c.is_alphabetic())
+                    .parse()
+                    .ok()
+                    .filter(|x| (1..=31).contains(x))
+                    .map(Recurrence::Monthly)
+            },
+            ["every", days, "days", rest @ ..] | ["every", days, "day", rest @ ..] if rest.is_empty() || rest == ["after", "completion"] => {
+                days.parse().ok().filter(|x| *x > 0).map(Recurrence::AfterCompletion)
+            },
+            _ => None
+        }
+    }
+
+    /// When the instance after one due `due` (if it had a due date) and
+    /// completed `today` is due. It keeps the time of day it was due at.
+    ///
+    /// Rules tied to the calendar count from the due date, or from today if
+    /// it is overdue or had none, so an early completion doesn't bring the
+    /// next one forward and a late one doesn't leave it overdue.
+    pub fn next(&self, due: Option<Due>, today: NaiveDate) -> Due
+    {
+        let base = due.map(|x| x.date()).filter(|x| *x > today).unwrap_or(today);
+        let after = |matches: &dyn Fn(NaiveDate) -> bool| {
+            base.iter_days().skip(1).find(|x| matches(*x)).unwrap()
+        };
+
+        let date = match self
+        {
+            Recurrence::Daily => after(&|_| true),
+            Recurrence::Weekly(days) if days.is_empty() => {
+                let weekday = due.map(|x| x.date()).unwrap_or(today).weekday();
+                after(&|x| x.weekday() == weekday)
+            },
+            Recurrence::Weekly(days) => after(&|x| days.contains(&x.weekday())),
+            Recurrence::Monthly(day) => after(&|x| x.day() == (*day).min(last_day_of_month(x))),
+            Recurrence::AfterCompletion(days) => today + Duration::days(*days as i64)
+        };
+
+        match due
+        {
+            Some(Due::Time(time)) => Due::Time(date.and_time(time.time()).and_utc()),
+            _ => Due::Date(date)
+        }
+    }
+}
+
+fn last_day_of_month(date: NaiveDate) -> u32
+{
+    let first = date.with_day(1).unwrap();
+    let next = first.checked_add_months(Months::new(1)).unwrap();
+    next.pred_opt().unwrap().day()
+}
+
+impl fmt::Display for Recurrence
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) if days.is_empty() => write!(f, "weekly"),
+            Recurrence::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|x| x.to_string().to_lowercase()).collect();
+                write!(f, "weekly on {}", days.join(", "))
+            },
+            Recurrence::Monthly(day) => write!(f, "monthly on day {}", day),
+            Recurrence::AfterCompletion(1) => write!(f, "every 1 day after completion"),
+            Recurrence::AfterCompletion(days) => write!(f, "every {} days after completion", days)
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence
+{
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Recurrence, String>
+    {
+        Recurrence::parse(&s).ok_or_else(|| format!("unknown recurrence \"{}\"", s))
+    }
+}
+
+impl From<Recurrence> for String
+{
+    fn from(recurrence: Recurrence) -> String
+    {
+        recurrence.to_string()
+    }
+}
+
+/// Reads a day as typed on the command line: `YYYY-MM-DD`, `today`,
+/// `tomorrow`, a weekday (the next one after `today`), or `next week` or
+/// `next month` (their first day).
+pub fn parse_day(s: &str, today: NaiveDate) -> Option<NaiveDate>
+{
+    let s = s.trim().to_lowercase();
+    match &s[..]
+    {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        "next week" => today.iter_days().skip(1).find(|x| x.weekday() == Weekday::Mon),
+        "next month" => today.with_day(1)?.checked_add_months(Months::new(1)),
+        _ => match s.parse::<Weekday>()
+        {
+            Ok(weekday) => today.iter_days().skip(1).find(|x| x.weekday() == weekday),
+            Err(_) => NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok()
+        }
+    }
+}
+
+/// Reads a day in the past as typed on the command line: `YYYY-MM-DD`,
+/// `today`, `yesterday`, a weekday (the last one, or `today`), or `this
+/// week`, `last week`, `this month` or `last month` (their first day).
+pub fn parse_past_day(s: &str, today: NaiveDate) -> Option<NaiveDate>
+{
+    let s = s.trim().to_lowercase();
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    match &s[..]
+    {
+        "today" => Some(today),
+        "yesterday" => today.pred_opt(),
+        "this week" => Some(monday),
+        "last week" => Some(monday - Duration::days(7)),
+        "this month" => today.with_day(1),
+        "last month" => today.with_day(1)?.checked_sub_months(Months::new(1)),
+        _ => match s.parse::<Weekday>()
+        {
+            Ok(weekday) => (0..7).map(|x| today - Duration::days(x)).find(|x| x.weekday() == weekday),
+            Err(_) => NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok()
+        }
+    }
+}
+
+/// A stretch of time spent on a node, from `todos start` to `todos stop`, as
+/// moments in UTC written like a `Due::Time`. A running timer has no `end`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Interval
+{
+    pub start: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>
+}
+
+impl Interval
+{
+    pub fn starting(now: DateTime<Utc>) -> Interval
+    {
+        Interval { start: Due::Time(now).to_string(), end: None }
+    }
+
+    /// Reads an interval written as [`Interval`] displays it: `start/end`,
+    /// or just `start` while the timer runs.
+    pub fn parse(s: &str) -> Option<Interval>
+    {
+        let (start, end) = match s.trim().split_once('/')
+        {
+            Some((start, end)) => (start, Some(end)),
+            None => (s.trim(), None)
+        };
+        let start = moment(start)?;
+        let end = match end
+        {
+            Some(end) => Some(Due::Time(moment(end)?).to_string()),
+            None => None
+        };
+        Some(Interval { start: Due::Time(start).to_string(), end })
+    }
+
+    /// How much of the interval falls after `since`, counting a running one
+    /// up to `now`.
+    pub fn length(&self, since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Duration
+    {
+        let start = match moment(&self.start)
+        {
+            Some(start) => since.map(|x| x.max(start)).unwrap_or(start),
+            None => { return Duration::zero(); }
+        };
+        let end = self.end.as_deref().and_then(moment).unwrap_or(now);
+        (end - start).max(Duration::zero())
+    }
+}
+
+fn moment(s: &str) -> Option<DateTime<Utc>>
+{
+    DateTime::parse_from_rfc3339(s).ok().map(|x| x.with_timezone(&Utc))
+}
+
+/// `start/end`, as ISO 8601 writes intervals.
+impl fmt::Display for Interval
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match &self.end
+        {
+            Some(end) => write!(f, "{}/{}", self.start, end),
+            None => write!(f, "{}", self.start)
+        }
+    }
+}
+
+/// Writes tracked time the way reports show it, like `1h 05m` or `25m`.
+pub fn format_duration(duration: Duration) -> String
+{
+    let minutes = duration.num_minutes();
+    if minutes < 60
+    {
+        return format!("{}m", minutes);
+    }
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,80 @@
+use crate::error::TodosError;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+/// Lets the user edit `text` in `$VISUAL` or `$EDITOR` (falling back to
+/// `vi`) and returns the result. `extension` is given to the temporary file
+/// so the editor can pick a syntax, e.g. "md".
+pub fn edit_text(text: &str, extension: &str) -> Result<String, TodosError>
+{
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|x| !x.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+
+    let file = TempFile::create(text, extension)?;
+    let path = &file.path;
+
+    // the editor may come with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let status = process::Command::new(words.next().unwrap())
+        .args(words)
+        .arg(path)
+        .status();
+
+    match status
+    {
+        Ok(status) if status.success() => fs::read_to_string(path).map_err(|error| TodosError::io(path, error)),
+        Ok(status) => Err(TodosError::Invalid(format!("{} exited with {}; nothing was changed.", editor, status))),
+        Err(error) => Err(TodosError::Invalid(format!("Unable to run {}: {}", editor, error)))
+    }
+}
+
+/// A file in the temporary directory that only its owner can read, with a
+/// name no one can guess, removed when dropped.
+struct TempFile
+{
+    path: PathBuf
+}
+
+impl TempFile
+{
+    fn create(text: &str, extension: &str) -> Result<TempFile, TodosError>
+    {
+        loop
+        {
+            let path = env::temp_dir().join(format!("todos-{}.{}", uuid::Uuid::new_v4().simple(), extension));
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+
+            // never an existing file, which could be a link planted elsewhere
+            let mut handle = match options.open(&path)
+            {
+                Ok(handle) => handle,
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => { continue; },
+                Err(error) => { return Err(TodosError::io(&path, error)); }
+            };
+            let file = TempFile { path };
+            handle.write_all(text.as_bytes()).map_err(|error| TodosError::io(&file.path, error))?;
+            return Ok(file);
+        }
+    }
+}
+
+impl Drop for TempFile
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_file(&self.path);
+    }
+}
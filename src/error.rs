@@ -0,0 +1,173 @@
+use crate::node::Node;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum TodosError
+{
+    /// No node has this id.
+    NotFound(usize),
+    /// The operation would create (or the graph contains) a cycle. The nodes
+    /// are listed in link order, ending with the node they started with.
+    Cycle(Vec<Node>),
+    /// `parent` lists `child` as a dependency (or the other way around) but
+    /// the other node doesn't acknowledge the link.
+    OneSidedLink
+    {
+        parent: usize,
+        child: usize
+    },
+    /// A node refers to a node that isn't in the graph.
+    DanglingLink
+    {
+        node: usize,
+        missing: usize
+    },
+    /// A node's id doesn't match its position in the graph.
+    IdMismatch
+    {
+        index: usize,
+        id: usize
+    },
+    /// A request that doesn't make sense, such as moving a node above itself.
+    Invalid(String),
+    /// The config file has no setting by this name.
+    UnknownSetting(String),
+    /// A setting was given a value of the wrong type.
+    InvalidSetting
+    {
+        key: String,
+        expected: String
+    },
+    /// A todos or config file was written by a newer version of todos.
+    UnsupportedVersion
+    {
+        path: PathBuf,
+        found: u64,
+        supported: u64
+    },
+    Io
+    {
+        path: PathBuf,
+        source: io::Error
+    },
+    /// A file could be read but its contents don't make sense.
+    Parse
+    {
+        path: PathBuf,
+        message: String
+    },
+    /// The storage backend's database reported an error.
+    Database
+    {
+        path: PathBuf,
+        message: String
+    }
+}
+
+impl TodosError
+{
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> TodosError
+    {
+        TodosError::Io { path: path.into(), source }
+    }
+
+    pub fn parse(path: impl Into<PathBuf>, message: impl fmt::Display) -> TodosError
+    {
+        TodosError::Parse { path: path.into(), message: message.to_string() }
+    }
+
+    /// A short name for the kind of error, for programs talking to todos
+    /// (see `todos serve`) to match on instead of the message.
+    pub fn code(&self) -> &'static str
+    {
+        match self
+        {
+            TodosError::NotFound(_) => "not_found",
+            TodosError::Cycle(_) => "cycle",
+            TodosError::OneSidedLink { .. } => "one_sided_link",
+            TodosError::DanglingLink { .. } => "dangling_link",
+            TodosError::IdMismatch { .. } => "id_mismatch",
+            TodosError::Invalid(_) => "invalid",
+            TodosError::UnknownSetting(_) => "unknown_setting",
+            TodosError::InvalidSetting { .. } => "invalid_setting",
+            TodosError::UnsupportedVersion { .. } => "unsupported_version",
+            TodosError::Io { .. } => "io",
+            TodosError::Parse { .. } => "parse",
+            TodosError::Database { .. } => "database"
+        }
+    }
+}
+
+impl fmt::Display for TodosError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            TodosError::NotFound(id) => {
+                write!(f, "Node with id {} not present in todos.", id)
+            },
+            TodosError::Cycle(nodes) => {
+                write!(f, "Idiot. Haven't you heard of a DAG above? These nodes form a cycle:")?;
+                for (i, node) in nodes.iter().enumerate()
+                {
+                    write!(
+                        f,
+                        "\n  {}{} ({}): {}",
+                        if i == 0 { "" } else { "-> " },
+                        node.id,
+                        node.node_type,
+                        node.description
+                    )?;
+                }
+                Ok(())
+            },
+            TodosError::OneSidedLink { parent, child } => {
+                write!(f, "One-sided dependency encountered between parent with id {} and child with id {}.", parent, child)
+            },
+            TodosError::DanglingLink { node, missing } => {
+                write!(f, "Node with id {} links to node {}, which is not present in the graph.", node, missing)
+            },
+            TodosError::IdMismatch { index, id } => {
+                write!(f, "Ids do not match the list structure: node at position {} has id {}.", index, id)
+            },
+            TodosError::Invalid(message) => {
+                write!(f, "{}", message)
+            },
+            TodosError::UnknownSetting(key) => {
+                write!(f, "Unknown setting `{}`. Valid settings are: {}.", key, crate::config::KEYS.join(", "))
+            },
+            TodosError::InvalidSetting { key, expected } => {
+                write!(f, "`{}` must be {}.", key, expected)
+            },
+            TodosError::UnsupportedVersion { path, found, supported } => {
+                write!(f, "{} has version {}, but this build only understands up to version {}. Please update todos.", path.display(), found, supported)
+            },
+            TodosError::Io { path, source } => {
+                write!(f, "Unable to access {}: {}", path.display(), source)
+            },
+            TodosError::Parse { path, message } => {
+                write!(f, "Unable to parse {}: {}", path.display(), message)
+            },
+            TodosError::Database { path, message } => {
+                write!(f, "Database error in {}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl Error for TodosError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self
+        {
+            TodosError::Io { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Comma-separated values ([RFC 4180](https://www.rfc-editor.org/rfc/rfc4180)),
+//! for reviewing the workload in a spreadsheet.
+//!
+//! Each node is a row with its id, type, description, parents and deps (ids
+//! separated by spaces), its depth below the top of the export, its due date
+//! and whether it is a leaf, blocked by a condition somewhere below it, or
+//! open, followed by its priority, tags (separated by `;`), notes, how it
+//! repeats, the days it was completed on (separated by `;`), the day it is
+//! deferred until and the time tracked on it (intervals like `start/end`,
+//! separated by `;`).
+//!
+//! On import, columns are found by their header, so they can be reordered
+//! or left out; only `description` is required. Depth and status are worked
+//! out from the graph, so those columns are ignored.
+//!
+//! Spreadsheets run cells starting with `=`, `+`, `-` or `@` as formulas, so
+//! those are written with a `'` in front, which spreadsheets hide and import
+//! takes off again.
+
+use super::{blocked, Record, Report};
+use crate::dates::{Due, Interval, Recurrence};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+const COLUMNS: [&str; 15] = [
+    "id", "type", "description", "parents", "deps", "depth", "due", "status", "priority", "tags", "notes", "recurrence", "completed",
+    "deferred", "time"
+];
+
+pub fn export(graph: &Graph, ids: &BTreeSet<usize>, under: Option<usize>) -> String
+{
+    let depths = depths(graph, ids, under);
+    let blocked = blocked(graph, ids);
+
+    let mut out = String::new();
+    write_row(&mut out, &COLUMNS.map(String::from));
+    for id in ids
+    {
+        let node = &graph.nodes()[*id];
+        let list = |links: &crate::node::Links| {
+            links.iter().filter(|x| ids.contains(x)).map(|x| x.to_string()).collect::<Vec<_>>().join(" ")
+        };
+        let status = if node.deps.is_empty()
+        {
+            "leaf"
+        }
+        else if blocked.contains(id)
+        {
+            "blocked"
+        }
+        else
+        {
+            "open"
+        };
+
+        write_row(&mut out, &[
+            id.to_string(),
+            node.node_type.to_string(),
+            node.description.clone(),
+            list(&node.parents),
+            list(&node.deps),
+            depths.get(id).map(|x| x.to_string()).unwrap_or_default(),
+            node.due_date.clone().unwrap_or_default(),
+            status.to_string(),
+            node.priority.map(String::from).unwrap_or_default(),
+            node.tags.join(";"),
+            node.notes.clone().unwrap_or_default(),
+            node.recurrence.as_ref().map(|x| x.to_string()).unwrap_or_default(),
+            node.completed.join(";"),
+            node.deferred_until.clone().unwrap_or_default(),
+            node.time.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(";")
+        ]);
+    }
+    out
+}
+
+/// How far below the top of the export each node first appears, the top
+/// being `under` or the nodes without parents.
+fn depths(graph: &Graph, ids: &BTreeSet<usize>, under: Option<usize>) -> HashMap<usize, usize>
+{
+    let mut queue: VecDeque<(usize, usize)> = match under
+    {
+        Some(under) => VecDeque::from([(under, 0)]),
+        None => ids.iter().filter(|x| graph.nodes()[**x].parents.is_empty()).map(|x| (*x, 0)).collect()
+    };
+
+    let mut depths = HashMap::new();
+    while let Some((id, depth)) = queue.pop_front()
+    {
+        if depths.contains_key(&id)
+        {
+            continue;
+        }
+        depths.insert(id, depth);
+        queue.extend(graph.nodes()[id].deps.iter().filter(|x| ids.contains(x)).map(|x| (*x, depth + 1)));
+    }
+    depths
+}
+
+fn write_row(out: &mut String, fields: &[String])
+{
+    let fields: Vec<String> = fields.iter()
+        .map(|x| if needs_guard(x) { format!("'{}", x) } else { x.clone() })
+        .map(|x| {
+            if x.contains(['"', ',', '\n', '\r'])
+            {
+                format!("\"{}\"", x.replace('"', "\"\""))
+            }
+            else
+            {
+                x
+            }
+        })
+        .collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+/// Characters that make a spreadsheet read a cell as a formula.
+const FORMULA: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Whether `field` needs a `'` in front: it would run as a formula, or
+/// already starts with quotes in front of one, which import takes one of off.
+fn needs_guard(field: &str) -> bool
+{
+    field.trim_start_matches('\'').starts_with(FORMULA)
+}
+
+/// Splits `content` into rows of fields, with the line each row starts on.
+fn read_rows(content: &str) -> Result<Vec<(usize, Vec<String>)>, TodosError>
+{
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next()
+    {
+        match (c, quoted)
+        {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            ('"', true) => { quoted = false; },
+            ('"', false) if field.is_empty() => { quoted = true; },
+            (',', false) => { row.push(std::mem::take(&mut field)); },
+            ('\r', false) if chars.peek() == Some(&'\n') => {},
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push((start, std::mem::take(&mut row)));
+                line += 1;
+                start = line;
+            },
+            (c, _) => {
+                if c == '\n'
+                {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted
+    {
+        return Err(TodosError::Invalid(format!("Line {}: a quoted field is never closed.", start)));
+    }
+    if !field.is_empty() || !row.is_empty()
+    {
+        row.push(field);
+        rows.push((start, row));
+    }
+
+    // spreadsheets like to leave empty rows at the end
+    rows.retain(|(_, row)| row.iter().any(|x| !x.trim().is_empty()));
+    Ok(rows)
+}
+
+pub fn parse(content: &str, report: &mut Report) -> Result<Vec<Record>, TodosError>
+{
+    let mut rows = read_rows(content.strip_prefix('\u{feff}').unwrap_or(content))?.into_iter();
+    let header: Vec<String> = match rows.next()
+    {
+        Some((_, header)) => header.iter().map(|x| x.trim().to_lowercase()).collect(),
+        None => { return Ok(Vec::new()); }
+    };
+    let column = |name: &str| header.iter().position(|x| x == name);
+    let description_column = column("description")
+        .ok_or_else(|| TodosError::Invalid(String::from("Line 1: the header has no description column.")))?;
+
+    let unknown: Vec<&str> = header.iter().filter(|x| !COLUMNS.contains(&&x[..]) && !x.is_empty()).map(|x| &x[..]).collect();
+    if !unknown.is_empty()
+    {
+        report.warnings.push(format!("Left out the columns {}, which todos doesn't keep.", unknown.join(", ")));
+    }
+
+    let mut records: Vec<Record> = Vec::new();
+    // parent and child keys, from the parents column
+    let mut parents: Vec<(usize, String, String)> = Vec::new();
+    for (line, row) in rows
+    {
+        let row: Vec<String> = row.into_iter()
+            .map(|x| if x.starts_with('\'') && needs_guard(&x) { x[1..].to_string() } else { x })
+            .collect();
+        let field = |name: &str| column(name).and_then(|x| row.get(x)).map(|x| x.trim()).filter(|x| !x.is_empty());
+
+        let description = row.get(description_column).map(|x| x.trim()).unwrap_or("");
+        if description.is_empty()
+        {
+            return Err(TodosError::Invalid(format!("Line {}: missing description.", line)));
+        }
+        let key = field("id").map(String::from).unwrap_or_else(|| format!("\n{}", line));
+        let mut record = Record::new(key, line, description.to_string());
+
+        if let Some(node_type) = field("type")
+        {
+            record.node_type = NodeType::from_string(&node_type.to_lowercase()).ok_or_else(|| {
+                TodosError::Invalid(format!("Line {}: unknown type {}; use goal, condition or task.", line, node_type))
+            })?;
+        }
+        record.deps = field("deps").map(split_ids).unwrap_or_default();
+        for parent in field("parents").map(split_ids).unwrap_or_default()
+        {
+            parents.push((line, parent, record.key.clone()));
+        }
+        record.due_date = field("due").map(String::from);
+        record.priority = match field("priority")
+        {
+            Some(priority) => {
+                let mut chars = priority.chars();
+                match (chars.next(), chars.next())
+                {
+                    (Some(c), None) => Some(c.to_ascii_uppercase()),
+                    _ => { return Err(TodosError::Invalid(format!("Line {}: the priority must be a letter.", line))); }
+                }
+            },
+            None => None
+        };
+        record.tags = field("tags").into_iter().flat_map(|x| x.split(';')).map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect();
+        record.notes = column("notes").and_then(|x| row.get(x)).filter(|x| !x.trim().is_empty()).cloned();
+        if let Some(recurrence) = field("recurrence")
+        {
+            record.recurrence = Some(Recurrence::parse(recurrence).ok_or_else(|| {
+                TodosError::Invalid(format!("Line {}: unknown recurrence {}; use a rule like `weekly on mon`.", line, recurrence))
+            })?);
+        }
+        record.completed = field("completed").into_iter().flat_map(|x| x.split(';')).map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect();
+        if let Some(until) = field("deferred")
+        {
+            record.deferred_until = Some(Due::parse(until).map(|x| x.date()).ok_or_else(|| {
+                TodosError::Invalid(format!("Line {}: {} isn't a valid date to defer until.", line, until))
+            })?);
+        }
+        for interval in field("time").into_iter().flat_map(|x| x.split(';')).map(|x| x.trim()).filter(|x| !x.is_empty())
+        {
+            record.time.push(Interval::parse(interval).ok_or_else(|| {
+                TodosError::Invalid(format!("Line {}: {} isn't a valid interval of tracked time.", line, interval))
+            })?);
+        }
+        records.push(record);
+    }
+
+    // a link can be in both columns, as exports have it
+    let positions: HashMap<String, usize> = records.iter().enumerate().map(|(i, x)| (x.key.clone(), i)).collect();
+    for (line, parent, child) in parents
+    {
+        match positions.get(&parent)
+        {
+            Some(parent) => {
+                if !records[*parent].deps.contains(&child)
+                {
+                    records[*parent].deps.push(child);
+                }
+            },
+            None => report.warnings.push(format!(
+                "Line {}: the parent {} isn't in the file; the link was dropped.",
+                line,
+                parent
+            ))
+        }
+    }
+    Ok(records)
+}
+
+/// Ids separated by spaces, commas or semicolons.
+fn split_ids(value: &str) -> Vec<String>
+{
+    value.split([' ', ',', ';']).filter(|x| !x.is_empty()).map(String::from).collect()
+}
@@ -0,0 +1,373 @@
+//! A self-contained HTML report, for sharing the state of things with
+//! people who don't have `todos`.
+//!
+//! The page has a collapsible tree of the graph from the effective root (or
+//! a given node), with the node types in the colors of the config and badges
+//! for leaves and for nodes blocked by a condition somewhere below them,
+//! followed by an SVG drawing of the DAG. Everything is inline, so the file
+//! can be sent as it is.
+
+use super::{blocked, subtree};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+const BOX_WIDTH: usize = 168;
+const BOX_HEIGHT: usize = 36;
+const GAP_X: usize = 24;
+const GAP_Y: usize = 56;
+const MARGIN: usize = 16;
+/// Longer descriptions are cut short in the drawing.
+const BOX_CHARS: usize = 22;
+
+pub fn export(graph: &Graph, under: Option<usize>) -> Result<String, TodosError>
+{
+    let start = under.or(graph.effective_root());
+    let top: Vec<usize> = match start
+    {
+        Some(start) => vec![graph.node(start)?.id],
+        None => graph.nodes().iter().filter(|x| x.parents.is_empty()).map(|x| x.id).collect()
+    };
+    let ids = subtree(graph, start)?;
+    let blocked = blocked(graph, &ids);
+
+    let title = match start
+    {
+        Some(start) => format!("todos: {}", graph.nodes()[start].description),
+        None => String::from("todos")
+    };
+    let leaves = ids.iter().filter(|x| graph.nodes()[**x].deps.is_empty()).count();
+
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>").unwrap();
+    writeln!(out, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>{}</title>", escape(&title)).unwrap();
+    writeln!(out, "<style>\n{}</style>\n</head>\n<body>", style(graph)).unwrap();
+    writeln!(out, "<h1>{}</h1>", escape(&title)).unwrap();
+    writeln!(
+        out,
+        "<p class=\"summary\">{} nodes, {} leaves, {} blocked. Generated on {}.</p>",
+        ids.len(),
+        leaves,
+        blocked.len(),
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    ).unwrap();
+
+    writeln!(out, "<h2>Tree</h2>\n<ul class=\"tree\">").unwrap();
+    let mut seen = HashSet::new();
+    for id in &top
+    {
+        tree(&mut out, graph, *id, start, &blocked, &mut seen);
+    }
+    writeln!(out, "</ul>").unwrap();
+
+    writeln!(out, "<h2>Graph</h2>").unwrap();
+    svg(&mut out, graph, &ids, &top);
+    writeln!(out, "</body>\n</html>").unwrap();
+    Ok(out)
+}
+
+/// Writes `id` and everything under it as nested lists. Nodes that were
+/// already written link back to where they are.
+fn tree(out: &mut String, graph: &Graph, id: usize, start: Option<usize>, blocked: &HashSet<usize>, seen: &mut HashSet<usize>)
+{
+    let node = &graph.nodes()[id];
+    if !seen.insert(id)
+    {
+        writeln!(
+            out,
+            "<li><a class=\"{}\" href=\"#node-{}\">{}</a> <span class=\"meta\">(see above)</span></li>",
+            node.node_type,
+            id,
+            escape(&node.description)
+        ).unwrap();
+        return;
+    }
+
+    let mut label = format!("<span class=\"{}\">{}</span>", node.node_type, escape(&node.description));
+    if node.deps.is_empty()
+    {
+        label.push_str(" <span class=\"badge leaf\">leaf</span>");
+    }
+    if blocked.contains(&id)
+    {
+        label.push_str(" <span class=\"badge blocked\">blocked</span>");
+    }
+    if let Some(priority) = node.priority
+    {
+        write!(label, " <span class=\"meta\">({})</span>", priority).unwrap();
+    }
+    if let Some(due_date) = &node.due_date
+    {
+        write!(label, " <span class=\"meta\">due {}</span>", escape(due_date)).unwrap();
+    }
+    for tag in &node.tags
+    {
+        write!(label, " <span class=\"tag\">{}</span>", escape(tag)).unwrap();
+    }
+    if let Some(notes) = &node.notes
+    {
+        write!(label, "<div class=\"notes\">{}</div>", escape(notes)).unwrap();
+    }
+
+    if node.deps.is_empty()
+    {
+        writeln!(out, "<li id=\"node-{}\">{}</li>", id, label).unwrap();
+        return;
+    }
+
+    // the backlog starts out closed, as `todos` hides it
+    let config = graph.config();
+    let hidden = config.hide_backlog_items &&
+        node.node_type == NodeType::Goal &&
+        node.description == config.backlog_name &&
+        Some(id) != start;
+    writeln!(
+        out,
+        "<li id=\"node-{}\"><details{}><summary>{}</summary>\n<ul>",
+        id,
+        if hidden { "" } else { " open" },
+        label
+    ).unwrap();
+    for dep in &node.deps
+    {
+        tree(out, graph, *dep, start, blocked, seen);
+    }
+    writeln!(out, "</ul></details></li>").unwrap();
+}
+
+/// Draws the DAG in layers, each node one layer below its lowest parent, so
+/// every edge points down.
+fn svg(out: &mut String, graph: &Graph, ids: &BTreeSet<usize>, top: &[usize])
+{
+    // the order nodes are first met in from the top, to start from
+    let mut order: Vec<usize> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack: Vec<usize> = top.iter().rev().copied().collect();
+    while let Some(id) = stack.pop()
+    {
+        if seen.insert(id)
+        {
+            order.push(id);
+            stack.extend(graph.nodes()[id].deps.iter().rev().filter(|x| ids.contains(x)));
+        }
+    }
+
+    // longest path from the top, taking nodes once all their parents are
+    // placed
+    let mut layer: HashMap<usize, usize> = HashMap::new();
+    let mut waiting: HashMap<usize, usize> = order.iter()
+        .map(|x| (*x, graph.nodes()[*x].parents.iter().filter(|parent| ids.contains(parent)).count()))
+        .collect();
+    let mut ready: Vec<usize> = order.iter().copied().filter(|x| waiting[x] == 0).collect();
+    while let Some(id) = ready.pop()
+    {
+        let below = layer.get(&id).copied().unwrap_or(0) + 1;
+        layer.entry(id).or_insert(0);
+        for dep in graph.nodes()[id].deps.iter().filter(|x| ids.contains(x))
+        {
+            let current = layer.entry(*dep).or_insert(below);
+            *current = (*current).max(below);
+            let count = waiting.get_mut(dep).unwrap();
+            *count -= 1;
+            if *count == 0
+            {
+                ready.push(*dep);
+            }
+        }
+    }
+
+    let depth = layer.values().max().map(|x| x + 1).unwrap_or(0);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); depth];
+    for id in &order
+    {
+        layers[layer[id]].push(*id);
+    }
+
+    // order each layer by where its parents are, to cut down on crossings
+    let mut position: HashMap<usize, f64> = HashMap::new();
+    for row in layers.iter_mut()
+    {
+        let key = |id: &usize| {
+            let parents: Vec<f64> = graph.nodes()[*id].parents.iter().filter_map(|x| position.get(x).copied()).collect();
+            if parents.is_empty() { f64::MAX } else { parents.iter().sum::<f64>() / parents.len() as f64 }
+        };
+        let mut keyed: Vec<(f64, usize)> = row.iter().map(|id| (key(id), *id)).collect();
+        // a stable sort keeps the first met order among equals
+        keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        *row = keyed.into_iter().map(|x| x.1).collect();
+        for (i, id) in row.iter().enumerate()
+        {
+            position.insert(*id, i as f64);
+        }
+    }
+
+    let widest = layers.iter().map(|x| x.len()).max().unwrap_or(0);
+    let width = MARGIN * 2 + widest * BOX_WIDTH + widest.saturating_sub(1) * GAP_X;
+    let height = MARGIN * 2 + depth * BOX_HEIGHT + depth.saturating_sub(1) * GAP_Y;
+    let mut corner: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (level, row) in layers.iter().enumerate()
+    {
+        let row_width = row.len() * BOX_WIDTH + row.len().saturating_sub(1) * GAP_X;
+        let left = MARGIN + (width - MARGIN * 2 - row_width) / 2;
+        for (i, id) in row.iter().enumerate()
+        {
+            corner.insert(*id, (left + i * (BOX_WIDTH + GAP_X), MARGIN + level * (BOX_HEIGHT + GAP_Y)));
+        }
+    }
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+        width,
+        height,
+        width,
+        height
+    ).unwrap();
+    for id in &order
+    {
+        let (x, y) = corner[id];
+        for dep in graph.nodes()[*id].deps.iter().filter(|x| ids.contains(x))
+        {
+            let (dep_x, dep_y) = corner[dep];
+            let (from_x, from_y) = (x + BOX_WIDTH / 2, y + BOX_HEIGHT);
+            let (to_x, to_y) = (dep_x + BOX_WIDTH / 2, dep_y);
+            let middle = (from_y + to_y) / 2;
+            writeln!(
+                out,
+                "<path class=\"edge\" d=\"M{},{} C{},{} {},{} {},{}\"/>",
+                from_x, from_y, from_x, middle, to_x, middle, to_x, to_y
+            ).unwrap();
+        }
+    }
+    for id in &order
+    {
+        let node = &graph.nodes()[*id];
+        let (x, y) = corner[id];
+        let mut text: String = node.description.chars().take(BOX_CHARS).collect();
+        if node.description.chars().count() > BOX_CHARS
+        {
+            text.pop();
+            text.push('…');
+        }
+        writeln!(
+            out,
+            "<a href=\"#node-{}\" class=\"{}\"><title>{}</title><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"6\"/><text x=\"{}\" y=\"{}\">{}</text></a>",
+            id,
+            node.node_type,
+            escape(&node.description),
+            x,
+            y,
+            BOX_WIDTH,
+            BOX_HEIGHT,
+            x + BOX_WIDTH / 2,
+            y + BOX_HEIGHT / 2 + 5,
+            escape(&text)
+        ).unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+}
+
+fn style(graph: &Graph) -> String
+{
+    let config = graph.config();
+    let mut css = String::from("\
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+.summary, .meta { color: #666; }
+.tree, .tree ul { list-style: none; padding-left: 1.2em; }
+.tree li { margin: 0.2em 0; }
+summary { cursor: pointer; }
+.badge { font-size: 0.75em; padding: 0.1em 0.5em; border-radius: 0.8em; color: white; }
+.leaf { background: #2a9d4b; }
+.blocked { background: #c0392b; }
+.tag { font-size: 0.8em; background: #eee; padding: 0.1em 0.4em; border-radius: 0.3em; }
+.notes { white-space: pre-wrap; color: #444; margin: 0.2em 0 0.4em 1em; font-size: 0.9em; }
+svg { max-width: 100%; height: auto; }
+svg .edge { fill: none; stroke: #999; stroke-width: 1.5; }
+svg rect { fill: white; stroke: currentColor; stroke-width: 2; }
+svg text { fill: currentColor; text-anchor: middle; font-size: 13px; }
+");
+    for (class, code) in [("goal", &config.goal_color), ("condition", &config.condition_color), ("task", &config.task_color)]
+    {
+        writeln!(css, ".{}, svg .{} {{ {} }}", class, class, ansi_to_css(code)).unwrap();
+    }
+    css
+}
+
+/// The CSS for an ANSI SGR code like `01;94`, as used for the terminal.
+fn ansi_to_css(code: &str) -> String
+{
+    const PALETTE: [&str; 16] = [
+        "#000000", "#cd0000", "#00a600", "#b58900", "#0000ee", "#cd00cd", "#00a6b2", "#999999",
+        "#666666", "#e50000", "#00c800", "#c7a900", "#3b78ff", "#e500e5", "#00c5d1", "#aaaaaa"
+    ];
+
+    let numbers: Vec<u8> = code.split(';').filter_map(|x| x.trim().parse().ok()).collect();
+    let mut css = Vec::new();
+    let mut i = 0;
+    while i < numbers.len()
+    {
+        match numbers[i]
+        {
+            1 => css.push(String::from("font-weight: bold;")),
+            2 => css.push(String::from("opacity: 0.7;")),
+            3 => css.push(String::from("font-style: italic;")),
+            4 => css.push(String::from("text-decoration: underline;")),
+            n @ 30..=37 => css.push(format!("color: {};", PALETTE[(n - 30) as usize])),
+            n @ 90..=97 => css.push(format!("color: {};", PALETTE[(n - 90 + 8) as usize])),
+            38 => {
+                match numbers.get(i + 1)
+                {
+                    Some(5) => {
+                        if let Some(n) = numbers.get(i + 2)
+                        {
+                            css.push(format!("color: {};", color_256(*n, &PALETTE)));
+                        }
+                        i += 2;
+                    },
+                    Some(2) => {
+                        if let (Some(r), Some(g), Some(b)) = (numbers.get(i + 2), numbers.get(i + 3), numbers.get(i + 4))
+                        {
+                            css.push(format!("color: #{:02x}{:02x}{:02x};", r, g, b));
+                        }
+                        i += 4;
+                    },
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    css.join(" ")
+}
+
+/// A color of the 256 color palette: the 16 basic ones, a 6×6×6 cube and
+/// 24 grays.
+fn color_256(n: u8, palette: &[&str; 16]) -> String
+{
+    match n
+    {
+        0..=15 => palette[n as usize].to_string(),
+        16..=231 => {
+            let level = |x: u8| if x == 0 { 0 } else { 55 + x * 40 };
+            let n = n - 16;
+            format!("#{:02x}{:02x}{:02x}", level(n / 36), level(n / 6 % 6), level(n % 6))
+        },
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+fn escape(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,539 @@
+//! iCalendar ([RFC 5545](https://www.rfc-editor.org/rfc/rfc5545)) to-dos,
+//! for calendars and phones.
+//!
+//! Only the actionable leaves are exported, as shown by `todos`: tasks
+//! nothing else has to happen for, outside of a hidden backlog. Each links
+//! to its parents with `RELATED-TO`, and the goals above it become its
+//! `CATEGORIES`. On import, categories become goals again, and `RELATED-TO`
+//! links between to-dos in the file become links in the graph.
+//!
+//! The day a deferred node shows up again is its `DTSTART`. How a node
+//! repeats is its `RRULE`, except for rules counting from
+//! completion, which calendars don't have. The days it was completed on and
+//! the time tracked on it are left out.
+
+use super::{assign_uuids, report_dropped, Exported, Record, Report};
+use crate::dates::{Due, Recurrence};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Properties that have no place in the graph but aren't worth a warning.
+const IGNORED: [&str; 8] = ["DTSTAMP", "CREATED", "LAST-MODIFIED", "SEQUENCE", "CLASS", "COMPLETED", "PERCENT-COMPLETE", "URL"];
+
+pub fn export(graph: &mut Graph, under: Option<usize>) -> Result<Exported, TodosError>
+{
+    let leaves = graph.actionable_leaves(under)?;
+
+    // the to-dos and their parents need UIDs
+    let mut ids: BTreeSet<usize> = leaves.iter().copied().collect();
+    for leaf in &leaves
+    {
+        ids.extend(graph.nodes()[*leaf].parents.iter());
+    }
+    assign_uuids(graph, &ids)?;
+
+    let mut exported = Exported::default();
+    let now = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//todos//todos//EN")
+    ];
+
+    for id in leaves
+    {
+        let node = &graph.nodes()[id];
+        lines.push(String::from("BEGIN:VTODO"));
+        lines.push(format!("UID:{}", node.uuid.as_ref().unwrap()));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("SUMMARY:{}", escape(&node.description)));
+        lines.push(String::from("STATUS:NEEDS-ACTION"));
+
+        if let Some(due_date) = &node.due_date
+        {
+            match Due::parse(due_date)
+            {
+                Some(due @ Due::Date(_)) => lines.push(format!("DUE;VALUE=DATE:{}", due.to_compact())),
+                Some(due) => lines.push(format!("DUE:{}", due.to_compact())),
+                None => exported.warnings.push(format!(
+                    "Node {}: left out the due date {}, which isn't a date calendars understand.",
+                    id,
+                    due_date
+                ))
+            }
+        }
+        if let Some(until) = node.deferred_until.as_deref().and_then(Due::parse)
+        {
+            lines.push(format!("DTSTART;VALUE=DATE:{}", Due::Date(until.date()).to_compact()));
+        }
+        if let Some(priority) = node.priority
+        {
+            // 1 is the highest and 9 the lowest
+            let level = (priority as u32 - 'A' as u32 + 1).min(9);
+            if level == 9 && priority > 'I'
+            {
+                exported.warnings.push(format!("Node {}: priority {} became 9, the lowest.", id, priority));
+            }
+            lines.push(format!("PRIORITY:{}", level));
+        }
+
+        if let Some(recurrence) = &node.recurrence
+        {
+            match rrule(recurrence)
+            {
+                Some(rule) => lines.push(format!("RRULE:{}", rule)),
+                None => exported.warnings.push(format!(
+                    "Node {}: left out that it repeats {}; calendars can't count from when a to-do is completed.",
+                    id,
+                    recurrence
+                ))
+            }
+        }
+        if !node.completed.is_empty()
+        {
+            exported.warnings.push(format!("Node {}: left out the days it was completed on, which calendars don't keep.", id));
+        }
+        if !node.time.is_empty()
+        {
+            exported.warnings.push(format!("Node {}: left out the time tracked on it, which calendars don't keep.", id));
+        }
+
+        let categories: Vec<String> = goals_above(graph, id).iter().map(|x| escape(x)).collect();
+        if !categories.is_empty()
+        {
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+        for parent in &node.parents
+        {
+            lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", graph.nodes()[*parent].uuid.as_ref().unwrap()));
+        }
+        if let Some(notes) = &node.notes
+        {
+            lines.push(format!("DESCRIPTION:{}", escape(notes)));
+        }
+        lines.push(String::from("END:VTODO"));
+    }
+    lines.push(String::from("END:VCALENDAR"));
+
+    for line in lines
+    {
+        fold(&mut exported.content, &line);
+    }
+    Ok(exported)
+}
+
+/// The days of the week as `BYDAY` writes them.
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+/// The `RRULE` for a recurrence, if calendars can follow it. Monthly rules
+/// past the 28th take the last of the days up to theirs that the month has.
+fn rrule(recurrence: &Recurrence) -> Option<String>
+{
+    match recurrence
+    {
+        Recurrence::Daily => Some(String::from("FREQ=DAILY")),
+        Recurrence::Weekly(days) if days.is_empty() => Some(String::from("FREQ=WEEKLY")),
+        Recurrence::Weekly(days) => {
+            let days: Vec<&str> = days.iter().map(|x| WEEKDAYS[x.num_days_from_monday() as usize]).collect();
+            Some(format!("FREQ=WEEKLY;BYDAY={}", days.join(",")))
+        },
+        Recurrence::Monthly(day) if *day <= 28 => Some(format!("FREQ=MONTHLY;BYMONTHDAY={}", day)),
+        Recurrence::Monthly(day) => {
+            let days: Vec<String> = (28..=*day).map(|x| x.to_string()).collect();
+            Some(format!("FREQ=MONTHLY;BYMONTHDAY={};BYSETPOS=-1", days.join(",")))
+        },
+        Recurrence::AfterCompletion(_) => None
+    }
+}
+
+/// Reads the `RRULE`s [`rrule`] writes, and the other ways of saying the same.
+fn parse_rrule(value: &str) -> Option<Recurrence>
+{
+    let mut parts: HashMap<String, String> = HashMap::new();
+    for part in value.split(';')
+    {
+        let (key, value) = part.split_once('=')?;
+        parts.insert(key.trim().to_ascii_uppercase(), value.trim().to_ascii_uppercase());
+    }
+    if parts.keys().any(|x| !["FREQ", "INTERVAL", "BYDAY", "BYMONTHDAY", "BYSETPOS", "WKST"].contains(&&x[..])) ||
+        parts.get("INTERVAL").map(|x| x != "1").unwrap_or(false)
+    {
+        return None;
+    }
+
+    let get = |key: &str| parts.get(key).map(|x| &x[..]);
+    match (get("FREQ")?, get("BYDAY"), get("BYMONTHDAY"), get("BYSETPOS"))
+    {
+        ("DAILY", None, None, None) => Some(Recurrence::Daily),
+        ("WEEKLY", None, None, None) => Some(Recurrence::Weekly(Vec::new())),
+        ("WEEKLY", Some(days), None, None) => {
+            let mut names = Vec::new();
+            for day in days.split(',')
+            {
+                let position = WEEKDAYS.iter().position(|x| *x == day)?;
+                names.push(["mon", "tue", "wed", "thu", "fri", "sat", "sun"][position]);
+            }
+            Recurrence::parse(&format!("weekly on {}", names.join(", ")))
+        },
+        ("MONTHLY", None, Some("-1"), None) => Some(Recurrence::Monthly(31)),
+        ("MONTHLY", None, Some(day), None) => day.parse().ok().filter(|x| (1..=28).contains(x)).map(Recurrence::Monthly),
+        ("MONTHLY", None, Some(days), Some("-1")) => {
+            let days: Vec<u32> = days.split(',').map(|x| x.parse().ok()).collect::<Option<_>>()?;
+            let last = *days.last()?;
+            let expected: Vec<u32> = (28..=last).collect();
+            if days == expected && last <= 31 { Some(Recurrence::Monthly(last)) } else { None }
+        },
+        _ => None
+    }
+}
+
+/// Every goal above a node, nearest first.
+fn goals_above(graph: &Graph, id: usize) -> Vec<String>
+{
+    let mut goals = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue: Vec<usize> = graph.nodes()[id].parents.iter().copied().collect();
+    let mut i = 0;
+    while i < queue.len()
+    {
+        let node = &graph.nodes()[queue[i]];
+        i += 1;
+        if !seen.insert(node.id)
+        {
+            continue;
+        }
+        if node.node_type == NodeType::Goal
+        {
+            goals.push(node.description.clone());
+        }
+        queue.extend(node.parents.iter());
+    }
+    goals
+}
+
+fn escape(value: &str) -> String
+{
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String
+{
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next()
+    {
+        if c != '\\'
+        {
+            out.push(c);
+            continue;
+        }
+        match chars.next()
+        {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\')
+        }
+    }
+    out
+}
+
+/// Splits an escaped list on the commas that aren't escaped.
+fn split_list(value: &str) -> Vec<String>
+{
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars()
+    {
+        if c == ',' && !escaped
+        {
+            items.push(unescape(&current));
+            current.clear();
+            continue;
+        }
+        escaped = c == '\\' && !escaped;
+        current.push(c);
+    }
+    items.push(unescape(&current));
+    items.into_iter().filter(|x| !x.is_empty()).collect()
+}
+
+/// Appends a content line, folded at 75 octets as RFC 5545 requires.
+fn fold(out: &mut String, line: &str)
+{
+    let mut length = 0;
+    for c in line.chars()
+    {
+        if length + c.len_utf8() > 75
+        {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// A content line: name, parameters and value.
+struct Property
+{
+    name: String,
+    parameters: HashMap<String, String>,
+    value: String
+}
+
+fn parse_property(line: &str) -> Option<Property>
+{
+    // the value starts at the first colon outside of quotes
+    let mut quoted = false;
+    let colon = line.char_indices().find(|(_, c)| {
+        if *c == '"'
+        {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    })?.0;
+
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.to_ascii_uppercase();
+    let parameters = head
+        .filter_map(|x| x.split_once('='))
+        .map(|(key, value)| (key.to_ascii_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+
+    Some(Property { name, parameters, value: line[colon + 1..].to_string() })
+}
+
+pub fn parse(content: &str, report: &mut Report) -> Result<Vec<Record>, TodosError>
+{
+    // unfold lines continued with leading whitespace
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, line) in content.lines().enumerate()
+    {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut())
+        {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((i + 1, line.to_string()))
+        }
+    }
+
+    let mut records: Vec<Record> = Vec::new();
+    let mut categories: Vec<Vec<String>> = Vec::new();
+    // parent keys from RELATED-TO, with the position of the child
+    let mut related: Vec<(String, usize)> = Vec::new();
+    let mut dropped: BTreeMap<String, usize> = BTreeMap::new();
+    let mut current: Option<(Record, Vec<String>)> = None;
+    let mut nested = 0;
+
+    for (number, line) in lines
+    {
+        if line.trim().is_empty()
+        {
+            continue;
+        }
+        let property = parse_property(&line)
+            .ok_or_else(|| TodosError::Invalid(format!("Line {}: expected a property like NAME:value.", number)))?;
+        let value = &property.value;
+
+        let (record, record_categories) = match (&property.name[..], &mut current)
+        {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                current = Some((Record::new(format!("\n{}", number), number, String::new()), Vec::new()));
+                continue;
+            },
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VTODO") && nested == 0 => {
+                let (record, record_categories) = current.take().unwrap();
+                if record.description.trim().is_empty()
+                {
+                    return Err(TodosError::Invalid(format!("Line {}: to-do without a SUMMARY.", record.line)));
+                }
+                records.push(record);
+                categories.push(record_categories);
+                continue;
+            },
+            // alarms and the like inside a to-do
+            ("BEGIN", Some(_)) => {
+                nested += 1;
+                continue;
+            },
+            ("END", Some(_)) => {
+                nested -= 1;
+                continue;
+            },
+            (_, Some(_)) if nested > 0 => { continue; },
+            (_, Some((record, record_categories))) => (record, record_categories),
+            (_, None) => { continue; }
+        };
+
+        match &property.name[..]
+        {
+            "UID" => {
+                record.key = value.clone();
+                record.uuid = Some(value.clone());
+            },
+            "SUMMARY" => { record.description = unescape(value); },
+            "DESCRIPTION" => { record.notes = Some(unescape(value)); },
+            "STATUS" => {
+                if value.eq_ignore_ascii_case("COMPLETED") || value.eq_ignore_ascii_case("CANCELLED")
+                {
+                    record.done = true;
+                }
+            },
+            "DUE" => {
+                match parse_due(&property)
+                {
+                    Some((due, exact)) => {
+                        record.due_date = Some(due.to_string());
+                        if !exact
+                        {
+                            report.warnings.push(format!(
+                                "Line {}: the due time {} isn't in UTC; only its date was kept.",
+                                number,
+                                value
+                            ));
+                        }
+                    },
+                    None => report.warnings.push(format!("Line {}: left out the due date {}, which isn't a valid date.", number, value))
+                }
+            },
+            "DTSTART" => {
+                match parse_due(&property)
+                {
+                    Some((until, _)) => { record.deferred_until = Some(until.date()); },
+                    None => report.warnings.push(format!("Line {}: left out the start date {}, which isn't a valid date.", number, value))
+                }
+            },
+            "PRIORITY" => {
+                record.priority = match value.trim().parse::<u8>()
+                {
+                    Ok(level @ 1..=9) => Some((b'A' + level - 1) as char),
+                    _ => None
+                };
+            },
+            "CATEGORIES" => { record_categories.extend(split_list(value)); },
+            "RELATED-TO" => {
+                let relation = property.parameters.get("RELTYPE").map(|x| x.to_ascii_uppercase());
+                match relation.as_deref()
+                {
+                    None | Some("PARENT") => related.push((value.clone(), records.len())),
+                    Some("CHILD") => record.deps.push(value.clone()),
+                    _ => {}
+                }
+            },
+            "RRULE" => {
+                record.recurrence = parse_rrule(value);
+                if record.recurrence.is_none()
+                {
+                    report.warnings.push(format!("Line {}: left out the repeat rule {}, which todos can't follow.", number, value));
+                }
+            },
+            "X-TODOS-TYPE" => {
+                record.node_type = NodeType::from_string(&value.to_ascii_lowercase()).unwrap_or(record.node_type.clone());
+            },
+            name if IGNORED.contains(&name) => {},
+            name => {
+                if !record.done
+                {
+                    *dropped.entry(name.to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    if current.is_some()
+    {
+        return Err(TodosError::Invalid(String::from("The last to-do is missing its END:VTODO.")));
+    }
+
+    // a to-do's key is only known once its UID is read, so parent links are
+    // resolved last
+    let positions: HashMap<String, usize> = records.iter().enumerate().map(|(i, x)| (x.key.clone(), i)).collect();
+    let mut missing = 0;
+    for (parent, child) in related
+    {
+        if records[child].done
+        {
+            continue;
+        }
+        let child = records[child].key.clone();
+        match positions.get(&parent)
+        {
+            Some(parent) => {
+                if !records[*parent].deps.contains(&child)
+                {
+                    records[*parent].deps.push(child);
+                }
+            },
+            // exports only have leaves, so this is common
+            None => { missing += 1; }
+        }
+    }
+    if missing > 0
+    {
+        report.warnings.push(format!(
+            "Dropped {} link(s) to parents that aren't in the file; to-dos are still filed under their categories.",
+            missing
+        ));
+    }
+
+    report_dropped(report, dropped, "to-do", "to-dos");
+    add_categories(&mut records, categories);
+    Ok(records)
+}
+
+/// Reads a DUE property. The flag is false when a local time was reduced to
+/// its date, as todos only keeps times in UTC.
+fn parse_due(property: &Property) -> Option<(Due, bool)>
+{
+    let value = property.value.trim();
+    if value.len() == 8
+    {
+        return Due::parse(value).map(|x| (x, true));
+    }
+    if value.ends_with('Z')
+    {
+        return Due::parse(value).map(|x| (x, true));
+    }
+    // a floating time, or one in the time zone given by TZID
+    let date = Due::parse(value.get(..8)?)?;
+    Some((date, false))
+}
+
+/// Adds a goal for each category, holding the to-dos in that category that
+/// no other to-do in the file is the parent of.
+fn add_categories(records: &mut Vec<Record>, categories: Vec<Vec<String>>)
+{
+    let has_parent: HashSet<String> = records.iter()
+        .filter(|x| !x.done)
+        .flat_map(|x| x.deps.iter().cloned())
+        .collect();
+
+    let mut goals: BTreeMap<String, Record> = BTreeMap::new();
+    for (record, categories) in records.iter().zip(categories)
+    {
+        if record.done || has_parent.contains(&record.key)
+        {
+            continue;
+        }
+        for category in categories
+        {
+            let key = format!("category:{}", category);
+            let goal = goals.entry(key.clone()).or_insert_with(|| {
+                let mut goal = Record::new(key, record.line, category.clone());
+                goal.node_type = NodeType::Goal;
+                goal
+            });
+            goal.deps.push(record.key.clone());
+        }
+    }
+    records.extend(goals.into_values());
+}
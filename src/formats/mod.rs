@@ -0,0 +1,379 @@
+//! Importing todos from, and exporting them to, other tools' formats.
+//!
+//! Each format reads its input into [`Record`]s, which [`add_records`] then
+//! adds to the graph in one transaction, so formats only have to deal with
+//! their own syntax.
+
+pub mod todotxt;
+pub mod taskwarrior;
+pub mod ics;
+pub mod org;
+pub mod csv;
+pub mod html;
+
+use crate::dates::{Interval, Recurrence};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+use crate::outline;
+
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format
+{
+    Outline,
+    TodoTxt,
+    Taskwarrior,
+    Ics,
+    Org,
+    Csv,
+    Html
+}
+
+impl fmt::Display for Format
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", match self {
+            Format::Outline => "outline",
+            Format::TodoTxt => "todotxt",
+            Format::Taskwarrior => "taskwarrior",
+            Format::Ics => "ics",
+            Format::Org => "org",
+            Format::Csv => "csv",
+            Format::Html => "html"
+        })
+    }
+}
+
+impl Format
+{
+    pub fn from_string(s: &str) -> Option<Format>
+    {
+        match s
+        {
+            "outline" => Some(Format::Outline),
+            "todotxt" | "todo.txt" => Some(Format::TodoTxt),
+            "taskwarrior" => Some(Format::Taskwarrior),
+            "ics" | "ical" | "icalendar" => Some(Format::Ics),
+            "org" => Some(Format::Org),
+            "csv" => Some(Format::Csv),
+            "html" => Some(Format::Html),
+            _ => None
+        }
+    }
+}
+
+/// A node read from another format, before it is added to the graph.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record
+{
+    /// Identifies the record within its file, for `deps`.
+    pub key: String,
+    /// Where the record starts, for messages.
+    pub line: usize,
+    pub description: String,
+    pub node_type: NodeType,
+    pub due_date: Option<String>,
+    pub priority: Option<char>,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub uuid: Option<String>,
+    pub recurrence: Option<Recurrence>,
+    /// `YYYY-MM-DD` days earlier instances were completed on, oldest first.
+    pub completed: Vec<String>,
+    pub deferred_until: Option<NaiveDate>,
+    pub time: Vec<Interval>,
+    /// Keys of the records this one depends on.
+    pub deps: Vec<String>,
+    /// Already completed, so not added.
+    pub done: bool
+}
+
+impl Record
+{
+    pub fn new(key: String, line: usize, description: String) -> Record
+    {
+        Record
+        {
+            key,
+            line,
+            description,
+            node_type: NodeType::Task,
+            due_date: None,
+            priority: None,
+            tags: Vec::new(),
+            notes: None,
+            uuid: None,
+            recurrence: None,
+            completed: Vec::new(),
+            deferred_until: None,
+            time: Vec::new(),
+            deps: Vec::new(),
+            done: false
+        }
+    }
+}
+
+/// What an import did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report
+{
+    /// Ids of the new nodes.
+    pub added: Vec<usize>,
+    /// Completed items that were left out.
+    pub skipped: usize,
+    /// Anything that couldn't be represented in the graph.
+    pub warnings: Vec<String>
+}
+
+/// The result of an export.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exported
+{
+    pub content: String,
+    /// Anything that couldn't be represented in the format.
+    pub warnings: Vec<String>
+}
+
+pub fn import(graph: &mut Graph, format: Format, content: &str, under: Option<usize>) -> Result<Report, TodosError>
+{
+    let mut report = Report::default();
+    match format
+    {
+        Format::Outline => {
+            let entries = outline::parse_plain(content)?;
+            let (added, skipped) = outline::import(graph, under, &entries)?;
+            report.added = added;
+            report.skipped = skipped;
+        },
+        Format::TodoTxt => {
+            let records = todotxt::parse(content, &mut report)?;
+            add_records(graph, under, &records, &mut report)?;
+        },
+        Format::Taskwarrior => {
+            let records = taskwarrior::parse(content, &mut report)?;
+            add_records(graph, under, &records, &mut report)?;
+        },
+        Format::Ics => {
+            let records = ics::parse(content, &mut report)?;
+            add_records(graph, under, &records, &mut report)?;
+        },
+        Format::Org => {
+            let records = org::parse(content, &mut report)?;
+            add_records(graph, under, &records, &mut report)?;
+        },
+        Format::Csv => {
+            let records = csv::parse(content, &mut report)?;
+            add_records(graph, under, &records, &mut report)?;
+        },
+        Format::Html => {
+            return Err(TodosError::Invalid(String::from("HTML reports can only be exported.")));
+        }
+    }
+    Ok(report)
+}
+
+/// Exports everything under `under` (including `under` itself), or the
+/// whole graph. iCalendar only gets the tasks there is something to do
+/// about, and HTML starts from the effective root, as `todos` does.
+///
+/// Only changes the graph to give nodes an id other tools need, like the
+/// UUIDs of Taskwarrior or the ids org links to, so that later exports use
+/// the same one.
+pub fn export(graph: &mut Graph, format: Format, under: Option<usize>) -> Result<Exported, TodosError>
+{
+    let content = match format
+    {
+        Format::Outline => outline::render_plain(graph, under)?,
+        Format::TodoTxt => todotxt::export(graph, &subtree(graph, under)?),
+        Format::Taskwarrior => {
+            let ids = subtree(graph, under)?;
+            return taskwarrior::export(graph, &ids);
+        },
+        Format::Ics => { return ics::export(graph, under); },
+        Format::Org => {
+            let ids = subtree(graph, under)?;
+            return org::export(graph, &ids, under);
+        },
+        Format::Csv => csv::export(graph, &subtree(graph, under)?, under),
+        Format::Html => html::export(graph, under)?
+    };
+    Ok(Exported { content, warnings: Vec::new() })
+}
+
+/// `root` and everything under it, or every node if `root` is `None`.
+pub fn subtree(graph: &Graph, root: Option<usize>) -> Result<BTreeSet<usize>, TodosError>
+{
+    let mut ids = outline::region(graph, root)?;
+    ids.extend(root);
+    Ok(ids)
+}
+
+/// The nodes in `ids` with a condition in `ids` somewhere below them.
+pub fn blocked(graph: &Graph, ids: &BTreeSet<usize>) -> HashSet<usize>
+{
+    // everything above each condition
+    let mut blocked = HashSet::new();
+    let mut stack: Vec<usize> = ids.iter().copied().filter(|x| graph.nodes()[*x].node_type == NodeType::Condition).collect();
+    while let Some(id) = stack.pop()
+    {
+        for parent in &graph.nodes()[id].parents
+        {
+            if ids.contains(parent) && blocked.insert(*parent)
+            {
+                stack.push(*parent);
+            }
+        }
+    }
+    blocked
+}
+
+/// Gives every node in `ids` without a UUID a new one. Formats that need
+/// ids other tools can keep track of use these, so that later exports use
+/// the same ones.
+pub fn assign_uuids<'a>(graph: &mut Graph, ids: impl IntoIterator<Item = &'a usize>) -> Result<(), TodosError>
+{
+    for id in ids
+    {
+        if graph.node(*id)?.uuid.is_none()
+        {
+            graph.set_uuid(*id, Some(uuid::Uuid::new_v4().to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Warns about the fields each format left out, once per field.
+pub fn report_dropped(report: &mut Report, dropped: BTreeMap<String, usize>, one: &str, many: &str)
+{
+    for (key, count) in dropped
+    {
+        report.warnings.push(format!(
+            "Left out {} of {} {}, which todos doesn't keep.",
+            key,
+            count,
+            if count == 1 { one } else { many }
+        ));
+    }
+}
+
+/// Adds `records` as new nodes, linked as their `deps` say. Records nothing
+/// depends on go under `under` (or the effective root), and records that are
+/// done are skipped, as are dependencies on them.
+///
+/// Everything happens in one transaction and the graph is validated before
+/// it is kept, so on error nothing is added.
+pub fn add_records(graph: &mut Graph, under: Option<usize>, records: &[Record], report: &mut Report) -> Result<(), TodosError>
+{
+    let top = match under
+    {
+        Some(under) => Some(graph.node(under)?.id),
+        None => graph.effective_root()
+    };
+
+    let mut keys: HashMap<&str, &Record> = HashMap::new();
+    for record in records
+    {
+        if let Some(other) = keys.insert(&record.key, record)
+        {
+            return Err(TodosError::Invalid(format!(
+                "Entries {} and {} both have the id {}.",
+                other.line,
+                record.line,
+                record.key
+            )));
+        }
+    }
+
+    // drop links to records that are missing or done
+    let mut deps: Vec<Vec<&str>> = Vec::with_capacity(records.len());
+    for record in records
+    {
+        let mut wanted = Vec::new();
+        for dep in &record.deps
+        {
+            match keys.get(&dep[..])
+            {
+                Some(other) if !other.done => wanted.push(&dep[..]),
+                Some(_) => {},
+                None => report.warnings.push(format!(
+                    "Line {}: depends on {}, which isn't in the file; the dependency was dropped.",
+                    record.line,
+                    dep
+                ))
+            }
+        }
+        deps.push(wanted);
+    }
+    let has_parent: BTreeSet<&str> = records.iter()
+        .zip(&deps)
+        .filter(|(record, _)| !record.done)
+        .flat_map(|(_, deps)| deps.iter().copied())
+        .collect();
+
+    graph.transaction(|graph| {
+        // add every node at the top first, in file order, so that importing
+        // into an empty graph keeps the order of the file
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        for record in records
+        {
+            if record.done
+            {
+                report.skipped += 1;
+                continue;
+            }
+
+            let id = graph.add_node_to(record.description.clone(), record.node_type.clone(), top)?;
+            graph.set_due_date(id, record.due_date.clone())?;
+            graph.set_priority(id, record.priority).map_err(|error| {
+                TodosError::Invalid(format!("Line {}: {}", record.line, error))
+            })?;
+            graph.tag(id, record.tags.clone())?;
+            graph.set_notes(id, record.notes.clone())?;
+            graph.set_uuid(id, record.uuid.clone())?;
+            graph.set_recurrence(id, record.recurrence.clone())?;
+            graph.set_completed(id, record.completed.clone())?;
+            graph.defer(id, record.deferred_until)?;
+            let mut time = record.time.clone();
+            if let Some(running) = graph.running().filter(|_| time.iter().any(|x| x.end.is_none()))
+            {
+                // only one timer can run
+                time.retain(|x| x.end.is_some());
+                report.warnings.push(format!(
+                    "Line {}: left out a running timer, as one already runs on node {}.",
+                    record.line,
+                    running
+                ));
+            }
+            graph.set_time(id, time)?;
+            ids.insert(&record.key, id);
+            report.added.push(id);
+        }
+
+        for (record, deps) in records.iter().zip(&deps)
+        {
+            if record.done
+            {
+                continue;
+            }
+            for dep in deps
+            {
+                graph.link(&ids[&record.key[..]], &ids[dep])?;
+            }
+        }
+
+        if let Some(top) = top
+        {
+            for key in &has_parent
+            {
+                graph.unlink(&top, &ids[key])?;
+            }
+        }
+
+        graph.validate()
+    })
+}
@@ -0,0 +1,670 @@
+//! [Org mode](https://orgmode.org) outlines, for planning in Emacs.
+//!
+//! Goals are plain headlines, tasks `TODO` items and conditions `WAITING`
+//! items, nested as the graph is. A node with several parents is written in
+//! full under the first one, with its UUID as its `:ID:` property, and as a
+//! link to that id (`[[id:...][description]]`) under the others, the way
+//! `org-id` links headlines. Due dates are `DEADLINE`s, the day a deferred
+//! node shows up again its `SCHEDULED` date, priorities `[#A]` cookies, tags
+//! headline tags and notes the text under a headline. How a node repeats is
+//! its `:REPEAT:` property, in the words `todos repeat` takes, and the days
+//! it was completed on are `State "DONE"` entries in its `:LOGBOOK:`, along
+//! with a `CLOCK` entry for each interval of tracked time. Clocks are in
+//! local time to the minute, so seconds are left out.
+//!
+//! On import, `#+TODO:` lines are honoured: keywords before the `|` are open
+//! (conditions if they are `WAITING`, `WAIT` or `HOLD`, tasks otherwise),
+//! and the ones after it are done, so those headlines are skipped with
+//! everything under them.
+
+use super::{assign_uuids, report_dropped, Exported, Record, Report};
+use crate::dates::{Due, Interval, Recurrence};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Keywords of open items that are conditions rather than tasks.
+const WAITING: [&str; 3] = ["WAITING", "WAIT", "HOLD"];
+
+pub fn export(graph: &mut Graph, ids: &BTreeSet<usize>, under: Option<usize>) -> Result<Exported, TodosError>
+{
+    // nodes that appear more than once need an id to link to
+    let shared: Vec<usize> = ids.iter()
+        .copied()
+        .filter(|x| graph.nodes()[*x].parents.iter().filter(|parent| ids.contains(parent)).count() > 1)
+        .collect();
+    assign_uuids(graph, &shared)?;
+
+    let top: Vec<usize> = match under
+    {
+        Some(under) => vec![under],
+        None => graph.nodes().iter().filter(|x| x.parents.is_empty()).map(|x| x.id).collect()
+    };
+
+    let mut exported = Exported::default();
+    // nodes whose clocks lost their seconds
+    let mut rounded = 0;
+    let mut seen = HashSet::new();
+    let mut stack: Vec<(usize, usize)> = top.into_iter().rev().map(|id| (id, 1)).collect();
+    while let Some((id, level)) = stack.pop()
+    {
+        let node = &graph.nodes()[id];
+        let out = &mut exported.content;
+        out.push_str(&"*".repeat(level));
+        out.push(' ');
+        match node.node_type
+        {
+            NodeType::Task => out.push_str("TODO "),
+            NodeType::Condition => out.push_str("WAITING "),
+            NodeType::Goal => {}
+        }
+        if let Some(priority) = node.priority
+        {
+            out.push_str(&format!("[#{}] ", priority));
+        }
+
+        let title = node.description.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !seen.insert(id)
+        {
+            // written in full elsewhere
+            out.push_str(&format!("[[id:{}][{}]]\n", node.uuid.as_ref().unwrap(), title));
+            continue;
+        }
+        out.push_str(&title);
+        if node.node_type == NodeType::Goal && keyword(&title).is_some()
+        {
+            exported.warnings.push(format!(
+                "Node {}: the goal \"{}\" starts with a word org reads as a keyword, so it will be imported as an item.",
+                id,
+                title
+            ));
+        }
+
+        let mut tags = Vec::new();
+        for tag in &node.tags
+        {
+            if !tag.is_empty() && tag.chars().all(|c| c.is_alphanumeric() || "_@#%".contains(c))
+            {
+                tags.push(tag.as_str());
+            }
+            else
+            {
+                exported.warnings.push(format!(
+                    "Node {}: left out the tag \"{}\"; org tags can only have letters, numbers and _@#%.",
+                    id,
+                    tag
+                ));
+            }
+        }
+        if !tags.is_empty()
+        {
+            out.push_str(&format!(" :{}:", tags.join(":")));
+        }
+        out.push('\n');
+
+        let indent = " ".repeat(level + 1);
+        let mut planning = Vec::new();
+        if let Some(due_date) = &node.due_date
+        {
+            match Due::parse(due_date)
+            {
+                Some(due) => planning.push(format!("DEADLINE: {}", timestamp(&due))),
+                None => exported.warnings.push(format!(
+                    "Node {}: left out the due date {}, which isn't a date org understands.",
+                    id,
+                    due_date
+                ))
+            }
+        }
+        if let Some(until) = node.deferred_until.as_deref().and_then(Due::parse)
+        {
+            planning.push(format!("SCHEDULED: {}", timestamp(&Due::Date(until.date()))));
+        }
+        if !planning.is_empty()
+        {
+            out.push_str(&format!("{}{}\n", indent, planning.join(" ")));
+        }
+        let mut properties = Vec::new();
+        if let Some(uuid) = &node.uuid
+        {
+            properties.push(("ID", uuid.clone()));
+        }
+        if let Some(recurrence) = &node.recurrence
+        {
+            properties.push(("REPEAT", recurrence.to_string()));
+        }
+        if !properties.is_empty()
+        {
+            out.push_str(&format!("{}:PROPERTIES:\n", indent));
+            for (key, value) in properties
+            {
+                out.push_str(&format!("{}:{}: {}\n", indent, key, value));
+            }
+            out.push_str(&format!("{}:END:\n", indent));
+        }
+        if !node.completed.is_empty() || !node.time.is_empty()
+        {
+            out.push_str(&format!("{}:LOGBOOK:\n", indent));
+            for interval in node.time.iter().rev()
+            {
+                match clock(interval)
+                {
+                    Some((line, exact)) => {
+                        out.push_str(&format!("{}{}\n", indent, line));
+                        rounded += !exact as usize;
+                    },
+                    None => exported.warnings.push(format!(
+                        "Node {}: left out the tracked time {}, which isn't a valid interval.",
+                        id,
+                        interval
+                    ))
+                }
+            }
+            // newest first, as org adds them
+            for day in node.completed.iter().rev()
+            {
+                let day = NaiveDate::parse_from_str(day, "%Y-%m-%d").map(|x| x.format("%Y-%m-%d %a").to_string()).unwrap_or_else(|_| day.clone());
+                out.push_str(&format!("{}- State \"DONE\"       from \"TODO\"       [{}]\n", indent, day));
+            }
+            out.push_str(&format!("{}:END:\n", indent));
+        }
+        if let Some(notes) = &node.notes
+        {
+            for line in notes.lines()
+            {
+                if !line.trim().is_empty()
+                {
+                    out.push_str(&indent);
+                    out.push_str(line);
+                }
+                out.push('\n');
+            }
+        }
+
+        stack.extend(node.deps.iter().rev().filter(|x| ids.contains(x)).map(|child| (*child, level + 1)));
+    }
+    if rounded > 0
+    {
+        exported.warnings.push(format!("Left out the seconds of {} clock(s), as org clocks have minutes.", rounded));
+    }
+    Ok(exported)
+}
+
+/// A `CLOCK:` line for an interval, with its length, or just its start
+/// while the timer runs. The flag is false when seconds were left out.
+fn clock(interval: &Interval) -> Option<(String, bool)>
+{
+    let stamp = |moment: &str| {
+        let moment = DateTime::parse_from_rfc3339(moment).ok()?.with_timezone(&Local);
+        Some((moment, moment.format("[%Y-%m-%d %a %H:%M]").to_string()))
+    };
+    let (start, start_stamp) = stamp(&interval.start)?;
+    let end = match &interval.end
+    {
+        Some(end) => Some(stamp(end)?),
+        None => None
+    };
+
+    let exact = start.second() == 0 && end.as_ref().map(|x| x.0.second() == 0).unwrap_or(true);
+    let line = match end
+    {
+        Some((end, end_stamp)) => {
+            // the length org shows is between the minutes written
+            let minutes = (end.with_second(0)? - start.with_second(0)?).num_minutes();
+            format!("CLOCK: {}--{} => {:>2}:{:02}", start_stamp, end_stamp, minutes / 60, minutes % 60)
+        },
+        None => format!("CLOCK: {}", start_stamp)
+    };
+    Some((line, exact))
+}
+
+/// The interval of a logbook line like `CLOCK: [2024-06-01 Sat
+/// 10:00]--[2024-06-01 Sat 11:00] =>  1:00`.
+fn logbook_clock(line: &str) -> Option<Interval>
+{
+    let rest = line.strip_prefix("CLOCK:")?.trim();
+    let moment = |stamp: &str| {
+        let stamp = stamp.strip_prefix('[')?.strip_suffix(']')?;
+        let mut parts = stamp.split_whitespace();
+        let date = NaiveDate::parse_from_str(parts.next()?, "%Y-%m-%d").ok()?;
+        let time = parts.find_map(|x| NaiveTime::parse_from_str(x, "%H:%M").ok())?;
+        let moment = Local.from_local_datetime(&date.and_time(time)).earliest()?.with_timezone(&Utc);
+        Some(Due::Time(moment).to_string())
+    };
+    match rest.split_once("--")
+    {
+        Some((start, end)) => {
+            let end = end.split("=>").next()?.trim();
+            Some(Interval { start: moment(start.trim())?, end: Some(moment(end)?) })
+        },
+        None => Some(Interval { start: moment(rest)?, end: None })
+    }
+}
+
+/// An active timestamp: `<2024-06-01 Sat>`, with the local time if there is
+/// one, as org has no time zones.
+fn timestamp(due: &Due) -> String
+{
+    match due
+    {
+        Due::Date(date) => date.format("<%Y-%m-%d %a>").to_string(),
+        Due::Time(time) => time.with_timezone(&Local).format("<%Y-%m-%d %a %H:%M>").to_string()
+    }
+}
+
+/// Reads the date and local time of a timestamp like `<2024-06-01 Sat 15:00
+/// +1w>`. The flag is true when there is more to it, like a repeater, that
+/// was left out.
+fn parse_timestamp(text: &str) -> Option<(Due, bool)>
+{
+    let mut parts = text.split_whitespace();
+    let date = NaiveDate::parse_from_str(parts.next()?, "%Y-%m-%d").ok()?;
+    let mut time = None;
+    let mut rest = false;
+    for part in parts
+    {
+        if part.chars().all(|c| c.is_alphabetic() || c == '.')
+        {
+            // the day of the week
+            continue;
+        }
+        // a range like 10:00-11:00 is due when it starts
+        match NaiveTime::parse_from_str(part.split('-').next().unwrap(), "%H:%M")
+        {
+            Ok(start) if time.is_none() => { time = Some(start); },
+            _ => { rest = true; }
+        }
+    }
+
+    let due = match time
+    {
+        Some(time) => Local.from_local_datetime(&date.and_time(time)).earliest().map(|x| Due::from(x.with_timezone(&Utc)))?,
+        None => Due::Date(date)
+    };
+    Some((due, rest))
+}
+
+/// The first word of `title`, if it is one of the default keywords.
+fn keyword(title: &str) -> Option<&str>
+{
+    let first = title.split(' ').next()?;
+    ["TODO", "NEXT", "WAITING", "HOLD", "DONE", "CANCELED", "CANCELLED", "COMMENT"].iter().copied().find(|x| *x == first)
+}
+
+/// A headline: its level, keyword, priority, title and tags.
+struct Headline<'a>
+{
+    level: usize,
+    keyword: Option<&'a str>,
+    priority: Option<char>,
+    title: &'a str,
+    tags: Vec<&'a str>
+}
+
+fn parse_headline<'a>(line: &'a str, open: &[String], done: &[String]) -> Option<Headline<'a>>
+{
+    let level = line.chars().take_while(|c| *c == '*').count();
+    if level == 0
+    {
+        return None;
+    }
+    let mut rest = line[level..].strip_prefix(' ')?.trim();
+
+    let first = rest.split(' ').next().unwrap_or("");
+    let keyword = open.iter().chain(done).find(|x| **x == first).map(|_| first);
+    if let Some(keyword) = keyword
+    {
+        rest = rest[keyword.len()..].trim_start();
+    }
+
+    let mut priority = None;
+    let bytes = rest.as_bytes();
+    if bytes.len() >= 4 && bytes.starts_with(b"[#") && bytes[3] == b']' && bytes[2].is_ascii_uppercase()
+    {
+        priority = Some(bytes[2] as char);
+        rest = rest[4..].trim_start();
+    }
+
+    // tags are the last word, when it is like :a:b:
+    let mut tags = Vec::new();
+    if let Some((title, last)) = rest.rsplit_once(' ').or(Some(("", rest)))
+    {
+        if last.len() > 1 && last.starts_with(':') && last.ends_with(':') && !last.contains("::")
+        {
+            tags = last.trim_matches(':').split(':').collect();
+            rest = title.trim_end();
+        }
+    }
+
+    Some(Headline { level, keyword, priority, title: rest, tags })
+}
+
+/// The id a headline links to, if all it is is a link like `[[id:...][...]]`.
+fn link_target(title: &str) -> Option<&str>
+{
+    let inner = title.strip_prefix("[[id:")?.strip_suffix("]]")?;
+    match inner.split_once("][")
+    {
+        Some((id, _)) => Some(id),
+        None => Some(inner)
+    }
+}
+
+pub fn parse(content: &str, report: &mut Report) -> Result<Vec<Record>, TodosError>
+{
+    // the keywords in use: open ones, then done ones
+    let mut open: Vec<String> = Vec::new();
+    let mut done: Vec<String> = Vec::new();
+    for line in content.lines()
+    {
+        let words = ["#+TODO:", "#+SEQ_TODO:", "#+TYP_TODO:"].iter().find_map(|x| line.trim().strip_prefix(x));
+        if let Some(words) = words
+        {
+            let (before, after) = words.split_once('|').unwrap_or((words, ""));
+            let mut before: Vec<String> = before.split_whitespace().map(|x| x.split('(').next().unwrap().to_string()).collect();
+            let after: Vec<String> = after.split_whitespace().map(|x| x.split('(').next().unwrap().to_string()).collect();
+            if after.is_empty()
+            {
+                // the last keyword means done
+                done.extend(before.pop());
+            }
+            open.extend(before);
+            done.extend(after);
+        }
+    }
+    if open.is_empty() && done.is_empty()
+    {
+        open = vec!["TODO".to_string(), "NEXT".to_string(), "WAITING".to_string(), "HOLD".to_string()];
+        done = vec!["DONE".to_string(), "CANCELED".to_string(), "CANCELLED".to_string()];
+    }
+
+    let mut records: Vec<Record> = Vec::new();
+    // the key of each headline on the current path, by level
+    let mut path: Vec<(usize, String, bool)> = Vec::new();
+    // parent and child keys
+    let mut links: Vec<(usize, String, String)> = Vec::new();
+    let mut notes: Vec<Vec<&str>> = Vec::new();
+    let mut dropped: BTreeMap<String, usize> = BTreeMap::new();
+    // whether the current headline is a link, whose body is left out
+    let mut in_link = false;
+    let mut drawer: Option<String> = None;
+    // whether the drawer has lines that were left out
+    let mut unread = false;
+    let mut preamble = false;
+
+    for (i, line) in content.lines().enumerate()
+    {
+        let number = i + 1;
+        if let Some(headline) = parse_headline(line, &open, &done)
+        {
+            // a drawer left open ends with its headline
+            if let (Some(name), Some(record)) = (drawer.take(), records.last())
+            {
+                if unread && !record.done && !in_link
+                {
+                    *dropped.entry(format!("the {} drawer", name)).or_default() += 1;
+                }
+            }
+            unread = false;
+            while path.last().map(|x| x.0 >= headline.level).unwrap_or(false)
+            {
+                path.pop();
+            }
+            let parent = path.last().map(|x| x.1.clone());
+            let parent_done = path.last().map(|x| x.2).unwrap_or(false);
+
+            if let Some(target) = link_target(headline.title)
+            {
+                if let Some(parent) = parent
+                {
+                    links.push((number, parent, target.to_string()));
+                }
+                path.push((headline.level, target.to_string(), parent_done));
+                in_link = true;
+                continue;
+            }
+            in_link = false;
+
+            if headline.title.is_empty()
+            {
+                return Err(TodosError::Invalid(format!("Line {}: headline without a title.", number)));
+            }
+            let mut record = Record::new(format!("\n{}", number), number, headline.title.to_string());
+            record.priority = headline.priority;
+            record.tags = headline.tags.iter().map(|x| x.to_string()).collect();
+            record.node_type = match headline.keyword
+            {
+                None => NodeType::Goal,
+                Some(keyword) if WAITING.contains(&keyword) => NodeType::Condition,
+                Some(_) => NodeType::Task
+            };
+            // everything under something done is done too
+            record.done = headline.keyword.map(|x| done.iter().any(|y| y == x)).unwrap_or(false) || parent_done;
+
+            if let Some(parent) = parent
+            {
+                links.push((number, parent, record.key.clone()));
+            }
+            path.push((headline.level, record.key.clone(), record.done));
+            records.push(record);
+            notes.push(Vec::new());
+            continue;
+        }
+
+        let trimmed = line.trim();
+        let record = match records.last_mut()
+        {
+            _ if in_link => { continue; },
+            Some(record) => record,
+            None => {
+                // settings like #+TITLE: are fine
+                preamble |= !trimmed.is_empty() && !trimmed.starts_with('#');
+                continue;
+            }
+        };
+
+        // drawers: the properties, and others like LOGBOOK
+        if let Some(name) = &drawer
+        {
+            if trimmed.eq_ignore_ascii_case(":END:")
+            {
+                if unread && !record.done
+                {
+                    *dropped.entry(format!("the {} drawer", name)).or_default() += 1;
+                }
+                drawer = None;
+                unread = false;
+            }
+            else if name == "LOGBOOK"
+            {
+                match (logbook_state(trimmed), logbook_clock(trimmed))
+                {
+                    (Some((keyword, day)), _) if done.iter().any(|x| x == keyword) => record.completed.insert(0, day),
+                    (_, Some(interval)) => record.time.insert(0, interval),
+                    _ => { unread |= !trimmed.is_empty(); }
+                }
+            }
+            else if name == "PROPERTIES"
+            {
+                let property = trimmed.strip_prefix(':').and_then(|x| x.split_once(':'));
+                match property
+                {
+                    Some((key, value)) if key.eq_ignore_ascii_case("ID") && !value.trim().is_empty() => {
+                        // links are resolved by key, so the key changes here
+                        let old = std::mem::replace(&mut record.key, value.trim().to_string());
+                        record.uuid = Some(record.key.clone());
+                        for link in links.iter_mut().filter(|x| x.2 == old)
+                        {
+                            link.2 = record.key.clone();
+                        }
+                        for entry in path.iter_mut().filter(|x| x.1 == old)
+                        {
+                            entry.1 = record.key.clone();
+                        }
+                    },
+                    Some((key, value)) if key.eq_ignore_ascii_case("REPEAT") && !record.done => {
+                        record.recurrence = Recurrence::parse(value);
+                        if record.recurrence.is_none()
+                        {
+                            report.warnings.push(format!("Line {}: left out :REPEAT:{}, which isn't a rule todos understands.", number, value));
+                        }
+                    },
+                    Some((key, _)) if !record.done => {
+                        *dropped.entry(key.to_ascii_uppercase()).or_default() += 1;
+                    },
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        if trimmed.len() > 2 && trimmed.starts_with(':') && trimmed.ends_with(':') && !trimmed[1..].contains(' ')
+        {
+            let name = trimmed.trim_matches(':').to_ascii_uppercase();
+            // the lines of other drawers are all left out
+            unread = name != "PROPERTIES" && name != "LOGBOOK";
+            drawer = Some(name);
+            continue;
+        }
+
+        // the planning line, which comes before the text
+        let first = notes.last().unwrap().iter().all(|x| x.trim().is_empty());
+        if first && ["DEADLINE:", "SCHEDULED:", "CLOSED:"].iter().any(|x| trimmed.starts_with(x))
+        {
+            for (name, text) in planning(trimmed)
+            {
+                if record.done
+                {
+                    continue;
+                }
+                if name == "SCHEDULED"
+                {
+                    match parse_timestamp(text)
+                    {
+                        Some((until, rest)) => {
+                            record.deferred_until = Some(until.date());
+                            if rest
+                            {
+                                report.warnings.push(format!(
+                                    "Line {}: only the date of the scheduled time <{}> was kept.",
+                                    number,
+                                    text
+                                ));
+                            }
+                        },
+                        None => report.warnings.push(format!("Line {}: left out the scheduled date <{}>, which isn't a valid date.", number, text))
+                    }
+                    continue;
+                }
+                if name != "DEADLINE"
+                {
+                    *dropped.entry(name.to_string()).or_default() += 1;
+                    continue;
+                }
+                match parse_timestamp(text)
+                {
+                    Some((due, rest)) => {
+                        record.due_date = Some(due.to_string());
+                        if rest
+                        {
+                            report.warnings.push(format!(
+                                "Line {}: only the date and time of the deadline <{}> were kept.",
+                                number,
+                                text
+                            ));
+                        }
+                    },
+                    None => report.warnings.push(format!("Line {}: left out the deadline <{}>, which isn't a valid date.", number, text))
+                }
+            }
+            continue;
+        }
+
+        notes.last_mut().unwrap().push(line);
+    }
+
+    if preamble
+    {
+        report.warnings.push(String::from("Left out the text before the first headline."));
+    }
+    report_dropped(report, dropped, "headline", "headlines");
+
+    for (record, lines) in records.iter_mut().zip(notes)
+    {
+        record.notes = dedent(&lines);
+    }
+
+    let positions: HashMap<String, usize> = records.iter().enumerate().map(|(i, x)| (x.key.clone(), i)).collect();
+    for (number, parent, child) in links
+    {
+        match positions.get(&parent)
+        {
+            Some(parent) => {
+                if !records[*parent].deps.contains(&child)
+                {
+                    records[*parent].deps.push(child);
+                }
+            },
+            None => report.warnings.push(format!(
+                "Line {}: under a link to {}, which isn't in the file; the headline was put at the top.",
+                number,
+                parent
+            ))
+        }
+    }
+    Ok(records)
+}
+
+/// The keyword and day of a logbook line like `- State "DONE" from "TODO"
+/// [2024-06-01 Sat 10:00]`.
+fn logbook_state(line: &str) -> Option<(&str, String)>
+{
+    let rest = line.strip_prefix("- State \"")?;
+    let (keyword, rest) = rest.split_once('"')?;
+    let stamp = &rest[rest.find('[')? + 1..];
+    let day = NaiveDate::parse_from_str(stamp.split_whitespace().next()?, "%Y-%m-%d").ok()?;
+    Some((keyword, day.format("%Y-%m-%d").to_string()))
+}
+
+/// The keywords of a planning line and their timestamps, without brackets.
+fn planning(line: &str) -> Vec<(&str, &str)>
+{
+    let mut found = Vec::new();
+    for name in ["DEADLINE", "SCHEDULED", "CLOSED"]
+    {
+        let rest = match line.find(&format!("{}:", name))
+        {
+            Some(start) => line[start + name.len() + 1..].trim_start(),
+            None => { continue; }
+        };
+        let close = match rest.chars().next()
+        {
+            Some('<') => '>',
+            Some('[') => ']',
+            _ => { continue; }
+        };
+        if let Some(end) = rest.find(close)
+        {
+            found.push((name, &rest[1..end]));
+        }
+    }
+    found
+}
+
+/// The lines of a headline's body as notes, without the indentation they
+/// share or blank lines around them.
+fn dedent(lines: &[&str]) -> Option<String>
+{
+    let start = lines.iter().position(|x| !x.trim().is_empty())?;
+    let end = lines.iter().rposition(|x| !x.trim().is_empty())? + 1;
+    let lines = &lines[start..end];
+    let indent = lines.iter()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| x.len() - x.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let notes: Vec<&str> = lines.iter().map(|x| x.get(indent..).unwrap_or("").trim_end()).collect();
+    Some(notes.join("\n"))
+}
@@ -0,0 +1,360 @@
+//! [Taskwarrior](https://taskwarrior.org)'s JSON, as written by `task export`
+//! and read by `task import`.
+//!
+//! `depends` maps directly onto deps, and projects become goals: a task in
+//! project `home.garden` goes under a goal `garden` under a goal `home`.
+//! On export, each node's project is the chain of goals above it. Node types
+//! other than tasks are kept in a `todostype` attribute, which Taskwarrior
+//! keeps even without a matching UDA, and so are how a node repeats
+//! (`todosrecurrence`) and the days it was completed on (`todoscompleted`),
+//! as Taskwarrior's own recurrence needs templates todos doesn't have, and
+//! the time tracked on it (`todostime`, intervals like `start/end`).
+//! Deferred nodes `wait` until the day they show up again. Waiting tasks are
+//! imported as conditions, and annotations as notes.
+
+use super::{assign_uuids, report_dropped, Exported, Record, Report};
+use crate::dates::{Due, Interval, Recurrence};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Taskwarrior fields that have no place in the graph but aren't worth
+/// a warning, mostly bookkeeping Taskwarrior redoes itself.
+const IGNORED: [&str; 6] = ["id", "entry", "modified", "urgency", "end", "mask"];
+
+pub fn export(graph: &mut Graph, ids: &BTreeSet<usize>) -> Result<Exported, TodosError>
+{
+    let mut exported = Exported::default();
+    assign_uuids(graph, ids)?;
+
+    let now = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut tasks = Vec::new();
+    for id in ids
+    {
+        let node = &graph.nodes()[*id];
+        let mut task = Map::new();
+        task.insert("uuid".into(), json!(node.uuid));
+        task.insert("description".into(), json!(node.description));
+        task.insert("status".into(), json!("pending"));
+
+        let depends: Vec<&String> = node.deps.iter()
+            .filter(|x| ids.contains(x))
+            .filter_map(|x| graph.nodes()[*x].uuid.as_ref())
+            .collect();
+        if !depends.is_empty()
+        {
+            task.insert("depends".into(), json!(depends));
+        }
+
+        let project = project(graph, *id);
+        if !project.is_empty()
+        {
+            task.insert("project".into(), json!(project.join(".")));
+        }
+
+        if let Some(due_date) = &node.due_date
+        {
+            match Due::parse(due_date)
+            {
+                // Taskwarrior always wants a time
+                Some(Due::Date(date)) => { task.insert("due".into(), json!(date.format("%Y%m%dT000000Z").to_string())); },
+                Some(due) => { task.insert("due".into(), json!(due.to_compact())); },
+                None => exported.warnings.push(format!(
+                    "Node {}: left out the due date {}, which isn't a date Taskwarrior understands.",
+                    id,
+                    due_date
+                ))
+            }
+        }
+
+        if let Some(priority) = node.priority
+        {
+            let level = match priority
+            {
+                'A' => "H",
+                'B' => "M",
+                _ => "L"
+            };
+            if priority > 'C'
+            {
+                exported.warnings.push(format!("Node {}: priority {} became L, Taskwarrior's lowest.", id, priority));
+            }
+            task.insert("priority".into(), json!(level));
+        }
+
+        let mut tags = Vec::new();
+        for tag in &node.tags
+        {
+            if tag.is_empty() || tag.contains(char::is_whitespace)
+            {
+                exported.warnings.push(format!("Node {}: left out the tag \"{}\"; Taskwarrior tags can't contain spaces.", id, tag));
+            }
+            else
+            {
+                tags.push(tag);
+            }
+        }
+        if !tags.is_empty()
+        {
+            task.insert("tags".into(), json!(tags));
+        }
+
+        if let Some(notes) = &node.notes
+        {
+            task.insert("annotations".into(), json!([{ "entry": now, "description": notes }]));
+        }
+
+        match node.node_type
+        {
+            NodeType::Goal => { task.insert("todostype".into(), json!("goal")); },
+            NodeType::Condition => { task.insert("todostype".into(), json!("condition")); },
+            NodeType::Task => {}
+        }
+        if let Some(until) = node.deferred_until.as_deref().and_then(Due::parse)
+        {
+            task.insert("wait".into(), json!(until.date().format("%Y%m%dT000000Z").to_string()));
+        }
+        if let Some(recurrence) = &node.recurrence
+        {
+            task.insert("todosrecurrence".into(), json!(recurrence.to_string()));
+        }
+        if !node.completed.is_empty()
+        {
+            task.insert("todoscompleted".into(), json!(node.completed.join(",")));
+        }
+        if !node.time.is_empty()
+        {
+            task.insert("todostime".into(), json!(node.time.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")));
+        }
+
+        tasks.push(Value::Object(task));
+    }
+
+    // serializing plain JSON values can't fail
+    exported.content = serde_json::to_string_pretty(&tasks).unwrap() + "\n";
+    Ok(exported)
+}
+
+/// The goals above a node, outermost first, following the first parent.
+fn project(graph: &Graph, id: usize) -> Vec<String>
+{
+    let mut goals = Vec::new();
+    let mut current = id;
+    while let Some(parent) = graph.nodes()[current].parents.first()
+    {
+        let parent = &graph.nodes()[*parent];
+        if parent.node_type == NodeType::Goal
+        {
+            goals.push(parent.description.clone());
+        }
+        current = parent.id;
+    }
+    goals.reverse();
+    goals
+}
+
+pub fn parse(content: &str, report: &mut Report) -> Result<Vec<Record>, TodosError>
+{
+    // `task export` writes an array; older versions wrote one task per line
+    let tasks: Vec<(usize, Value)> = if content.trim_start().starts_with('[')
+    {
+        let tasks: Vec<Value> = serde_json::from_str(content)
+            .map_err(|error| TodosError::Invalid(format!("Invalid Taskwarrior JSON: {}", error)))?;
+        tasks.into_iter().enumerate().map(|(i, x)| (i + 1, x)).collect()
+    }
+    else
+    {
+        let mut tasks = Vec::new();
+        for (i, line) in content.lines().enumerate().filter(|(_, x)| !x.trim().is_empty())
+        {
+            let task = serde_json::from_str(line)
+                .map_err(|error| TodosError::Invalid(format!("Line {}: invalid Taskwarrior JSON: {}", i + 1, error)))?;
+            tasks.push((i + 1, task));
+        }
+        tasks
+    };
+
+    let mut records = Vec::new();
+    let mut projects: Vec<Option<String>> = Vec::new();
+    let mut dropped: BTreeMap<String, usize> = BTreeMap::new();
+
+    for (number, task) in tasks
+    {
+        let task = match task
+        {
+            Value::Object(task) => task,
+            _ => { return Err(TodosError::Invalid(format!("Task {} is not a JSON object.", number))); }
+        };
+        let text = |key: &str| task.get(key).and_then(|x| x.as_str());
+
+        let description = text("description")
+            .filter(|x| !x.trim().is_empty())
+            .ok_or_else(|| TodosError::Invalid(format!("Task {} has no description.", number)))?;
+        let uuid = text("uuid").map(String::from);
+        let mut record = Record::new(
+            uuid.clone().unwrap_or_else(|| format!("\n{}", number)),
+            number,
+            description.to_string()
+        );
+        record.uuid = uuid;
+
+        match text("status").unwrap_or("pending")
+        {
+            "pending" => {},
+            "waiting" => { record.node_type = NodeType::Condition; },
+            "recurring" => {
+                // the template; its instances are pending tasks of their own
+                report.warnings.push(format!(
+                    "Task {}: skipped the recurring template \"{}\"; its pending instances were imported.",
+                    number,
+                    description
+                ));
+                record.done = true;
+            },
+            _ => { record.done = true; }
+        }
+
+        for (key, value) in &task
+        {
+            match &key[..]
+            {
+                "uuid" | "description" | "status" => {},
+                "depends" => {
+                    // an array since Taskwarrior 2.6, a comma-separated
+                    // string before
+                    record.deps = match value
+                    {
+                        Value::Array(deps) => deps.iter().filter_map(|x| x.as_str()).map(String::from).collect(),
+                        Value::String(deps) => deps.split(',').filter(|x| !x.is_empty()).map(String::from).collect(),
+                        _ => Vec::new()
+                    };
+                },
+                "due" => {
+                    match value.as_str().and_then(Due::parse)
+                    {
+                        Some(due) => { record.due_date = Some(due.to_string()); },
+                        None => report.warnings.push(format!("Task {}: left out the due date {}, which isn't a valid date.", number, value))
+                    }
+                },
+                "wait" => {
+                    match value.as_str().and_then(Due::parse)
+                    {
+                        Some(until) => { record.deferred_until = Some(until.date()); },
+                        None => report.warnings.push(format!("Task {}: left out the wait date {}, which isn't a valid date.", number, value))
+                    }
+                },
+                "tags" => {
+                    record.tags = value.as_array().into_iter().flatten().filter_map(|x| x.as_str()).map(String::from).collect();
+                },
+                "priority" => {
+                    record.priority = match value.as_str()
+                    {
+                        Some("H") => Some('A'),
+                        Some("M") => Some('B'),
+                        Some("L") => Some('C'),
+                        _ => None
+                    };
+                },
+                "annotations" => {
+                    let notes: Vec<&str> = value.as_array().into_iter().flatten()
+                        .filter_map(|x| x.get("description").and_then(|x| x.as_str()))
+                        .collect();
+                    if !notes.is_empty()
+                    {
+                        record.notes = Some(notes.join("\n"));
+                    }
+                },
+                "todostype" => {
+                    record.node_type = NodeType::from_string(value.as_str().unwrap_or("")).unwrap_or(record.node_type);
+                },
+                "todosrecurrence" => {
+                    record.recurrence = value.as_str().and_then(Recurrence::parse);
+                    if record.recurrence.is_none()
+                    {
+                        report.warnings.push(format!("Task {}: left out the recurrence {}, which isn't a rule todos understands.", number, value));
+                    }
+                },
+                "todoscompleted" => {
+                    record.completed = value.as_str().unwrap_or("").split(',').filter(|x| !x.is_empty()).map(String::from).collect();
+                },
+                "todostime" => {
+                    for interval in value.as_str().unwrap_or("").split(',').filter(|x| !x.is_empty())
+                    {
+                        match Interval::parse(interval)
+                        {
+                            Some(interval) => record.time.push(interval),
+                            None => report.warnings.push(format!("Task {}: left out the tracked time {}, which isn't a valid interval.", number, interval))
+                        }
+                    }
+                },
+                "project" => {},
+                key if IGNORED.contains(&key) => {},
+                key => {
+                    if !record.done
+                    {
+                        *dropped.entry(key.to_string()).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        projects.push(text("project").map(String::from));
+        records.push(record);
+    }
+
+    report_dropped(report, dropped, "task", "tasks");
+
+    add_projects(&mut records, projects);
+    Ok(records)
+}
+
+/// Adds a goal for each project, holding the tasks of that project that
+/// nothing else in the file depends on.
+fn add_projects(records: &mut Vec<Record>, projects: Vec<Option<String>>)
+{
+    let depended_on: HashSet<String> = records.iter()
+        .filter(|x| !x.done)
+        .flat_map(|x| x.deps.iter().cloned())
+        .collect();
+
+    let mut goals: BTreeMap<String, Record> = BTreeMap::new();
+    for (record, project) in records.iter().zip(projects)
+    {
+        let project = match project
+        {
+            Some(project) if !record.done && !depended_on.contains(&record.key) => project,
+            _ => { continue; }
+        };
+
+        // the goal for each level of the project, `home`, then `home.garden`
+        let mut child = record.key.clone();
+        let mut path = project.as_str();
+        loop
+        {
+            let key = format!("project:{}", path);
+            let name = path.rsplit('.').next().unwrap();
+            let goal = goals.entry(key.clone()).or_insert_with(|| {
+                let mut goal = Record::new(key.clone(), record.line, name.to_string());
+                goal.node_type = NodeType::Goal;
+                goal
+            });
+            if goal.deps.contains(&child)
+            {
+                break;
+            }
+            goal.deps.push(child);
+            child = key;
+
+            match path.rsplit_once('.')
+            {
+                Some((parent, _)) => { path = parent; },
+                None => { break; }
+            }
+        }
+    }
+
+    records.extend(goals.into_values());
+}
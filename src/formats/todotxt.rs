@@ -0,0 +1,373 @@
+//! [todo.txt](https://github.com/todotxt/todo.txt): one task per line, with
+//! an optional `x` completion marker and `(A)` priority, `+project` and
+//! `@context` tokens, and `key:value` extensions.
+//!
+//! Projects and contexts are kept as tags. The DAG is written with `id:` on
+//! every task and `dep:` listing the ids it depends on, and everything else
+//! todos knows about a node gets its own extension (`due:`, `type:`, `tag:`,
+//! `note:`, `rec:`, `done:`, `t:`, the threshold date a deferred node shows
+//! up again, and `time:`, the intervals tracked as `start/end`), so
+//! exporting and importing again loses nothing. `rec:` is written like other
+//! todo.txt tools do, `+1w` for weekly and `3d` for three days after
+//! completion, when the rule is one of those, and as the rule `todos repeat`
+//! takes otherwise. Values are percent-encoded where they contain spaces or
+//! newlines, and so are the parts of a description that would be read as
+//! something else: a word starting with `+` or `@`, the `:` of a word like
+//! `due:friday`, and any whitespace but single spaces between words.
+
+use super::{Record, Report};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::dates::{Due, Interval, Recurrence};
+use crate::node::NodeType;
+
+use std::collections::BTreeSet;
+
+/// Extensions understood on import; any other `key:value` stays part of the
+/// description.
+const KEYS: [&str; 11] = ["id", "dep", "due", "type", "tag", "note", "pri", "rec", "done", "t", "time"];
+
+pub fn export(graph: &Graph, ids: &BTreeSet<usize>) -> String
+{
+    let mut out = String::new();
+    for id in ids
+    {
+        let node = &graph.nodes()[*id];
+        let mut parts: Vec<String> = Vec::new();
+
+        if let Some(priority) = node.priority
+        {
+            parts.push(format!("({})", priority));
+        }
+
+        // a description starting with something that looks like a marker
+        // would be misread, so the id goes first in that case
+        let first = node.description.split_whitespace().next().unwrap_or("");
+        let ambiguous = first == "x" || parse_priority(first).is_some() || is_date(first);
+        if ambiguous
+        {
+            parts.push(format!("id:{}", id));
+        }
+        parts.push(escape_description(&node.description));
+
+        for tag in &node.tags
+        {
+            if (tag.starts_with('+') || tag.starts_with('@')) && tag.len() > 1 && !tag.contains(char::is_whitespace)
+            {
+                parts.push(tag.clone());
+            }
+            else
+            {
+                parts.push(format!("tag:{}", encode(tag)));
+            }
+        }
+        if let Some(due_date) = &node.due_date
+        {
+            parts.push(format!("due:{}", encode(due_date)));
+        }
+        match node.node_type
+        {
+            NodeType::Goal => parts.push(String::from("type:goal")),
+            NodeType::Condition => parts.push(String::from("type:condition")),
+            NodeType::Task => {}
+        }
+        if let Some(notes) = &node.notes
+        {
+            parts.push(format!("note:{}", encode(notes)));
+        }
+        if let Some(recurrence) = &node.recurrence
+        {
+            parts.push(format!("rec:{}", write_recurrence(recurrence)));
+        }
+        if !node.completed.is_empty()
+        {
+            parts.push(format!("done:{}", node.completed.join(",")));
+        }
+        if let Some(until) = &node.deferred_until
+        {
+            parts.push(format!("t:{}", encode(until)));
+        }
+        if !node.time.is_empty()
+        {
+            let time: Vec<String> = node.time.iter().map(|x| encode(&x.to_string())).collect();
+            parts.push(format!("time:{}", time.join(",")));
+        }
+        if !ambiguous
+        {
+            parts.push(format!("id:{}", id));
+        }
+        let deps: Vec<String> = node.deps.iter().filter(|x| ids.contains(x)).map(|x| x.to_string()).collect();
+        if !deps.is_empty()
+        {
+            parts.push(format!("dep:{}", deps.join(",")));
+        }
+
+        out.push_str(&parts.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn parse(content: &str, report: &mut Report) -> Result<Vec<Record>, TodosError>
+{
+    let mut records = Vec::new();
+    let mut creation_dates = 0;
+
+    for (i, line) in content.lines().enumerate()
+    {
+        let mut tokens = line.split_whitespace().peekable();
+        if tokens.peek().is_none()
+        {
+            continue;
+        }
+
+        // keys must be unique, and no token can contain a newline
+        let mut record = Record::new(format!("\n{}", i + 1), i + 1, String::new());
+
+        if tokens.peek() == Some(&"x")
+        {
+            tokens.next();
+            record.done = true;
+            // completion date, then creation date
+            tokens.next_if(|x| is_date(x));
+        }
+        else if let Some(priority) = tokens.peek().and_then(|x| parse_priority(x))
+        {
+            tokens.next();
+            record.priority = Some(priority);
+        }
+        if tokens.next_if(|x| is_date(x)).is_some() && !record.done
+        {
+            creation_dates += 1;
+        }
+
+        let mut description: Vec<&str> = Vec::new();
+        for token in tokens
+        {
+            if token.len() > 1 && (token.starts_with('+') || token.starts_with('@'))
+            {
+                record.tags.push(token.to_string());
+                continue;
+            }
+
+            let (key, value) = match token.split_once(':')
+            {
+                Some((key, value)) if KEYS.contains(&key) && !value.is_empty() => (key, value),
+                _ => {
+                    description.push(token);
+                    continue;
+                }
+            };
+            match key
+            {
+                "id" => { record.key = value.to_string(); },
+                "dep" => { record.deps.extend(value.split(',').filter(|x| !x.is_empty()).map(String::from)); },
+                "due" => { record.due_date = Some(decode(value)); },
+                "tag" => { record.tags.push(decode(value)); },
+                "note" => { record.notes = Some(decode(value)); },
+                "rec" => {
+                    record.recurrence = read_recurrence(value);
+                    if record.recurrence.is_none()
+                    {
+                        report.warnings.push(format!(
+                            "Line {}: left out rec:{}, which isn't a rule todos understands.",
+                            i + 1,
+                            value
+                        ));
+                    }
+                },
+                "done" => { record.completed.extend(value.split(',').filter(|x| !x.is_empty()).map(String::from)); },
+                "t" => {
+                    record.deferred_until = Due::parse(&decode(value)).map(|x| x.date());
+                    if record.deferred_until.is_none()
+                    {
+                        report.warnings.push(format!(
+                            "Line {}: left out t:{}, which isn't a valid date.",
+                            i + 1,
+                            value
+                        ));
+                    }
+                },
+                "time" => {
+                    for interval in value.split(',').filter(|x| !x.is_empty())
+                    {
+                        match Interval::parse(&decode(interval))
+                        {
+                            Some(interval) => record.time.push(interval),
+                            None => report.warnings.push(format!(
+                                "Line {}: left out the tracked time {}, which isn't a valid interval.",
+                                i + 1,
+                                interval
+                            ))
+                        }
+                    }
+                },
+                // some tools move the priority of completed tasks here
+                "pri" => { record.priority = record.priority.or_else(|| parse_priority(&format!("({})", value))); },
+                _ => {
+                    record.node_type = match value
+                    {
+                        "goal" => NodeType::Goal,
+                        "condition" => NodeType::Condition,
+                        "task" => NodeType::Task,
+                        _ => {
+                            report.warnings.push(format!("Line {}: unknown type {}; imported as a task.", i + 1, value));
+                            NodeType::Task
+                        }
+                    };
+                }
+            }
+        }
+
+        if description.is_empty()
+        {
+            return Err(TodosError::Invalid(format!("Line {}: missing description.", i + 1)));
+        }
+        record.description = description.iter().map(|x| decode(x)).collect::<Vec<_>>().join(" ");
+        records.push(record);
+    }
+
+    if creation_dates > 0
+    {
+        report.warnings.push(format!("Dropped the creation dates of {} tasks, which todos doesn't keep.", creation_dates));
+    }
+    Ok(records)
+}
+
+fn write_recurrence(recurrence: &Recurrence) -> String
+{
+    match recurrence
+    {
+        Recurrence::Daily => String::from("+1d"),
+        Recurrence::Weekly(days) if days.is_empty() => String::from("+1w"),
+        Recurrence::AfterCompletion(days) => format!("{}d", days),
+        _ => encode(&recurrence.to_string())
+    }
+}
+
+/// Reads `rec:` as todo.txt tools write it: with a `+`, counted from the due
+/// date, and without, from completion.
+fn read_recurrence(value: &str) -> Option<Recurrence>
+{
+    let value = decode(value);
+    let (strict, period) = match value.strip_prefix('+')
+    {
+        Some(period) => (true, period),
+        None => (false, &value[..])
+    };
+    let unit = period.chars().last().unwrap_or(' ');
+    let count = period[..period.len() - unit.len_utf8().min(period.len())].parse::<u32>().ok().filter(|x| *x > 0);
+    match (strict, count, unit)
+    {
+        (true, Some(1), 'd') => Some(Recurrence::Daily),
+        (true, Some(1), 'w') => Some(Recurrence::Weekly(Vec::new())),
+        (false, Some(days), 'd') => Some(Recurrence::AfterCompletion(days)),
+        (false, Some(weeks), 'w') => Some(Recurrence::AfterCompletion(weeks * 7)),
+        _ => Recurrence::parse(&value)
+    }
+}
+
+fn parse_priority(token: &str) -> Option<char>
+{
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next())
+    {
+        (Some('('), Some(priority), Some(')'), None) if priority.is_ascii_uppercase() => Some(priority),
+        _ => None
+    }
+}
+
+/// Whether `token` is a `YYYY-MM-DD` date.
+fn is_date(token: &str) -> bool
+{
+    let bytes = token.as_bytes();
+    bytes.len() == 10 &&
+        bytes.iter().enumerate().all(|(i, x)| if i == 4 || i == 7 { *x == b'-' } else { x.is_ascii_digit() })
+}
+
+fn encode(value: &str) -> String
+{
+    let mut out = String::new();
+    for c in value.chars()
+    {
+        match c
+        {
+            '%' | ' ' | '\t' | '\n' | '\r' | ',' => out.push_str(&format!("%{:02X}", c as u32)),
+            _ => out.push(c)
+        }
+    }
+    out
+}
+
+/// Writes a description so its words read back as the description and
+/// nothing else.
+fn escape_description(description: &str) -> String
+{
+    let chars: Vec<char> = description.chars().collect();
+    let mut out = String::new();
+    let mut word_start = 0;
+    for (i, c) in chars.iter().copied().enumerate()
+    {
+        let prev = if i > 0 { chars.get(i - 1) } else { None };
+        let next = chars.get(i + 1);
+        let word = &out[word_start..];
+        let after_word = matches!(prev, Some(x) if !x.is_whitespace());
+        let before_word = matches!(next, Some(x) if !x.is_whitespace());
+
+        let escape = if c == ' ' && after_word && before_word
+        {
+            out.push(' ');
+            word_start = out.len();
+            continue;
+        }
+        else if c.is_whitespace()
+        {
+            true
+        }
+        else if c == '%'
+        {
+            // a lone % is left alone, as it can't be read as an escape
+            chars.get(i + 1..i + 3).map(|x| x.iter().all(char::is_ascii_hexdigit)).unwrap_or(false)
+        }
+        else
+        {
+            ((c == '+' || c == '@') && word.is_empty()) || (c == ':' && KEYS.contains(&word))
+        };
+
+        if escape
+        {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes()
+            {
+                out.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        else
+        {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn decode(value: &str) -> String
+{
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((first, tail)) = rest.split_first()
+    {
+        let hex = tail.get(..2).and_then(|x| std::str::from_utf8(x).ok()).and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (first, hex)
+        {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            },
+            _ => {
+                bytes.push(*first);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
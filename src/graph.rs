@@ -3,46 +3,158 @@ extern crate serde_json;
 
 use crate::node::*;
 use crate::config::Config;
+use crate::error::TodosError;
+use crate::dates::{self, Due, Interval, Recurrence};
 
-use std::fs;
+use crate::storage::{self, Changes, Lock, Storage};
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::result::Result;
-use std::io::prelude::*;
-use std::fs::File;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use std::path::Path;
+use std::fmt::Write;
 
+/// Version of the on-disk todos file format written by this build.
+///
+/// Bump this whenever the format changes and add a step to
+/// `JsonStorage::migrate` that upgrades files written with the previous
+/// version. The config file and SQLite databases have versions of their own,
+/// kept the same way.
+pub const SCHEMA_VERSION: u64 = 1;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Graph
 {
+    #[serde(default)]
+    version: u64,
     effective_root: Option<usize>,
     nodes: Vec<Node>,
 
     #[serde(skip_serializing, skip_deserializing)]
-    todos_file: PathBuf,
+    storage: Option<Box<dyn Storage>>,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    changes: Changes,
+
+    #[serde(skip_serializing, skip_deserializing)]
+    undo: Option<Undo>,
 
     #[serde(skip_serializing, skip_deserializing)]
     config: Config
 }
 
-#[allow(dead_code)]
+/// What a running transaction needs to put the graph back the way it was:
+/// the original of every node it touched, rather than a copy of the whole
+/// graph.
+///
+/// Changes recorded for saving are left alone when rolling back; saving a
+/// node that didn't actually change is harmless.
+struct Undo
+{
+    len: usize,
+    effective_root: Option<usize>,
+    originals: HashMap<usize, Node>
+}
+
 impl Graph
 {
-    pub fn load(todos_file: PathBuf, config: Config) ->  Graph
+    /// An empty graph that isn't saved anywhere until given a storage
+    /// backend with `set_storage`.
+    pub fn new(config: Config) -> Graph
+    {
+        Graph::from_parts(None, vec![]).with_config(config)
+    }
+
+    pub(crate) fn from_parts(effective_root: Option<usize>, nodes: Vec<Node>) -> Graph
+    {
+        Graph
+        {
+            version: SCHEMA_VERSION,
+            effective_root,
+            nodes,
+            storage: None,
+            changes: Changes::default(),
+            undo: None,
+            config: Config::default()
+        }
+    }
+
+    fn with_config(mut self, config: Config) -> Graph
+    {
+        self.config = config;
+        self
+    }
+
+    /// Loads the graph kept in `storage`, which is then used by `save`.
+    pub fn load(mut storage: Box<dyn Storage>, config: Config) -> Result<Graph, TodosError>
+    {
+        let mut graph = storage.load()?.with_config(config);
+        graph.storage = Some(storage);
+        Ok(graph)
+    }
+
+    /// Loads the todos file at `todos_file`, whichever backend wrote it.
+    pub fn open(todos_file: &Path, config: Config) -> Result<Graph, TodosError>
+    {
+        Graph::load(storage::open(todos_file)?, config)
+    }
+
+    /// Switches to a different storage backend. The next `save` writes the
+    /// whole graph to it.
+    pub fn set_storage(&mut self, storage: Box<dyn Storage>)
+    {
+        self.storage = Some(storage);
+        self.changes.all = true;
+    }
+
+    pub fn storage(&self) -> Option<&dyn Storage>
+    {
+        self.storage.as_deref()
+    }
+
+    /// Every change to an existing node goes through here, so that it is
+    /// saved and can be undone.
+    fn node_mut(&mut self, id: usize) -> &mut Node
+    {
+        if let Some(undo) = &mut self.undo
+        {
+            if id < undo.len && !undo.originals.contains_key(&id)
+            {
+                undo.originals.insert(id, self.nodes[id].clone());
+            }
+        }
+        self.changes.nodes.insert(id);
+        &mut self.nodes[id]
+    }
+
+    fn set_root(&mut self, effective_root: Option<usize>)
+    {
+        self.effective_root = effective_root;
+        self.changes.effective_root = true;
+    }
+
+    pub fn nodes(&self) -> &[Node]
     {
-        let mut graph: Graph = serde_json::from_str(
-            fs::read_to_string(&todos_file)
-                .unwrap()
-                .as_str()
-            ).unwrap();
+        &self.nodes
+    }
 
-        graph.config = config;
-        graph.todos_file = todos_file;
+    pub fn node(&self, id: usize) -> Result<&Node, TodosError>
+    {
+        self.nodes.get(id).ok_or(TodosError::NotFound(id))
+    }
 
-        graph
+    pub fn effective_root(&self) -> Option<usize>
+    {
+        self.effective_root
+    }
+
+    pub fn config(&self) -> &Config
+    {
+        &self.config
     }
 
-    pub fn validate(&self) -> Result<(), String>
+    pub fn validate(&self) -> Result<(), TodosError>
     {
         // check indices/ ids are all within bounds
         for i in 0..self.nodes.len()
@@ -50,77 +162,67 @@ impl Graph
             // id should be the index of the node
             if self.nodes[i].id != i
             {
-                return Err("Ids do not match the list structure.".to_string());
+                return Err(TodosError::IdMismatch { index: i, id: self.nodes[i].id });
             }
             // indices should all be within bounds
-            match self.validate_node(&self.nodes[i])
-            {
-                Err(message) => { return Err(message); },
-                _ => {}
-            }
+            self.validate_node(&self.nodes[i])?;
             //links should be double sided
             for p in &self.nodes[i].parents
             {
                 if !self.nodes[*p].deps.contains(&i)
                 {
-                    return Err(format!("One-sided dependency encountered: parent with id {} does not acknowledge the claimed child status of node with id {}", p, i).to_string());
+                    return Err(TodosError::OneSidedLink { parent: *p, child: i });
                 }
             }
             for p in &self.nodes[i].deps
             {
                 if !self.nodes[*p].parents.contains(&i)
                 {
-                    return Err(format!("One-sided dependency encountered: child with id {} does not acknowledge the claimed parental status of node with id {}", p, i).to_string());
+                    return Err(TodosError::OneSidedLink { parent: i, child: *p });
                 }
             }
         }
-        match self.check_topology()
-        {
-            Err(message) => { return Err(message) },
-            _ => {}
-        }
-        Ok(())
+        self.check_topology()
     }
-    fn validate_node(&self, n: &Node) -> Result<(), String>
+    fn validate_node(&self, n: &Node) -> Result<(), TodosError>
     {
-        for j in &n.deps
-        {
-            match self.nodes.get(*j)
-            {
-                None => { return Err(format!("Listed dependency {} for node with id {} not present in graph.", j, n.id ).to_string()); },
-                _ => {}
-            }
-        }
-        for j in &n.parents
+        for j in n.deps.iter().chain(n.parents.iter())
         {
-            match self.nodes.get(*j)
+            if self.nodes.get(*j).is_none()
             {
-                None => { return Err(format!("Listed parent {} for node with id {} not present in graph.", j, n.id ).to_string()); },
-                _ => {}
+                return Err(TodosError::DanglingLink { node: n.id, missing: *j });
             }
         }
-        return Ok(());
+        Ok(())
     }
 
-    pub fn add_node_to(&mut self, description: String, node_type: NodeType, to: Option<usize>) -> Result<usize, String>
+    pub fn add_node_to(&mut self, description: String, node_type: NodeType, to: Option<usize>) -> Result<usize, TodosError>
     {
         let n = Node
         {
-            id:0,
-            description: description,
-            node_type: node_type,
+            id: self.nodes.len(),
+            description,
+            node_type,
             due_date: None,
-            deps: Vec::<usize>::new(),
+            priority: None,
+            notes: None,
+            tags: Vec::new(),
+            uuid: None,
+            recurrence: None,
+            completed: Vec::new(),
+            deferred_until: None,
+            time: Vec::new(),
+            deps: Links::new(),
             parents: match to {
-                Some(val) => vec![val],
+                Some(val) => Links::from([val]),
                 None => {
                     if let Some(effective_root) = self.effective_root
                     {
-                        vec![effective_root]
+                        Links::from([effective_root])
                     }
                     else
                     {
-                        Vec::<usize>::new()
+                        Links::new()
                     }
                 }
             }
@@ -129,281 +231,573 @@ impl Graph
         self.add_node(n)
     }
 
-    pub fn add_node_above(&mut self, description: String, node_type: NodeType, above: usize) -> Result<usize, String>
+    pub fn add_node_above(&mut self, description: String, node_type: NodeType, above: usize) -> Result<usize, TodosError>
     {
-        let node_to_shift = &self.nodes[above];
-        let parents = node_to_shift.parents.clone();
+        let parents = self.node(above)?.parents.clone();
 
-        // unlink all references above node 'above'
-        for parent in &parents
-        {
-            match self.unlink(&parent, &above){
-                Ok(()) => {},
-                Err(message) => {
-                    panic!("{}", message);
-                }
+        self.transaction(|graph| {
+            // unlink all references above node 'above'
+            for parent in &parents
+            {
+                graph.unlink(parent, &above)?;
             }
-        }
 
-        let n = Node
-        {
-            id:0,
-            description: description,
-            node_type: node_type,
-            due_date: None,
-            deps: vec![above],
-            parents: parents
-        };
+            let n = Node
+            {
+                id: graph.nodes.len(),
+                description,
+                node_type,
+                due_date: None,
+                priority: None,
+                notes: None,
+                tags: Vec::new(),
+                uuid: None,
+                recurrence: None,
+                completed: Vec::new(),
+                deferred_until: None,
+                time: Vec::new(),
+                deps: Links::from([above]),
+                parents
+            };
 
-        let id = self.add_node(n)?;
-        self.link(&id, &above).map(|_x| id)
+            graph.add_node(n)
+        })
     }
 
 
-    fn add_node(&mut self, n: Node) -> Result<usize, String>
+    fn add_node(&mut self, n: Node) -> Result<usize, TodosError>
     {
-        let id_to_return: usize = self.nodes.len();
         // if it don't work, don't panic
-        match self.validate_node(&n)
-        {
-            Err(message) => {return Err(message);},
-            Ok(()) => {
-                let node_to_add = Node {
-                    id: self.nodes.len(),
-                    description: n.description,
-                    node_type: n.node_type,
-                    due_date: None,
-                    deps: n.deps,
-                    parents: n.parents
-                };
+        self.validate_node(&n)?;
 
-                // add other ends of links
-                for p in &node_to_add.parents
-                {
-                    if !self.nodes[*p].deps.contains(p)
-                    {
-                        self.nodes[*p].deps.push(id_to_return.clone());
-                    }
-                }
-                for p in &node_to_add.deps
+        let id = self.nodes.len();
+
+        // a new node can only close a loop if one of its children is
+        // already above one of its parents
+        for dep in &n.deps
+        {
+            for parent in &n.parents
+            {
+                if let Some(path) = self.find_path(*dep, *parent)
                 {
-                    if !self.nodes[*p].parents.contains(p)
-                    {
-                        self.nodes[*p].parents.push(id_to_return.clone());
-                    }
+                    let new_node = Node { id, ..n.clone() };
+                    let mut cycle = vec![new_node.clone()];
+                    cycle.extend(path.iter().map(|x| self.nodes[*x].clone()));
+                    cycle.push(new_node);
+                    return Err(TodosError::Cycle(cycle));
                 }
-                // insert node
-                self.nodes.push(node_to_add);
             }
         }
-        match self.validate()
+
+        // add other ends of links
+        for p in &n.parents
         {
-            Ok(()) => { return Ok(id_to_return); },
-            Err(_message) => {
-                match self.remove_node(self.nodes.len() - 1, false) {
-                    Ok(()) => {},
-                    Err(message) => panic!("{}", message)
-                }
-            } // try to remove last node
+            self.node_mut(*p).deps.insert(id);
         }
-        // but if it messes up your graph, then panic
-        match self.validate()
+        for p in &n.deps
         {
-            Ok(()) => { return Ok(id_to_return); },
-            Err(message) => { panic!("Added crappy node and couldn't properly get rid of it!!!\n Error: {}", message); }
+            self.node_mut(*p).parents.insert(id);
         }
+        // insert node
+        self.nodes.push(Node { id, ..n });
+        self.changes.nodes.insert(id);
+
+        Ok(id)
     }
 
 
-    pub fn remove_node(&mut self, index: usize, recurse: bool) -> Result<(), String>
+    pub fn remove_node(&mut self, index: usize, recurse: bool) -> Result<(), TodosError>
     {
         self.batch_remove(vec![index], recurse)
     }
 
-    pub fn batch_remove(&mut self, indices: Vec<usize>, recurse: bool) -> Result<(), String>
+    /// Removes the given nodes (and, if `recurse` is set, everything below
+    /// them). Nothing is removed unless every id is present.
+    ///
+    /// Ids stay contiguous: each removed node's id is taken over by the node
+    /// with the highest id, so only the links of the nodes involved change.
+    pub fn batch_remove(&mut self, indices: Vec<usize>, recurse: bool) -> Result<(), TodosError>
     {
-        let mut error = None;
-
         for index in indices.iter()
         {
-            match self.nodes.get(*index)
+            self.node(*index)?;
+        }
+
+        let mut doomed = BTreeSet::new();
+        let mut stack = indices;
+        while let Some(id) = stack.pop()
+        {
+            if doomed.insert(id) && recurse
             {
-                None => {
-                    let new_error_text = format!("Node with id {} not present in todos.\n", index);
-                    if let Some(msg) = error
-                    {
-                        error = Some(msg + &new_error_text[..]);
-                    }
-                    else
-                    {
-                        error = Some(new_error_text);
-                    }
-                },
-                _ => { self.inner_remove(index.clone(), recurse); }
+                stack.extend(self.nodes[id].deps.iter());
             }
-
         }
 
-        let invalid_val = self.nodes.len().clone();
-
-        //remove invalid nodes
-        self.nodes.retain(|x| x.id != invalid_val);
-
-        //update ids/indices
-        for i in 0..self.nodes.len()
+        // going from the highest id down means the node moved into a freed
+        // id is never one that is still waiting to be removed
+        for id in doomed.into_iter().rev()
         {
-            self.rename_node(&self.nodes[i].id.clone(), &i);
+            self.inner_remove(id);
         }
 
+        Ok(())
+    }
 
-        // but if it messes up your graph, then panic
-        if let Err(message) = self.validate()
+    /// Completes the given nodes, removing everything below them too, like
+    /// `todos complete`. A recurring node comes back: it is added again under
+    /// the same parents, with everything below it, due when its rule says
+    /// counting from `today`, and with `today` added to its `completed` days.
+    ///
+    /// Time tracked on the removed nodes is handed up to the nodes left above
+    /// them, so it still counts towards their goals.
+    ///
+    /// Returns the ids of the new instances.
+    pub fn complete(&mut self, ids: Vec<usize>, today: NaiveDate) -> Result<Vec<usize>, TodosError>
+    {
+        for id in ids.iter()
         {
-            panic!("{}", message);
+            self.node(*id)?;
         }
 
-        if let Some(msg) = error
+        let mut below = HashSet::new();
+        let mut stack: Vec<usize> = ids.iter().flat_map(|x| self.nodes[*x].deps.iter().copied()).collect();
+        while let Some(id) = stack.pop()
         {
-            Err(msg)
+            if below.insert(id)
+            {
+                stack.extend(self.nodes[id].deps.iter());
+            }
         }
-        else
+        let doomed: BTreeSet<usize> = ids.iter().chain(below.iter()).copied().collect();
+
+        // one completed along with something above it is done for good
+        let mut recurring = Vec::new();
+        for id in ids
         {
-            Ok(())
+            if self.nodes[id].recurrence.is_some() && !below.contains(&id) && !recurring.contains(&id)
+            {
+                recurring.push(id);
+            }
         }
+
+        self.transaction(|graph| {
+            let mut copies = Vec::new();
+            for id in recurring
+            {
+                copies.push(graph.copy_instance(id, today)?);
+            }
+
+            // work out where the copies end up as removing moves nodes around
+            let mut positions: Vec<usize> = (0..graph.nodes.len()).collect();
+            for id in doomed.iter().rev()
+            {
+                positions.swap_remove(*id);
+            }
+            graph.hand_up_time(&doomed);
+            graph.batch_remove(doomed.into_iter().collect(), false)?;
+
+            Ok(copies.iter().map(|x| positions.iter().position(|y| y == x).unwrap()).collect())
+        })
     }
 
-    fn rename_node(&mut self, old_id: &usize, new_id: &usize)
+    /// Adds the next instance of the recurring node `id`, with a copy of
+    /// everything below it.
+    fn copy_instance(&mut self, id: usize, today: NaiveDate) -> Result<usize, TodosError>
     {
-        for node in &mut self.nodes
+        let mut subtree = vec![id];
+        let mut i = 0;
+        while i < subtree.len()
         {
-            if node.id == *old_id
+            for child in &self.nodes[subtree[i]].deps
             {
-                node.id = new_id.clone();
+                if !subtree.contains(child)
+                {
+                    subtree.push(*child);
+                }
             }
-
-            node.parents = node.parents.iter().map(|x| { if x == old_id { new_id.clone() } else { x.clone() } }).collect();
-            node.deps = node.deps.iter().map(|x| { if x == old_id { new_id.clone() } else { x.clone() } }).collect();
+            i += 1;
         }
-    }
 
-    fn inner_remove(&mut self, index: usize, recurse: bool)
-    {
+        let mut copies = HashMap::new();
+        for old in &subtree
+        {
+            let node = Node
+            {
+                id: self.nodes.len(),
+                uuid: None,
+                time: Vec::new(),
+                parents: if *old == id { self.nodes[id].parents.clone() } else { Links::new() },
+                deps: Links::new(),
+                ..self.nodes[*old].clone()
+            };
+            copies.insert(*old, self.add_node(node)?);
+        }
+        for old in &subtree
+        {
+            for child in self.nodes[*old].deps.clone()
+            {
+                self.link(&copies[old], &copies[&child])?;
+            }
+        }
 
-        let to_remove:Vec<usize> = self.nodes[index].deps.clone();
+        let copy = copies[&id];
+        let old_due = self.nodes[id].due_date.as_deref().and_then(Due::parse);
+        let due = self.nodes[id].recurrence.as_ref().unwrap().next(old_due, today);
+        self.node_mut(copy).completed.push(today.format("%Y-%m-%d").to_string());
+        self.node_mut(copy).due_date = Some(due.to_string());
 
-        if recurse
+        // what's below keeps its due dates, and everything its deferral,
+        // relative to the node's due date
+        if let Some(old_due) = old_due
         {
-            for node in to_remove
+            let shift = due.date() - old_due.date();
+            for old in &subtree
             {
-                self.inner_remove(node, recurse);
+                let shifted = match self.nodes[*old].due_date.as_deref().and_then(Due::parse)
+                {
+                    Some(_) if *old == id => None,
+                    Some(Due::Date(date)) => Some(Due::Date(date + shift)),
+                    Some(Due::Time(time)) => Some(Due::Time(time + shift)),
+                    None => None
+                };
+                if let Some(shifted) = shifted
+                {
+                    self.node_mut(copies[old]).due_date = Some(shifted.to_string());
+                }
+                if let Some(until) = self.nodes[*old].deferred_until.as_deref().and_then(Due::parse)
+                {
+                    self.node_mut(copies[old]).deferred_until = Some(Due::Date(until.date() + shift).to_string());
+                }
             }
         }
+        Ok(copy)
+    }
 
-        //remove refs to this node
-        for node in &mut self.nodes
+    /// Gives the time tracked on the `doomed` nodes to the nearest nodes
+    /// above them that aren't, stopping the timer if it runs on one.
+    fn hand_up_time(&mut self, doomed: &BTreeSet<usize>)
+    {
+        if self.running().map(|x| doomed.contains(&x)).unwrap_or(false)
         {
-            node.parents.retain(|&x| x != index);
-            node.deps.retain(|&x| x != index);
+            self.stop_timer(Utc::now()).unwrap();
         }
 
-        // mark node for deletion
-        self.nodes[index].id = self.nodes.len();
+        for id in doomed
+        {
+            if self.nodes[*id].time.is_empty()
+            {
+                continue;
+            }
+
+            let mut keepers = Vec::new();
+            let mut seen = HashSet::new();
+            let mut stack: Vec<usize> = self.nodes[*id].parents.iter().copied().collect();
+            while let Some(parent) = stack.pop()
+            {
+                if !seen.insert(parent)
+                {
+                    continue;
+                }
+                if doomed.contains(&parent)
+                {
+                    stack.extend(self.nodes[parent].parents.iter());
+                }
+                else
+                {
+                    keepers.push(parent);
+                }
+            }
+
+            let time = self.nodes[*id].time.clone();
+            for keeper in keepers
+            {
+                let node = self.node_mut(keeper);
+                for interval in &time
+                {
+                    // handed up along several paths, it is still kept once
+                    if !node.time.contains(interval)
+                    {
+                        node.time.push(interval.clone());
+                    }
+                }
+                node.time.sort_by(|a, b| a.start.cmp(&b.start));
+            }
+        }
     }
 
-    pub fn check_topology(&self) -> Result<(), String>
+    fn inner_remove(&mut self, index: usize)
     {
-        let mut g = self.clone();
-        let mut out_edges = Vec::<usize>::new();
-        let mut orphans = Vec::<usize>::new();
+        //remove refs to this node
+        for parent in self.nodes[index].parents.clone()
+        {
+            self.node_mut(parent).deps.shift_remove(&index);
+        }
+        for child in self.nodes[index].deps.clone()
+        {
+            self.node_mut(child).parents.shift_remove(&index);
+        }
 
-        for node in &g.nodes
+        // move the last node into the freed id
+        let last = self.nodes.len() - 1;
+        self.node_mut(index);
+        if index != last
         {
-            if node.parents.len() == 0
+            self.node_mut(last);
+            for parent in self.nodes[last].parents.clone()
             {
-                orphans.push(node.id);
+                rename_link(&mut self.node_mut(parent).deps, last, index);
             }
+            for child in self.nodes[last].deps.clone()
+            {
+                rename_link(&mut self.node_mut(child).parents, last, index);
+            }
+        }
+        self.nodes.swap_remove(index);
+        if index != last
+        {
+            self.nodes[index].id = index;
+        }
+
+        if self.effective_root == Some(index)
+        {
+            self.set_root(None);
         }
+        else if self.effective_root == Some(last)
+        {
+            self.set_root(Some(index));
+        }
+    }
+
+    pub fn check_topology(&self) -> Result<(), TodosError>
+    {
+        // Kahn's algorithm: repeatedly strip nodes whose parents have all
+        // been stripped. Anything left over is on or below a cycle.
+        let mut remaining_parents: Vec<usize> = self.nodes.iter().map(|x| x.parents.len()).collect();
+        let mut orphans: Vec<usize> = self.nodes.iter().filter(|x| x.parents.is_empty()).map(|x| x.id).collect();
 
         while let Some(top) = orphans.pop()
         {
-            out_edges.push(top.clone());
-            let children = g.nodes[top].deps.clone();
-            for child in children
+            for child in &self.nodes[top].deps
             {
-                g.unlink(&top, &child).unwrap();
-                if g.nodes[child].parents.is_empty()
+                remaining_parents[*child] -= 1;
+                if remaining_parents[*child] == 0
                 {
-                    orphans.push(child);
+                    orphans.push(*child);
                 }
             }
         }
 
-        for node in &g.nodes
+        let start = match remaining_parents.iter().position(|x| *x != 0)
         {
-            if !node.parents.is_empty() || !node.deps.is_empty()
+            Some(start) => start,
+            None => { return Ok(()); }
+        };
+
+        // every leftover node has a leftover parent, so walking up through
+        // leftover parents must eventually come back around
+        let mut walk = vec![start];
+        loop
+        {
+            let current = *walk.last().unwrap();
+            let parent = *self.nodes[current].parents
+                .iter()
+                .find(|x| remaining_parents[**x] != 0)
+                .unwrap();
+
+            if let Some(position) = walk.iter().position(|x| *x == parent)
             {
-                return Err("Idiot. Haven't you heard of a DAG above?".to_string());
+                let mut cycle = walk.split_off(position);
+                cycle.reverse();
+                cycle.push(*cycle.first().unwrap());
+                return Err(self.cycle_error(&cycle));
             }
+            walk.push(parent);
         }
-
-        Ok(())
     }
-    pub fn link(&mut self, parent: &usize, child: &usize) -> Result<(), String>
+
+    /// Finds a chain of links leading from `from` down to `to`, both ends
+    /// included.
+    ///
+    /// Only the nodes below `from` are visited.
+    pub fn find_path(&self, from: usize, to: usize) -> Option<Vec<usize>>
     {
-        match self.nodes.get(*parent)
+        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+
+        while let Some(current) = queue.pop_front()
         {
-            None => { return Err(format!("Parent node {} doesn't exist.", parent).to_string()); },
-            _ => {}
+            if current == to
+            {
+                let mut path = vec![to];
+                let mut step = to;
+                while step != from
+                {
+                    step = came_from[&step];
+                    path.push(step);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for child in &self.nodes[current].deps
+            {
+                if *child != from && !came_from.contains_key(child)
+                {
+                    came_from.insert(*child, current);
+                    queue.push_back(*child);
+                }
+            }
         }
-        match self.nodes.get(*child)
+
+        None
+    }
+
+    fn cycle_error(&self, cycle: &[usize]) -> TodosError
+    {
+        TodosError::Cycle(cycle.iter().map(|id| self.nodes[*id].clone()).collect())
+    }
+
+    pub fn link(&mut self, parent: &usize, child: &usize) -> Result<(), TodosError>
+    {
+        self.node(*parent)?;
+        self.node(*child)?;
+        // refuse before touching anything if the new link would close a loop
+        if let Some(mut cycle) = self.find_path(*child, *parent)
         {
-            None => { return Err(format!("Child node {} doesn't exist.", child).to_string()); },
-            _ => {}
+            cycle.insert(0, *parent);
+            return Err(self.cycle_error(&cycle));
         }
-        if !self.nodes[*parent].deps.contains(child)
+        self.node_mut(*parent).deps.insert(*child);
+        self.node_mut(*child).parents.insert(*parent);
+        Ok(())
+    }
+    pub fn unlink(&mut self, parent: &usize, child: &usize) -> Result<(), TodosError>
+    {
+        self.node(*parent)?;
+        self.node(*child)?;
+        self.node_mut(*parent).deps.shift_remove(child);
+        self.node_mut(*child).parents.shift_remove(parent);
+        Ok(())
+    }
+
+    /// Runs `change` against the graph, putting everything back the way it
+    /// was if it fails. Transactions started from within `change` are part
+    /// of this one.
+    pub fn transaction<T, F>(&mut self, change: F) -> Result<T, TodosError>
+    where
+        F: FnOnce(&mut Graph) -> Result<T, TodosError>
+    {
+        if self.undo.is_some()
         {
-            self.nodes[*parent].deps.push(child.clone());
+            return change(self);
         }
-        if !self.nodes[*child].parents.contains(parent)
+
+        self.undo = Some(Undo {
+            len: self.nodes.len(),
+            effective_root: self.effective_root,
+            originals: HashMap::new()
+        });
+
+        let result = change(self);
+        let undo = self.undo.take().unwrap();
+
+        if result.is_err()
         {
-            self.nodes[*child].parents.push(parent.clone());
+            self.nodes.truncate(undo.len);
+            let mut originals = undo.originals;
+            // anything removed was touched (and so saved) on the way out
+            while self.nodes.len() < undo.len
+            {
+                let id = self.nodes.len();
+                self.nodes.push(originals.remove(&id).unwrap());
+            }
+            for (id, node) in originals
+            {
+                self.nodes[id] = node;
+            }
+            self.effective_root = undo.effective_root;
         }
-        self.validate()
+
+        result
     }
-    pub fn unlink(&mut self, parent: &usize, child: &usize) -> Result<(), String>
+
+    /// Replaces all of a node's parents with `to`, or makes it a top-level
+    /// node if `to` is `None`.
+    pub fn move_node(&mut self, id: usize, to: Option<usize>) -> Result<(), TodosError>
     {
-        match self.nodes.get(*parent)
-        {
-            None => { return Err(format!("Parent node {} doesn't exist.", parent).to_string()); },
-            _ => {}
-        }
-        match self.nodes.get(*child)
+        self.transaction(|graph| {
+            let parents = graph.node(id)?.parents.clone();
+
+            for parent in &parents
+            {
+                graph.unlink(parent, &id)?;
+            }
+
+            if let Some(to) = to
+            {
+                graph.link(&to, &id)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Inserts a node between `above` and its parents: the node takes over
+    /// the parents of `above` (instead of its own) and becomes the only
+    /// parent of `above`.
+    pub fn move_node_above(&mut self, id: usize, above: usize) -> Result<(), TodosError>
+    {
+        if id == above
         {
-            None => { return Err(format!("Child node {} doesn't exist.", child).to_string()); },
-            _ => {}
+            return Err(TodosError::Invalid(format!("Node {} can't be moved above itself.", id)));
         }
-        self.nodes[*parent].deps.retain( |x| *x != *child);
-        self.nodes[*child].parents.retain( |x| *x != *parent);
-        Ok(())
+
+        self.transaction(|graph| {
+            let parents = graph.node(id)?.parents.clone();
+            let new_parents = graph.node(above)?.parents.clone();
+
+            for parent in &parents
+            {
+                graph.unlink(parent, &id)?;
+            }
+            for parent in &new_parents
+            {
+                graph.unlink(parent, &above)?;
+            }
+            for parent in new_parents.iter().filter(|x| **x != id)
+            {
+                graph.link(parent, &id)?;
+            }
+
+            graph.link(&id, &above)
+        })
     }
 
     pub fn todos(&self, overwhelm: bool)
     {
+        print!("{}", self.render_todos(overwhelm));
+    }
+
+    /// What `todos` prints: everything under the effective root.
+    pub fn render_todos(&self, overwhelm: bool) -> String
+    {
+        let mut out = String::new();
         if let Some(root) = self.effective_root
         {
             if self.nodes.get(root).is_none()
             {
-                println!("\
+                out.push_str("\
                     Effective root has been deleted; please select a new \
                     effective root.\
                     \n\ne.x.\
                     \ntodos use 12\
                     \nor\
-                    \ntodos use root\
+                    \ntodos use root\n\
                 ");
-                return;
+                return out;
             }
-            self.show(&root, 0, overwhelm, Some(root)).unwrap();
+            self.render_show(&mut out, &root, 0, overwhelm, Some(root)).unwrap();
         }
         else
         {
@@ -411,40 +805,139 @@ impl Graph
             {
                 if node.parents.is_empty()
                 {
-                    self.show(&node.id, 0, overwhelm, None).unwrap();
+                    self.render_show(&mut out, &node.id, 0, overwhelm, None).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    /// The leaves `todos` would show under `under` (or the effective root), not
+    /// counting conditions and empty goals, which there is nothing to do about.
+    pub fn actionable_leaves(&self, under: Option<usize>) -> Result<Vec<usize>, TodosError>
+    {
+        let start = under.or(self.effective_root);
+        let mut stack: Vec<usize> = match start
+        {
+            Some(start) => vec![self.node(start)?.id],
+            None => self.nodes.iter().filter(|x| x.parents.is_empty()).map(|x| x.id).rev().collect()
+        };
+
+        let config = &self.config;
+        let today = chrono::Local::now().date_naive();
+        let mut seen = HashSet::new();
+        let mut leaves = Vec::new();
+        while let Some(id) = stack.pop()
+        {
+            if !seen.insert(id)
+            {
+                continue;
+            }
+            let node = &self.nodes[id];
+            if node.is_deferred(today) && Some(id) != start
+            {
+                continue;
+            }
+            if node.deps.is_empty()
+            {
+                if node.node_type == NodeType::Task
+                {
+                    leaves.push(id);
                 }
+                continue;
+            }
+
+            let hidden = config.hide_backlog_items &&
+                node.node_type == NodeType::Goal &&
+                node.description == config.backlog_name &&
+                Some(id) != start;
+            if !hidden
+            {
+                stack.extend(node.deps.iter().rev());
             }
         }
+        Ok(leaves)
+    }
+
+    /// Ids of the nodes whose description, notes or tags contain `query`,
+    /// ignoring case.
+    pub fn search(&self, query: &str) -> Vec<usize>
+    {
+        let query = query.to_lowercase();
+        self.nodes
+            .iter()
+            .filter(|node| {
+                node.description.to_lowercase().contains(&query) ||
+                    node.notes.as_ref().map(|x| x.to_lowercase().contains(&query)).unwrap_or(false) ||
+                    node.tags.iter().any(|x| x.to_lowercase().contains(&query))
+            })
+            .map(|node| node.id)
+            .collect()
     }
 
-    pub fn set_effective_root(&mut self, node_id: Option<usize>) -> Result<(), String>
+    /// Every node above `id`, nearest first.
+    pub fn ancestors(&self, id: usize) -> Result<Vec<usize>, TodosError>
     {
-        if let Some(ref id) = node_id
+        let mut queue: VecDeque<usize> = self.node(id)?.parents.iter().copied().collect();
+        let mut seen = HashSet::new();
+        let mut ancestors = Vec::new();
+        while let Some(id) = queue.pop_front()
         {
-            if let None = self.nodes.get(*id)
+            if seen.insert(id)
             {
-                return Err(format!("Node with id {} not present in todos.", id));
+                ancestors.push(id);
+                queue.extend(self.nodes[id].parents.iter());
             }
         }
+        Ok(ancestors)
+    }
+
+    pub fn set_effective_root(&mut self, node_id: Option<usize>) -> Result<(), TodosError>
+    {
+        if let Some(id) = node_id
+        {
+            self.node(id)?;
+        }
+
+        self.set_root(node_id);
+        Ok(())
+    }
 
-        self.effective_root = node_id;
+    pub fn show(&self, parent: &usize, level: u128, overwhelm: bool, started_from: Option<usize>) -> Result<(), TodosError>
+    {
+        let mut out = String::new();
+        self.render_show(&mut out, parent, level, overwhelm, started_from)?;
+        print!("{}", out);
         Ok(())
     }
 
-    pub fn show(&self, parent: &usize, mut level: u128, overwhelm: bool, started_from: Option<usize>) -> Result<(), String>
+    /// Appends what `show` would print to `out`.
+    pub fn render_show(&self, out: &mut String, parent: &usize, level: u128, overwhelm: bool, started_from: Option<usize>) -> Result<(), TodosError>
     {
-        let node = self.nodes.get(*parent).ok_or( format!("Node with id {} not present in todos.", parent))?;
+        self.walk_show(parent, level, overwhelm, started_from, &mut |node, level| self.render_node(out, node, level))
+    }
+
+    /// Calls `visit` with each node `show` would print and its indentation,
+    /// in order, for views that print nodes their own way.
+    pub fn walk_show(&self, parent: &usize, mut level: u128, overwhelm: bool, started_from: Option<usize>, visit: &mut dyn FnMut(&Node, u128)) -> Result<(), TodosError>
+    {
+        let node = self.node(*parent)?;
+
+        if
+            !overwhelm &&
+            started_from != Some(*parent) &&
+            node.is_deferred(chrono::Local::now().date_naive())
+        {
+            // deferred nodes are hidden, along with everything below them
+            return Ok(());
+        }
 
         if
             overwhelm || // print everything if overwhelming the user
             node.node_type == NodeType::Goal || // always print goals
             node.deps.is_empty() // always print leaves
         {
-            node.print(
-                &self.config.goal_color,
-                &self.config.condition_color,
-                &self.config.task_color,
-                level);
+            visit(node, level);
 
             // if the node was printed, then we increase the indentation for the children
             level += 1;
@@ -464,33 +957,337 @@ impl Graph
 
         for child in &node.deps
         {
-            self.show(child, level, overwhelm, started_from).unwrap();
+            self.walk_show(child, level, overwhelm, started_from, visit)?;
+        }
+
+        Ok(())
+
+    }
+    pub fn relabel(&mut self, id: usize, new_description: String) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).description = new_description;
+        Ok(())
+    }
+
+    pub fn retype(&mut self, id: usize, node_type: NodeType) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).node_type = node_type;
+        Ok(())
+    }
+
+    pub fn set_due_date(&mut self, id: usize, due_date: Option<String>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).due_date = due_date;
+        Ok(())
+    }
+
+    /// Sets a node's priority, `A` (highest) to `Z`.
+    pub fn set_priority(&mut self, id: usize, priority: Option<char>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        if let Some(priority) = priority.filter(|x| !x.is_ascii_uppercase())
+        {
+            return Err(TodosError::Invalid(format!("Priority {} should be a letter from A to Z.", priority)));
+        }
+        self.node_mut(id).priority = priority;
+        Ok(())
+    }
+
+    /// Replaces a node's notes. Blank notes are removed.
+    pub fn set_notes(&mut self, id: usize, notes: Option<String>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).notes = notes.filter(|x| !x.trim().is_empty());
+        Ok(())
+    }
+
+    pub fn set_uuid(&mut self, id: usize, uuid: Option<String>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).uuid = uuid;
+        Ok(())
+    }
+
+    /// Hides a node, and everything below it, until `until`; `None` shows it
+    /// again.
+    pub fn defer(&mut self, id: usize, until: Option<NaiveDate>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).deferred_until = until.map(|x| x.format("%Y-%m-%d").to_string());
+        Ok(())
+    }
+
+    /// Ids of the nodes still deferred on `today`, soonest back first.
+    pub fn deferred(&self, today: NaiveDate) -> Vec<usize>
+    {
+        let mut deferred: Vec<&Node> = self.nodes.iter().filter(|x| x.is_deferred(today)).collect();
+        deferred.sort_by(|a, b| a.deferred_until.cmp(&b.deferred_until));
+        deferred.iter().map(|x| x.id).collect()
+    }
+
+    /// What `todos deferred` prints: each deferred node with the day it
+    /// comes back.
+    pub fn render_deferred(&self, today: NaiveDate) -> String
+    {
+        let mut out = String::new();
+        for id in self.deferred(today)
+        {
+            let node = &self.nodes[id];
+            write!(out, "{}  ", node.deferred_until.as_deref().unwrap()).unwrap();
+            self.render_node(&mut out, node, 0);
+        }
+        out
+    }
+
+    /// The node the timer is running on, if any.
+    pub fn running(&self) -> Option<usize>
+    {
+        self.nodes.iter().find(|x| x.timer().is_some()).map(|x| x.id)
+    }
+
+    /// Starts timing `id` at `now`, stopping the timer first if it runs on
+    /// another node. Returns the node it was stopped on.
+    pub fn start_timer(&mut self, id: usize, now: DateTime<Utc>) -> Result<Option<usize>, TodosError>
+    {
+        self.node(id)?;
+        let stopped = match self.running()
+        {
+            Some(running) if running == id => {
+                return Err(TodosError::Invalid(format!("The timer is already running on node {}.", id)));
+            },
+            Some(_) => Some(self.stop_timer(now)?.0),
+            None => None
+        };
+        self.node_mut(id).time.push(Interval::starting(now));
+        Ok(stopped)
+    }
+
+    /// Stops the timer at `now`. Returns the node it was running on and how
+    /// long it ran.
+    pub fn stop_timer(&mut self, now: DateTime<Utc>) -> Result<(usize, Duration), TodosError>
+    {
+        let id = self.running().ok_or_else(|| TodosError::Invalid(String::from("No timer is running.")))?;
+        let interval = self.node_mut(id).time.iter_mut().find(|x| x.end.is_none()).unwrap();
+        interval.end = Some(Due::Time(now).to_string());
+        Ok((id, interval.length(None, now)))
+    }
+
+    /// The intervals tracked on `id` and everything below it, each once: one
+    /// handed up to several nodes is the same start and end on each.
+    fn intervals_below(&self, id: usize) -> Vec<&Interval>
+    {
+        let mut seen = HashSet::new();
+        let mut unique = HashSet::new();
+        let mut intervals = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop()
+        {
+            if !seen.insert(id)
+            {
+                continue;
+            }
+            for interval in &self.nodes[id].time
+            {
+                if unique.insert(interval)
+                {
+                    intervals.push(interval);
+                }
+            }
+            stack.extend(self.nodes[id].deps.iter());
+        }
+        intervals
+    }
+
+    /// The time tracked after `since` on `id` and everything below it,
+    /// counting the running timer up to `now`.
+    pub fn tracked(&self, id: usize, since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<Duration, TodosError>
+    {
+        self.node(id)?;
+        Ok(self.intervals_below(id).iter().fold(Duration::zero(), |total, x| total + x.length(since, now)))
+    }
+
+    /// What the default view starts with while the timer runs.
+    pub fn render_timer(&self, now: DateTime<Utc>) -> Option<String>
+    {
+        let node = &self.nodes[self.running()?];
+        let elapsed = node.timer().unwrap().length(None, now);
+        Some(format!("\x1B[1mTimer running\x1B[00m for {} on {}\n", dates::format_duration(elapsed), node.label()))
+    }
+
+    /// What `todos report time` prints: the time tracked after `since` under
+    /// each goal, most first, then the time not under any goal and the
+    /// total. Empty if nothing was tracked.
+    pub fn render_time_report(&self, since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String
+    {
+        let sum = |intervals: &[&Interval]| intervals.iter().fold(Duration::zero(), |total, x| total + x.length(since, now));
+
+        let mut goals: Vec<(Duration, &Node)> = self.nodes
+            .iter()
+            .filter(|x| x.node_type == NodeType::Goal)
+            .map(|x| (sum(&self.intervals_below(x.id)), x))
+            .filter(|(tracked, _)| *tracked > Duration::zero())
+            .collect();
+        goals.sort_by_key(|x| std::cmp::Reverse(x.0));
+
+        let mut unique = HashSet::new();
+        let mut all = Vec::new();
+        let mut loose = Vec::new();
+        for node in &self.nodes
+        {
+            let under_goal = node.node_type == NodeType::Goal ||
+                self.ancestors(node.id).unwrap().iter().any(|x| self.nodes[*x].node_type == NodeType::Goal);
+            for interval in &node.time
+            {
+                if unique.insert(interval)
+                {
+                    all.push(interval);
+                    if !under_goal
+                    {
+                        loose.push(interval);
+                    }
+                }
+            }
+        }
+        let total = sum(&all);
+        if total == Duration::zero()
+        {
+            return String::new();
+        }
+
+        let mut out = String::new();
+        for (tracked, goal) in goals
+        {
+            write!(out, "{:>8}  ", dates::format_duration(tracked)).unwrap();
+            self.render_node(&mut out, goal, 0);
+        }
+        let loose = sum(&loose);
+        if loose > Duration::zero()
+        {
+            writeln!(out, "{:>8}  not under any goal", dates::format_duration(loose)).unwrap();
         }
+        writeln!(out, "{:>8}  in total", dates::format_duration(total)).unwrap();
+        out
+    }
+
+    pub fn set_recurrence(&mut self, id: usize, recurrence: Option<Recurrence>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).recurrence = recurrence;
+        Ok(())
+    }
+
+    /// Replaces the days earlier instances of a recurring node were
+    /// completed on, oldest first.
+    pub fn set_completed(&mut self, id: usize, completed: Vec<String>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).completed = completed;
+        Ok(())
+    }
 
+    /// Replaces the time tracked on a node.
+    pub fn set_time(&mut self, id: usize, time: Vec<Interval>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        self.node_mut(id).time = time;
         Ok(())
+    }
 
+    /// Adds tags to a node, skipping any it already has.
+    pub fn tag(&mut self, id: usize, tags: Vec<String>) -> Result<(), TodosError>
+    {
+        self.node(id)?;
+        let node = self.node_mut(id);
+        for tag in tags
+        {
+            if !node.tags.contains(&tag)
+            {
+                node.tags.push(tag);
+            }
+        }
+        Ok(())
     }
-    pub fn relabel(&mut self, id: usize, new_description: String) -> Result<(), String>
+
+    pub fn untag(&mut self, id: usize, tags: &[String]) -> Result<(), TodosError>
     {
-        let node = self.nodes.get_mut(id).ok_or( format!("Node with id {} not present in todos.", id))?;
-        node.description = new_description;
+        self.node(id)?;
+        self.node_mut(id).tags.retain(|x| !tags.contains(x));
         Ok(())
     }
-    pub fn save(&self)
+
+    /// Writes any changes through the storage backend. Does nothing for a
+    /// graph without one.
+    pub fn save(&mut self) -> Result<(), TodosError>
     {
-        let mut f = match File::create(&self.todos_file) {
-            Ok(file) => file,
-            Err(_error) => {panic!("Unable to open file")}
+        let mut storage = match self.storage.take()
+        {
+            Some(storage) => storage,
+            None => { return Ok(()); }
         };
 
-        write!(f, "{}", serde_json::to_string(&self).unwrap()).unwrap();
+        let result = storage.save(self, &self.changes);
+        self.storage = Some(storage);
+
+        if result.is_ok()
+        {
+            self.changes = Changes::default();
+        }
+        result
     }
 
-    pub fn print_node(&self, id: usize, level: u128) -> Result<(), String>
+    /// Throws away the graph in memory, unsaved changes included, and loads
+    /// it again from its storage backend, to pick up changes made by other
+    /// processes. Does nothing for a graph without one.
+    pub fn reload(&mut self) -> Result<(), TodosError>
     {
-        let node = self.nodes.get(id).ok_or(
-            format!("Node {} not found.", id)
-        )?;
+        let mut storage = match self.storage.take()
+        {
+            Some(storage) => storage,
+            None => { return Ok(()); }
+        };
+
+        match storage.load()
+        {
+            Ok(graph) => {
+                let config = std::mem::take(&mut self.config);
+                *self = graph.with_config(config);
+                self.storage = Some(storage);
+                Ok(())
+            },
+            Err(error) => {
+                self.storage = Some(storage);
+                Err(error)
+            }
+        }
+    }
+
+    /// Makes `change` to the latest version of the todos file and saves it,
+    /// holding the file's lock throughout so no other process changes it in
+    /// between. For long-running processes, like `todos serve`, that share
+    /// the file with others.
+    pub fn locked<T, F>(&mut self, change: F) -> Result<T, TodosError>
+    where
+        F: FnOnce(&mut Graph) -> T
+    {
+        let path = match &self.storage
+        {
+            Some(storage) => storage.location().to_path_buf(),
+            None => { return Ok(change(self)); }
+        };
+
+        let _lock = Lock::acquire(&path)?;
+        self.reload()?;
+        let result = change(self);
+        self.save()?;
+        Ok(result)
+    }
+
+    pub fn print_node(&self, id: usize, level: u128) -> Result<(), TodosError>
+    {
+        let node = self.node(id)?;
 
         node.print(
             &self.config.goal_color,
@@ -501,4 +1298,90 @@ impl Graph
 
         Ok(())
     }
+
+    /// Everything known about a node, as printed by `todos show <id>`.
+    pub fn render_details(&self, id: usize) -> Result<String, TodosError>
+    {
+        let node = self.node(id)?;
+        let mut out = String::new();
+
+        self.render_node(&mut out, node, 0);
+        if let Some(priority) = node.priority
+        {
+            writeln!(out, "Priority: {}", priority).unwrap();
+        }
+        if let Some(due_date) = &node.due_date
+        {
+            writeln!(out, "Due: {}", due_date).unwrap();
+        }
+        if let Some(until) = &node.deferred_until
+        {
+            writeln!(out, "Deferred until: {}", until).unwrap();
+        }
+        if let Some(recurrence) = &node.recurrence
+        {
+            writeln!(out, "Repeats: {}", recurrence).unwrap();
+        }
+        if let Some(last) = node.completed.last()
+        {
+            match node.completed.len()
+            {
+                1 => writeln!(out, "Done: once, on {}", last).unwrap(),
+                n => writeln!(out, "Done: {} times, last on {}", n, last).unwrap()
+            }
+        }
+        if !node.tags.is_empty()
+        {
+            writeln!(out, "Tags: {}", node.tags.join(", ")).unwrap();
+        }
+        let tracked = self.tracked(id, None, Utc::now())?;
+        if tracked > Duration::zero()
+        {
+            let running = if self.running() == Some(id) { " (timer running)" } else { "" };
+            writeln!(out, "Tracked: {}{}", dates::format_duration(tracked), running).unwrap();
+        }
+        for (heading, links) in [("Parents", &node.parents), ("Deps", &node.deps)]
+        {
+            if links.is_empty()
+            {
+                continue;
+            }
+            writeln!(out, "{}:", heading).unwrap();
+            for link in links
+            {
+                self.render_node(&mut out, &self.nodes[*link], 1);
+            }
+        }
+        if let Some(notes) = &node.notes
+        {
+            writeln!(out, "Notes:").unwrap();
+            for line in notes.lines()
+            {
+                writeln!(out, "  {}", line).unwrap();
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn render_node(&self, out: &mut String, node: &Node, level: u128)
+    {
+        node.render(
+            out,
+            &self.config.goal_color,
+            &self.config.condition_color,
+            &self.config.task_color,
+            level
+        );
+    }
+}
+
+/// Replaces `old` with `new` in `links`, keeping its position.
+fn rename_link(links: &mut Links, old: usize, new: usize)
+{
+    if let Some(position) = links.get_index_of(&old)
+    {
+        links.shift_remove_index(position);
+        links.shift_insert(position, new);
+    }
 }
@@ -0,0 +1,28 @@
+//! A dependency DAG of todos.
+//!
+//! [`Graph`] holds the nodes and the links between them and is what the
+//! `todos` CLI is built on; anything the CLI can do to a todos file can be
+//! done through it. Every fallible operation returns a [`TodosError`] rather
+//! than panicking, and operations that fail leave the graph untouched.
+
+pub mod node;
+pub mod graph;
+pub mod config;
+pub mod paths;
+pub mod error;
+pub mod storage;
+pub mod editor;
+pub mod outline;
+pub mod formats;
+pub mod dates;
+pub mod rpc;
+pub mod watch;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "server")]
+pub mod server;
+
+pub use node::{Node, NodeType};
+pub use graph::Graph;
+pub use config::Config;
+pub use error::TodosError;
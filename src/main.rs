@@ -1,15 +1,16 @@
-extern crate dirs;
-
-mod node;
-mod graph;
-mod config;
-
-use node::{NodeType};
-use graph::Graph;
+use todos::config;
+use todos::editor;
+use todos::formats::{self, Format};
+use todos::outline;
+use todos::paths::Paths;
+use todos::storage::{self, Backend, JsonStorage, Lock};
+use todos::dates::{self, Recurrence};
+use chrono::NaiveDate;
+use todos::{Graph, NodeType, TodosError};
 use std::env;
 use std::collections::VecDeque;
-use std::path::Path;
-use std::io::{BufReader, BufRead, stdin};
+use std::path::{Path, PathBuf};
+use std::io::{BufReader, BufRead, Read, stdin};
 
 
 pub enum Command
@@ -40,6 +41,16 @@ pub enum Command
         parent: usize,
         child: usize
     },
+    Move
+    {
+        id: usize,
+        to: Option<usize>
+    },
+    MoveAbove
+    {
+        id: usize,
+        above: usize
+    },
     Show
     {
         overwhelm: bool
@@ -56,9 +67,100 @@ pub enum Command
     Edit
     {
         id: usize,
-        new_description: String
+        new_description: Option<String>
+    },
+    EditTree
+    {
+        root: Option<usize>,
+        outline: Option<String>
+    },
+    Note
+    {
+        id: usize,
+        text: Option<String>
+    },
+    Details
+    {
+        id: usize
+    },
+    Repeat
+    {
+        id: usize,
+        recurrence: Option<Recurrence>
+    },
+    Defer
+    {
+        id: usize,
+        until: Option<NaiveDate>
+    },
+    Deferred,
+    Start
+    {
+        id: usize
+    },
+    Stop,
+    ReportTime
+    {
+        since: Option<NaiveDate>
+    },
+    Tag
+    {
+        id: usize,
+        tags: Vec<String>
+    },
+    Untag
+    {
+        id: usize,
+        tags: Vec<String>
     },
-    Shell
+    Import
+    {
+        format: Format,
+        file: PathBuf,
+        under: Option<usize>
+    },
+    Export
+    {
+        format: Format,
+        under: Option<usize>
+    },
+    Shell,
+    Tui,
+    Rpc,
+    Watch
+    {
+        under: Option<usize>,
+        overwhelm: bool
+    },
+    Serve
+    {
+        port: u16,
+        token: Option<String>
+    },
+    Init,
+    Migrate
+    {
+        to: Backend
+    },
+    Config
+    {
+        action: ConfigAction
+    }
+}
+
+pub enum ConfigAction
+{
+    Get
+    {
+        key: String
+    },
+    Set
+    {
+        key: String,
+        value: String
+    },
+    List,
+    Path
 }
 
 fn main()
@@ -66,21 +168,311 @@ fn main()
     let mut args: VecDeque<String> = env::args().collect();
     args.pop_front();
 
-    let home_dir = dirs::home_dir().unwrap();
-    let root_path = Path::new(&home_dir).join(".todos");
-    let todos_file = root_path.join("todos");
-    let config_path = root_path.join("config.toml");
+    let file_override = match take_file_arg(&mut args)
+    {
+        Ok(file_override) => file_override,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+    let paths = Paths::resolve(file_override);
+
+    let command = match get_command(args)
+    {
+        Some(Command::Init) => {
+            init(&paths);
+            return;
+        },
+        Some(Command::Config { action }) => {
+            perform_config_action(action, &paths);
+            return;
+        },
+        Some(command) => command,
+        None => {
+            println!("Invalid command.");
+            return;
+        }
+    };
+
+    if !paths.todos_file.exists()
+    {
+        println!(
+            "No todos file found at {}. Run `todos init` to create one.",
+            paths.todos_file.display()
+        );
+        return;
+    }
+
+    let config = config::read_config_file(paths.config_file);
+
+    // the editor can stay open for as long as the user likes, so it is done
+    // with before taking the lock
+    let (command, before) = if needs_editor(&command)
+    {
+        let edited = Graph::open(&paths.todos_file, config.clone()).and_then(|graph| {
+            let before = edited_state(&command, &graph)?;
+            Ok((edit_first(command, &graph)?, Some(before)))
+        });
+        match edited
+        {
+            Ok(edited) => edited,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        }
+    }
+    else
+    {
+        (command, None)
+    };
+
+    // long-running commands take the lock for each change instead
+    let _lock = match command
+    {
+        Command::Shell | Command::Tui | Command::Rpc | Command::Watch { .. } | Command::Serve { .. } => None,
+        _ => match Lock::acquire(&paths.todos_file)
+        {
+            Ok(lock) => Some(lock),
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        }
+    };
+
+    if let Command::Migrate { to } = command
+    {
+        match storage::migrate(&paths.todos_file, to, config)
+        {
+            Ok(backup) => println!(
+                "Converted {} to {}. The original was kept as {}.",
+                paths.todos_file.display(),
+                to,
+                backup.display()
+            ),
+            Err(message) => println!("{}", message)
+        }
+        return;
+    }
+
+    let mut graph = match Graph::open(&paths.todos_file, config)
+    {
+        Ok(graph) => graph,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+
+    if let Err(message) = check_unchanged(&command, before, &graph)
+    {
+        println!("{}", message);
+        return;
+    }
+
+    perform_command(command, &mut graph, false);
+
+    if let Err(error) = graph.save()
+    {
+        println!("{}", error);
+    }
+}
+
+/// Creates the todos file and a default config, leaving existing ones alone.
+fn init(paths: &Paths)
+{
+    if paths.config_file.exists()
+    {
+        println!("Config file already exists at {}.", paths.config_file.display());
+    }
+    else
+    {
+        match config::write_default_config(&paths.config_file)
+        {
+            Ok(()) => println!("Created config file at {}.", paths.config_file.display()),
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        }
+    }
+
+    if paths.todos_file.exists()
+    {
+        println!("Todos file already exists at {}.", paths.todos_file.display());
+        return;
+    }
+
+    if let Some(dir) = paths.todos_file.parent()
+    {
+        if let Err(error) = std::fs::create_dir_all(dir)
+        {
+            println!("Unable to create {}: {}", dir.display(), error);
+            return;
+        }
+    }
+
+    let mut graph = Graph::new(config::Config::default());
+    graph.set_storage(Box::new(JsonStorage::new(paths.todos_file.clone())));
+    match graph.save()
+    {
+        Ok(()) => println!("Created todos file at {}. Enjoy!", paths.todos_file.display()),
+        Err(error) => println!("{}", error)
+    }
+}
+
+/// Pulls `--file <path>` (or `--file=<path>`) out of the argument list.
+fn take_file_arg(args: &mut VecDeque<String>) -> Result<Option<PathBuf>, String>
+{
+    let position = match args.iter().position(|x| x == "--file" || x.starts_with("--file="))
+    {
+        Some(position) => position,
+        None => { return Ok(None); }
+    };
+
+    let flag = args.remove(position).unwrap();
+    let path = if let Some(path) = flag.strip_prefix("--file=")
+    {
+        String::from(path)
+    }
+    else
+    {
+        args.remove(position).ok_or("Expected a path after --file.")?
+    };
 
-    let config = config::read_config_file(config_path);
-    let mut graph = Graph::load(todos_file, config);
+    Ok(Some(PathBuf::from(path)))
+}
 
-    match get_command(args)
+fn perform_config_action(action: ConfigAction, paths: &Paths)
+{
+    match action
     {
-        Some(command) => { perform_command(command, &mut graph, false) },
-        None => { println!("Invalid command."); }
+        ConfigAction::Get { key } => {
+            let config = config::read_config_file(paths.config_file.clone());
+            match config.get(&key)
+            {
+                Some(value) => println!("{}", value),
+                None => println!("{}", TodosError::UnknownSetting(key))
+            }
+        },
+        ConfigAction::Set { key, value } => {
+            match config::set_config_value(&paths.config_file, &key, &value)
+            {
+                Ok(()) => println!("Set {} to {}.", key, value),
+                Err(message) => println!("{}", message)
+            }
+        },
+        ConfigAction::List => {
+            let config = config::read_config_file(paths.config_file.clone());
+            for (key, value) in config.list()
+            {
+                println!("{} = {}", key, value);
+            }
+        },
+        ConfigAction::Path => {
+            println!("{}", paths.config_file.display());
+        }
     }
+}
 
-    graph.save();
+fn needs_editor(command: &Command) -> bool
+{
+    matches!(
+        command,
+        Command::Edit { new_description: None, .. } | Command::Note { text: None, .. } | Command::EditTree { outline: None, .. }
+    )
+}
+
+/// Asks the user for what a command left out in their editor, giving the
+/// command with it filled in.
+fn edit_first(command: Command, graph: &Graph) -> Result<Command, TodosError>
+{
+    match command
+    {
+        Command::Edit { id, new_description: None } => Ok(Command::Edit {
+            id,
+            new_description: Some(editor::edit_text(&graph.node(id)?.description, "txt")?)
+        }),
+        Command::Note { id, text: None } => Ok(Command::Note {
+            id,
+            text: Some(editor::edit_text(graph.node(id)?.notes.as_deref().unwrap_or(""), "md")?)
+        }),
+        Command::EditTree { root, outline: None } => Ok(Command::EditTree {
+            root,
+            outline: Some(edit_tree(graph, root)?)
+        }),
+        command => Ok(command)
+    }
+}
+
+/// What an edit made in the editor is based on: the node being edited, or
+/// the whole graph for `edit --tree`, which removes what the outline leaves
+/// out. Removing a node renumbers another, so ids alone won't do.
+fn edited_state(command: &Command, graph: &Graph) -> Result<serde_json::Value, TodosError>
+{
+    let state = match command
+    {
+        Command::Edit { id, .. } | Command::Note { id, .. } => serde_json::to_value(graph.node(*id)?),
+        _ => serde_json::to_value(graph)
+    };
+    state.map_err(|error| TodosError::Invalid(error.to_string()))
+}
+
+/// Refuses to apply an edit made in the editor if another process changed
+/// what it was based on while the editor was open.
+fn check_unchanged(command: &Command, before: Option<serde_json::Value>, graph: &Graph) -> Result<(), TodosError>
+{
+    match before
+    {
+        Some(before) if edited_state(command, graph).ok().as_ref() != Some(&before) => Err(TodosError::Invalid(
+            String::from("The todos file was changed while the editor was open; nothing was changed. Please edit again.")
+        )),
+        _ => Ok(())
+    }
+}
+
+/// Lets the user rewrite the tree under `root` as an outline in their editor.
+fn edit_tree(graph: &Graph, root: Option<usize>) -> Result<String, TodosError>
+{
+    let heading = match root
+    {
+        Some(root) => format!("#{} {}", root, graph.node(root)?.description),
+        None => String::from("root")
+    };
+    let text = format!(
+        "// Editing everything under {}.\n\
+         // One node per line, indented under its parents. Lines without an #id are\n\
+         // added and deleted lines are removed; \"goal:\" and \"?\" mark goals and\n\
+         // conditions. A node may be listed under several parents.\n\
+         {}",
+        heading,
+        outline::render(graph, root)?
+    );
+
+    editor::edit_text(&text, "md")
+}
+
+/// Reads an import file, or stdin if the file is `-`.
+fn read_input(file: &Path) -> Result<String, TodosError>
+{
+    let mut content = String::new();
+    if file == Path::new("-")
+    {
+        stdin().read_to_string(&mut content).map_err(|error| TodosError::io(file, error))?;
+    }
+    else
+    {
+        content = std::fs::read_to_string(file).map_err(|error| TodosError::io(file, error))?;
+    }
+    Ok(content)
+}
+
+fn import(graph: &mut Graph, format: Format, file: &Path, under: Option<usize>) -> Result<formats::Report, TodosError>
+{
+    let content = read_input(file)?;
+    formats::import(graph, format, &content, under)
 }
 
 fn shell_mode(graph: &mut Graph)
@@ -102,20 +494,39 @@ fn shell_mode(graph: &mut Graph)
         // split line and parse to get_command
         let args = line
             .split_ascii_whitespace()
-            .map(|x| String::from(x))
+            .map(String::from)
             .collect::<VecDeque<String>>();
 
-        match get_command(args)
+        // each command sees and saves the latest todos file, as other
+        // processes may be using it too
+        let result = match get_command(args)
+        {
+            Some(command @ Command::Tui) | Some(command @ Command::Rpc) |
+            Some(command @ Command::Watch { .. }) | Some(command @ Command::Serve { .. }) => {
+                perform_command(command, graph, true);
+                Ok(())
+            },
+            // the editor is open without the lock, so the edit is checked
+            // against the file again once it is taken
+            Some(command) if needs_editor(&command) => graph
+                .reload()
+                .and_then(|()| edited_state(&command, graph))
+                .and_then(|before| Ok((edit_first(command, graph)?, before)))
+                .and_then(|(command, before)| graph.locked(|graph| {
+                    check_unchanged(&command, Some(before), graph).map(|()| perform_command(command, graph, true))
+                }))
+                .and_then(|result| result),
+            Some(command) => graph.locked(|graph| perform_command(command, graph, true)),
+            None => {
+                println!("Invalid command.");
+                Ok(())
+            }
+        };
+        if let Err(error) = result
         {
-            Some(command) => { perform_command(command, graph, true) },
-            None => { println!("Invalid command."); }
+            println!("{}", error);
         }
 
-        // could technically be moved outside of the loop; however,
-        // in the case of a crash, it is desirable to have the graph
-        // saved already.
-        graph.save();
-
         line.clear();
     }
 
@@ -131,10 +542,10 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
     arg_list.retain( |x| *x != short && *x != long);
 
     let command_option = arg_list.pop_front();
-    if let None = command_option
+    if command_option.is_none()
     {
         return Some(Command::Show{
-            overwhelm: overwhelm
+            overwhelm
         });
     };
     let command = command_option.unwrap();
@@ -157,11 +568,11 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
 
             // read optional "to" clause or "above" clause
             let next_token_option = arg_list.pop_front();
-            if let None = next_token_option
+            if next_token_option.is_none()
             {
                     return Some(Command::New{
-                        node_type: node_type,
-                        description: description,
+                        node_type,
+                        description,
                         to: None
                     });
             }
@@ -174,11 +585,11 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
                     {
                         return None;
                     }
-                    return Some(Command::New{
-                        node_type: node_type,
-                        description: description,
+                    Some(Command::New{
+                        node_type,
+                        description,
                         to: Some(id)
-                    });
+                    })
 
 
                 },
@@ -188,14 +599,14 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
                     {
                         return None;
                     }
-                    return Some(Command::NewAbove{
-                        node_type: node_type,
-                        description: description,
+                    Some(Command::NewAbove{
+                        node_type,
+                        description,
                         above: id
-                    });
+                    })
                 },
                 _ => {
-                    return None;
+                    None
                 }
             }
 
@@ -224,10 +635,10 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
             {
                 return None;
             }
-            return Some( Command::Link{
-                parent: parent,
-                child: child
-            });
+            Some( Command::Link{
+                parent,
+                child
+            })
 
         },
         "unlink" => {
@@ -237,11 +648,41 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
             {
                 return None;
             }
-            return Some( Command::Unlink{
-                parent: parent,
-                child: child
-            });
+            Some( Command::Unlink{
+                parent,
+                child
+            })
         },
+        "move" =>
+        {
+            let id = arg_list.pop_front()?.parse::<usize>().ok()?;
+            let direction = arg_list.pop_front()?;
+            let token = arg_list.pop_front()?;
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            match &direction[..]
+            {
+                "to" | "under" => {
+                    let to = match &token[..]
+                    {
+                        "root" => { None },
+                        _ => {
+                            Some(token.parse().ok()?)
+                        }
+                    };
+                    Some(Command::Move{ id, to })
+                },
+                "above" => {
+                    let above = token.parse().ok()?;
+                    Some(Command::MoveAbove{ id, above })
+                },
+                _ => {
+                    None
+                }
+            }
+        }
         "under" =>
         {
             let id = arg_list.pop_front()?.parse::<usize>().ok()?;
@@ -249,10 +690,10 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
             {
                 return None;
             }
-            return Some(Command::Under{
-                id: id,
-                overwhelm: overwhelm
-            });
+            Some(Command::Under{
+                id,
+                overwhelm
+            })
         }
         "use" =>
         {
@@ -269,20 +710,229 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
             {
                 return None;
             }
-            return Some(Command::Use{ effective_root });
+            Some(Command::Use{ effective_root })
         }
         "edit" | "relabel" =>
         {
-            let id = arg_list.pop_front()?.parse::<usize>().ok()?;
-            let description = arg_list.pop_front()?;
+            let token = arg_list.pop_front()?;
+            if token == "--tree"
+            {
+                let token = arg_list.pop_front()?;
+                let root = match &token[..]
+                {
+                    "root" => { None },
+                    _ => {
+                        Some(token.parse().ok()?)
+                    }
+                };
+                if !arg_list.is_empty()
+                {
+                    return None;
+                }
+                return Some(Command::EditTree{ root, outline: None });
+            }
+            let id = token.parse::<usize>().ok()?;
+            let description = arg_list.pop_front();
             if !arg_list.is_empty()
             {
                 return None;
             }
-            return Some(Command::Edit{
-                id: id,
+            Some(Command::Edit{
+                id,
                 new_description: description
-            });
+            })
+        }
+        "note" =>
+        {
+            let id = arg_list.pop_front()?.parse::<usize>().ok()?;
+            let text = arg_list.pop_front();
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Note{ id, text })
+        }
+        "show" =>
+        {
+            let token = arg_list.pop_front();
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            match token
+            {
+                Some(token) => Some(Command::Details{ id: token.parse().ok()? }),
+                None => Some(Command::Show{ overwhelm })
+            }
+        }
+        "tag" | "untag" =>
+        {
+            let id = arg_list.pop_front()?.parse::<usize>().ok()?;
+            let tags: Vec<String> = arg_list.into_iter().collect();
+            if tags.is_empty()
+            {
+                return None;
+            }
+            if command == "tag"
+            {
+                Some(Command::Tag{ id, tags })
+            }
+            else
+            {
+                Some(Command::Untag{ id, tags })
+            }
+        }
+        "repeat" =>
+        {
+            let id = arg_list.pop_front()?.parse().ok()?;
+            let rule = Vec::from(arg_list).join(" ");
+            let recurrence = if rule == "never"
+            {
+                None
+            }
+            else
+            {
+                Some(Recurrence::parse(&rule)?)
+            };
+            Some(Command::Repeat{ id, recurrence })
+        }
+        "defer" =>
+        {
+            let id = arg_list.pop_front()?.parse().ok()?;
+            if arg_list.pop_front()? != "until"
+            {
+                return None;
+            }
+            let until = dates::parse_day(&Vec::from(arg_list).join(" "), chrono::Local::now().date_naive())?;
+            Some(Command::Defer{ id, until: Some(until) })
+        }
+        "undefer" =>
+        {
+            let id = arg_list.pop_front()?.parse().ok()?;
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Defer{ id, until: None })
+        }
+        "deferred" =>
+        {
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Deferred)
+        }
+        "start" =>
+        {
+            let id = arg_list.pop_front()?.parse().ok()?;
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Start{ id })
+        }
+        "stop" =>
+        {
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Stop)
+        }
+        "report" =>
+        {
+            if arg_list.pop_front()? != "time"
+            {
+                return None;
+            }
+            let since = match arg_list.pop_front()
+            {
+                Some(flag) if flag == "--since" => {
+                    Some(dates::parse_past_day(&Vec::from(arg_list).join(" "), chrono::Local::now().date_naive())?)
+                },
+                Some(_) => { return None; },
+                None => None
+            };
+            Some(Command::ReportTime{ since })
+        }
+        "init" =>
+        {
+            if !arg_list.is_empty()
+            {
+                None
+            }
+            else
+            {
+                Some(Command::Init)
+            }
+        }
+        "migrate" =>
+        {
+            if arg_list.pop_front()? != "--to"
+            {
+                return None;
+            }
+            let to = Backend::from_string(&arg_list.pop_front()?)?;
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Migrate{ to })
+        }
+        "import" =>
+        {
+            let format = Format::from_string(&arg_list.pop_front()?)?;
+            let file = PathBuf::from(arg_list.pop_front()?);
+            let under = match arg_list.pop_front()
+            {
+                Some(token) if token == "under" || token == "to" => {
+                    Some(arg_list.pop_front()?.parse().ok()?)
+                },
+                Some(_) => { return None; },
+                None => None
+            };
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Import{ format, file, under })
+        }
+        "export" =>
+        {
+            let format = Format::from_string(&arg_list.pop_front()?)?;
+            let under = match arg_list.pop_front()
+            {
+                Some(token) if token == "under" => {
+                    Some(arg_list.pop_front()?.parse().ok()?)
+                },
+                Some(_) => { return None; },
+                None => None
+            };
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Export{ format, under })
+        }
+        "config" =>
+        {
+            let action = match &arg_list.pop_front()?[..]
+            {
+                "get" => ConfigAction::Get{ key: arg_list.pop_front()? },
+                "set" => ConfigAction::Set{
+                    key: arg_list.pop_front()?,
+                    value: arg_list.pop_front()?
+                },
+                "list" => ConfigAction::List,
+                "path" => ConfigAction::Path,
+                _ => { return None; }
+            };
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Config{ action })
         }
         "shell" =>
         {
@@ -296,8 +946,61 @@ fn get_command(mut arg_list: VecDeque<String>) -> Option<Command>
             }
 
         }
+        "tui" =>
+        {
+            if !arg_list.is_empty()
+            {
+                None
+            }
+            else
+            {
+                Some(Command::Tui)
+            }
+        }
+        "rpc" =>
+        {
+            if !arg_list.is_empty()
+            {
+                None
+            }
+            else
+            {
+                Some(Command::Rpc)
+            }
+        }
+        "watch" =>
+        {
+            let under = match arg_list.pop_front()
+            {
+                Some(token) if token == "under" => {
+                    Some(arg_list.pop_front()?.parse().ok()?)
+                },
+                Some(_) => { return None; },
+                None => None
+            };
+            if !arg_list.is_empty()
+            {
+                return None;
+            }
+            Some(Command::Watch{ under, overwhelm })
+        }
+        "serve" =>
+        {
+            let mut port = 7878;
+            let mut token = None;
+            while let Some(flag) = arg_list.pop_front()
+            {
+                match &flag[..]
+                {
+                    "--port" => { port = arg_list.pop_front()?.parse().ok()?; },
+                    "--token" => { token = Some(arg_list.pop_front()?); },
+                    _ => { return None; }
+                }
+            }
+            Some(Command::Serve{ port, token })
+        }
         _ => {
-            return None
+            None
         }
     }
 
@@ -335,13 +1038,18 @@ fn perform_command(command: Command, graph: &mut Graph, in_shell: bool)
 
         },
         Command::Complete { node_ids } => {
-            match graph.batch_remove(node_ids, true)
+            match graph.complete(node_ids, chrono::Local::now().date_naive())
             {
-                Ok(()) => {
-                    println!("Thank god, you managed to complete something")
+                Ok(again) => {
+                    println!("Thank god, you managed to complete something");
+                    for id in again
+                    {
+                        let node = graph.node(id).unwrap();
+                        println!("Don't get comfortable: {} is back as {}, due {}.", node.description, id, node.due_date.as_deref().unwrap_or(""));
+                    }
                 },
                 Err(message) => {
-                    print!("{}", message);
+                    println!("{}", message);
                 }
             }
         },
@@ -367,16 +1075,38 @@ fn perform_command(command: Command, graph: &mut Graph, in_shell: bool)
                 }
             }
         },
-        Command::Show { overwhelm } => {
-            graph.todos(overwhelm);
+        Command::Move { id, to } => {
+            match graph.move_node(id, to)
+            {
+                Ok(()) => {
+                    println!("Successfully moved node");
+                }
+                Err(message) => {
+                    println!("{}", message);
+                }
+            }
         },
-        Command::Under { id, overwhelm } => {
-            match graph.show(&id, 0, overwhelm, Some(id))
+        Command::MoveAbove { id, above } => {
+            match graph.move_node_above(id, above)
             {
+                Ok(()) => {
+                    println!("Successfully moved node");
+                }
                 Err(message) => {
                     println!("{}", message);
-                },
-                _ => {}
+                }
+            }
+        },
+        Command::Show { overwhelm } => {
+            if let Some(timer) = graph.render_timer(chrono::Utc::now())
+            {
+                println!("{}", timer);
+            }
+            graph.todos(overwhelm);
+        },
+        Command::Under { id, overwhelm } => {
+            if let Err(message) = graph.show(&id, 0, overwhelm, Some(id)) {
+                println!("{}", message);
             }
         },
         Command::Use { effective_root } => {
@@ -397,14 +1127,177 @@ fn perform_command(command: Command, graph: &mut Graph, in_shell: bool)
                 }
             }
         },
-        Command::Edit { id, new_description } =>
+        Command::Edit { id, new_description: Some(description) } =>
         {
-            match graph.relabel(id, new_description)
+            // editors like to add a trailing newline
+            let description = description.trim();
+            let relabeled = if description.is_empty()
+            {
+                Err(TodosError::Invalid(String::from("Descriptions can't be empty; nothing was changed.")))
+            }
+            else
+            {
+                graph.relabel(id, description.to_string())
+            };
+            match relabeled
             {
                 Ok(()) => println!("Successfully relabeled node."),
                 Err(message) => println!("{}", message)
             }
         }
+        Command::EditTree { root, outline: Some(outline) } =>
+        {
+            match outline::parse(&outline).and_then(|entries| outline::apply(graph, root, &entries))
+            {
+                Ok(summary) => println!(
+                    "Added {}, removed {} and changed {} nodes; made {} links and removed {}.",
+                    summary.added,
+                    summary.removed,
+                    summary.changed,
+                    summary.linked,
+                    summary.unlinked
+                ),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Note { id, text: Some(text) } =>
+        {
+            match graph.set_notes(id, Some(text))
+            {
+                Ok(()) => println!("Successfully updated notes."),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Edit { .. } | Command::EditTree { .. } | Command::Note { .. } =>
+        {
+            unreachable!("edit_first fills in what the editor is for before the lock is taken")
+        }
+        Command::Details { id } =>
+        {
+            match graph.render_details(id)
+            {
+                Ok(details) => print!("{}", details),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Repeat { id, recurrence } =>
+        {
+            let message = match &recurrence
+            {
+                Some(recurrence) => format!("Node {} now repeats {}.", id, recurrence),
+                None => format!("Node {} no longer repeats.", id)
+            };
+            match graph.set_recurrence(id, recurrence)
+            {
+                Ok(()) => println!("{}", message),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Defer { id, until } =>
+        {
+            match graph.defer(id, until)
+            {
+                Ok(()) => match until
+                {
+                    Some(until) => println!("Out of sight, out of mind: node {} is hidden until {}.", id, until),
+                    None => println!("Node {} is back. Hope you're ready for it.", id)
+                },
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Deferred =>
+        {
+            let deferred = graph.render_deferred(chrono::Local::now().date_naive());
+            if deferred.is_empty()
+            {
+                println!("Nothing is deferred. Everything is your problem right now.");
+            }
+            print!("{}", deferred);
+        }
+        Command::Start { id } =>
+        {
+            match graph.start_timer(id, chrono::Utc::now())
+            {
+                Ok(stopped) => {
+                    if let Some(stopped) = stopped
+                    {
+                        println!("Stopped the timer on node {} first. Multitasking is a myth.", stopped);
+                    }
+                    println!("The clock is ticking on node {}. Get to it.", id);
+                },
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Stop =>
+        {
+            match graph.stop_timer(chrono::Utc::now())
+            {
+                Ok((id, spent)) => {
+                    println!("Stopped the timer on node {} after {}.", id, dates::format_duration(spent));
+                },
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::ReportTime { since } =>
+        {
+            // from the start of the day, here
+            let start = since.and_then(|x| x.and_time(chrono::NaiveTime::MIN).and_local_timezone(chrono::Local).earliest());
+            let report = graph.render_time_report(start.map(|x| x.with_timezone(&chrono::Utc)), chrono::Utc::now());
+            if report.is_empty()
+            {
+                println!("No time tracked. Either you've been slacking or you forgot `todos start`.");
+            }
+            print!("{}", report);
+        }
+        Command::Tag { id, tags } =>
+        {
+            match graph.tag(id, tags)
+            {
+                Ok(()) => println!("Successfully tagged node."),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Untag { id, tags } =>
+        {
+            match graph.untag(id, &tags)
+            {
+                Ok(()) => println!("Successfully untagged node."),
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Import { format, file, under } =>
+        {
+            match import(graph, format, &file, under)
+            {
+                Ok(report) => {
+                    println!("Ha! Your workload just got a little bigger. Imported {} nodes.", report.added.len());
+                    if report.skipped > 0
+                    {
+                        println!("Skipped {} items that were already done.", report.skipped);
+                    }
+                    for warning in report.warnings
+                    {
+                        println!("{}", warning);
+                    }
+                },
+                Err(message) => println!("{}", message)
+            }
+        }
+        Command::Export { format, under } =>
+        {
+            match formats::export(graph, format, under)
+            {
+                Ok(exported) => {
+                    print!("{}", exported.content);
+                    // keep stdout clean for redirecting to a file
+                    for warning in exported.warnings
+                    {
+                        eprintln!("{}", warning);
+                    }
+                },
+                Err(message) => println!("{}", message)
+            }
+        }
         Command::Shell =>
         {
             // the following could be recursive if already in shell mode.
@@ -414,5 +1307,54 @@ fn perform_command(command: Command, graph: &mut Graph, in_shell: bool)
                 shell_mode(graph);
             }
         }
+        Command::Tui =>
+        {
+            #[cfg(feature = "tui")]
+            if let Err(message) = todos::tui::run(graph)
+            {
+                println!("{}", message);
+            }
+            #[cfg(not(feature = "tui"))]
+            println!("This build of todos doesn't have the TUI; rebuild it with the `tui` feature.");
+        }
+        Command::Rpc =>
+        {
+            if let Err(message) = todos::rpc::run(graph, stdin().lock(), std::io::stdout())
+            {
+                eprintln!("{}", message);
+            }
+        }
+        Command::Watch { under, overwhelm } =>
+        {
+            if let Err(message) = todos::watch::run(graph, under, overwhelm)
+            {
+                println!("{}", message);
+            }
+        }
+        Command::Serve { port, token } =>
+        {
+            #[cfg(feature = "server")]
+            if let Err(message) = todos::server::serve(graph, port, token.as_deref())
+            {
+                println!("{}", message);
+            }
+            #[cfg(not(feature = "server"))]
+            {
+                let _ = (port, token);
+                println!("This build of todos doesn't have the server; rebuild it with the `server` feature.");
+            }
+        }
+        Command::Init =>
+        {
+            println!("Already initialized.");
+        }
+        Command::Migrate { .. } =>
+        {
+            println!("The todos file can't be converted from the shell; exit and run `todos migrate` instead.");
+        }
+        Command::Config { .. } =>
+        {
+            println!("Settings can't be changed from the shell; exit and run `todos config` instead.");
+        }
     }
 }
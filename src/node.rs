@@ -3,9 +3,16 @@ extern crate serde_json;
 
 
 use std::fmt;
+use std::fmt::Write;
+use indexmap::IndexSet;
 use serde::{Serialize, Deserialize};
+use crate::dates::{Due, Interval, Recurrence};
+use chrono::NaiveDate;
 
-#[derive(Clone, Serialize, Deserialize, PartialEq)]
+/// The ids a node is linked to, in the order the links were made.
+pub type Links = IndexSet<usize>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum NodeType
 {
     Task,
@@ -25,9 +32,9 @@ impl fmt::Display for NodeType
 }
 impl NodeType
 {
-    pub fn from_string(s: &String) -> Option<NodeType>
+    pub fn from_string(s: &str) -> Option<NodeType>
     {
-        match &s[..]
+        match s
         {
             "task" => Some(NodeType::Task),
             "condition" => Some(NodeType::Condition),
@@ -37,36 +44,109 @@ impl NodeType
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Node
 {
     pub id: usize,
     pub description: String,
     pub node_type: NodeType,
     pub due_date: Option<String>,
-    pub deps: Vec<usize>,
-    pub parents: Vec<usize>
+    /// `A` (highest) to `Z`, as in todo.txt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<char>,
+    /// Long-form details in markdown, edited with `todos note`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Identifies the node to other tools, such as Taskwarrior.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Brings the node back when it is completed (see `todos repeat`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// The days earlier instances of a recurring node were completed on,
+    /// oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completed: Vec<String>,
+    /// `YYYY-MM-DD`; until then the node is hidden (see `todos defer`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deferred_until: Option<String>,
+    /// Time spent on the node (see `todos start`), oldest first, along with
+    /// that of completed nodes that were below it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time: Vec<Interval>,
+    pub deps: Links,
+    pub parents: Links
 }
 
 impl Node
 {
     pub fn print(&self, goal_color: &String, condition_color: &String, task_color: &String, level: u128)
+    {
+        let mut line = String::new();
+        self.render(&mut line, goal_color, condition_color, task_color, level);
+        print!("{}", line);
+    }
+
+    /// Appends the line `print` would print to `out`.
+    pub fn render(&self, out: &mut String, goal_color: &String, condition_color: &String, task_color: &String, level: u128)
     {
         for _i in 0..level
         {
-            print!("  ");
+            out.push_str("  ");
         }
-        match self.node_type {
-            NodeType::Goal => {
-                println!("\x1B[{}m{} goal (id {}):\x1B[00m", goal_color, &self.description, &self.id);
-            },
-            NodeType::Condition => {
-                println!("\x1B[{}m{} ({}): {}\x1B[00m", condition_color, &self.id, self.node_type ,&self.description);
-            },
-            _ => {
-                println!("\x1B[{}m{} ({}): {}\x1B[00m", task_color, &self.id, self.node_type ,&self.description);
-            }
+        let color = match self.node_type
+        {
+            NodeType::Goal => goal_color,
+            NodeType::Condition => condition_color,
+            NodeType::Task => task_color
+        };
+        writeln!(out, "\x1B[{}m{}\x1B[00m", color, self.label()).unwrap();
+    }
+
+    /// The text of the node's line, without indentation or colors.
+    pub fn label(&self) -> String
+    {
+        // marks nodes with more to read in `todos show <id>`
+        let marker = if self.notes.is_some() { " [notes]" } else { "" };
+        match self.node_type
+        {
+            NodeType::Goal => format!("{} goal (id {}):{}", &self.description, &self.id, marker),
+            _ => format!("{} ({}): {}{}", &self.id, self.node_type, &self.description, marker)
         }
     }
-}
 
+    /// The interval of the timer, if it is running on the node.
+    pub fn timer(&self) -> Option<&Interval>
+    {
+        self.time.iter().find(|x| x.end.is_none())
+    }
+
+    /// Whether the node is still deferred on `today`.
+    pub fn is_deferred(&self, today: NaiveDate) -> bool
+    {
+        self.deferred_until.as_deref().and_then(Due::parse).map(|x| x.date() > today).unwrap_or(false)
+    }
+
+    /// The node as JSON for other programs (see `todos serve` and `todos
+    /// rpc`), with its type spelled like on the command line.
+    pub fn to_json(&self) -> serde_json::Value
+    {
+        serde_json::json!({
+            "id": self.id,
+            "type": self.node_type.to_string(),
+            "description": self.description,
+            "due_date": self.due_date,
+            "priority": self.priority,
+            "tags": self.tags,
+            "recurrence": self.recurrence.as_ref().map(|x| x.to_string()),
+            "completed": self.completed,
+            "deferred_until": self.deferred_until,
+            "time": self.time,
+            "notes": self.notes,
+            "deps": self.deps,
+            "parents": self.parents
+        })
+    }
+}
@@ -0,0 +1,488 @@
+//! An indented text outline of (part of) the graph, used to edit a subtree
+//! in a text editor.
+//!
+//! Each line is one node, indented under its parent:
+//!
+//! ```text
+//! - #0 goal: house
+//!   - #1 paint the fence
+//!     - #2 buy paint
+//!     - #3 ? dry weather
+//! ```
+//!
+//! The `#id` ties a line to an existing node; lines without one are new
+//! nodes. `goal:` and `?` (or `condition:`) mark goals and conditions, and
+//! anything else is a task; `task:` marks one whose description would read
+//! as something else, like `task: ? why`. A line with just an `#id` refers
+//! to a node without changing it. Bullets (`-`, `*`, `+` or numbers) and
+//! checkboxes are optional, Markdown headings are goals holding the lines
+//! below them, and lines starting with `//` are ignored.
+
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+
+use indexmap::IndexSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// One line of an outline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry
+{
+    /// 1-based line number, for error messages.
+    pub line: usize,
+    pub id: Option<usize>,
+    pub node_type: NodeType,
+    /// Empty for a line that only refers to an existing node by its id.
+    pub description: String,
+    /// Index of the entry this one is indented under, if any.
+    pub parent: Option<usize>,
+    /// Whether the line was a ticked checkbox, `- [x]`.
+    pub done: bool
+}
+
+/// What applying an outline did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary
+{
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub linked: usize,
+    pub unlinked: usize
+}
+
+/// Parses an outline written by `render`, where `#id` refers to an existing
+/// node.
+pub fn parse(text: &str) -> Result<Vec<Entry>, TodosError>
+{
+    parse_lines(text, true)
+}
+
+/// Parses an outline from elsewhere, such as a Markdown checklist, where
+/// `#12` is just part of the description.
+pub fn parse_plain(text: &str) -> Result<Vec<Entry>, TodosError>
+{
+    parse_lines(text, false)
+}
+
+fn parse_lines(text: &str, with_ids: bool) -> Result<Vec<Entry>, TodosError>
+{
+    let mut entries: Vec<Entry> = Vec::new();
+    // indentation and index of the entries the next line could be under
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    // level and index of the Markdown headings the next line could be under
+    let mut headings: Vec<(usize, usize)> = Vec::new();
+
+    for (i, line) in text.lines().enumerate()
+    {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with("//")
+        {
+            continue;
+        }
+
+        let level = content.chars().take_while(|c| *c == '#').count();
+        if level > 0 && content[level..].starts_with(' ')
+        {
+            // headings are goals, holding everything up to the next heading
+            // of the same level
+            while headings.last().is_some_and(|(x, _)| *x >= level)
+            {
+                headings.pop();
+            }
+            open.clear();
+            headings.push((level, entries.len()));
+            entries.push(Entry
+            {
+                line: i + 1,
+                id: None,
+                node_type: NodeType::Goal,
+                description: content[level..].trim().to_string(),
+                parent: headings.iter().rev().nth(1).map(|(_, index)| *index),
+                done: false
+            });
+            continue;
+        }
+
+        let indent = indentation(line);
+        while open.last().is_some_and(|(x, _)| *x >= indent)
+        {
+            open.pop();
+        }
+
+        let (id, node_type, description, done) = parse_line(content, with_ids)
+            .map_err(|message| TodosError::Invalid(format!("Line {}: {}", i + 1, message)))?;
+
+        let parent = open.last().or(headings.last()).map(|(_, index)| *index);
+        open.push((indent, entries.len()));
+        entries.push(Entry
+        {
+            line: i + 1,
+            id,
+            node_type,
+            description,
+            parent,
+            done
+        });
+    }
+
+    Ok(entries)
+}
+
+fn indentation(line: &str) -> usize
+{
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn parse_line(content: &str, with_ids: bool) -> Result<(Option<usize>, NodeType, String, bool), String>
+{
+    let mut rest = content;
+    if let Some(stripped) = ["- ", "* ", "+ "].iter().find_map(|bullet| rest.strip_prefix(bullet))
+    {
+        rest = stripped.trim_start();
+    }
+    else
+    {
+        // numbered lists, `1.` or `1)`
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0
+        {
+            if let Some(stripped) = rest[digits..].strip_prefix(". ").or_else(|| rest[digits..].strip_prefix(") "))
+            {
+                rest = stripped.trim_start();
+            }
+        }
+    }
+
+    let mut done = false;
+    if let Some(stripped) = rest.strip_prefix("[ ]")
+    {
+        rest = stripped.trim_start();
+    }
+    else if let Some(stripped) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]"))
+    {
+        rest = stripped.trim_start();
+        done = true;
+    }
+
+    let mut id = None;
+    if let Some(stripped) = rest.strip_prefix('#').filter(|_| with_ids)
+    {
+        let digits: String = stripped.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if !digits.is_empty()
+        {
+            id = Some(digits.parse().map_err(|_| format!("#{} is not a valid id.", digits))?);
+            rest = stripped[digits.len()..].trim_start();
+        }
+    }
+
+    let (node_type, description) = if let Some(stripped) = rest.strip_prefix("goal:")
+    {
+        (NodeType::Goal, stripped)
+    }
+    else if let Some(stripped) = rest.strip_prefix("condition:").or_else(|| rest.strip_prefix('?'))
+    {
+        (NodeType::Condition, stripped)
+    }
+    else
+    {
+        (NodeType::Task, rest.strip_prefix("task:").unwrap_or(rest))
+    };
+
+    let description = description.trim();
+    if description.is_empty() && id.is_none()
+    {
+        return Err(String::from("missing description."));
+    }
+
+    Ok((id, node_type, description.to_string(), done))
+}
+
+fn format_line(out: &mut String, level: usize, id: Option<usize>, node_type: &NodeType, description: &str)
+{
+    let prefix = match node_type
+    {
+        NodeType::Goal => "goal: ",
+        NodeType::Condition => "? ",
+        NodeType::Task if reads_as_more(description) => "task: ",
+        NodeType::Task => ""
+    };
+    let id = id.map(|x| format!("#{} ", x)).unwrap_or_default();
+    writeln!(out, "{}- {}{}{}", "  ".repeat(level), id, prefix, description).unwrap();
+}
+
+/// Whether a task's description starts with something `parse_line` would
+/// take for a prefix, checkbox, id or comment.
+fn reads_as_more(description: &str) -> bool
+{
+    let id = description.strip_prefix('#').is_some_and(|x| x.starts_with(|c: char| c.is_ascii_digit()));
+    id || ["goal:", "condition:", "task:", "?", "[ ]", "[x]", "[X]", "//"].iter().any(|x| description.starts_with(x))
+}
+
+/// The nodes under `root`, or the whole graph if `root` is `None`.
+pub fn region(graph: &Graph, root: Option<usize>) -> Result<BTreeSet<usize>, TodosError>
+{
+    let root = match root
+    {
+        Some(root) => root,
+        None => { return Ok((0..graph.nodes().len()).collect()); }
+    };
+
+    let mut region = BTreeSet::new();
+    let mut stack: Vec<usize> = graph.node(root)?.deps.iter().copied().collect();
+    while let Some(id) = stack.pop()
+    {
+        if region.insert(id)
+        {
+            stack.extend(graph.nodes()[id].deps.iter());
+        }
+    }
+    Ok(region)
+}
+
+/// Writes the nodes under `root` (or the whole graph) as an outline. A node
+/// with several parents appears under each of them, but its children are
+/// only listed the first time.
+pub fn render(graph: &Graph, root: Option<usize>) -> Result<String, TodosError>
+{
+    render_lines(graph, root, true)
+}
+
+/// Like `render`, but without ids and starting from `root` itself, for
+/// reading elsewhere. A node with several parents is listed in full under
+/// each of them.
+pub fn render_plain(graph: &Graph, root: Option<usize>) -> Result<String, TodosError>
+{
+    render_lines(graph, root, false)
+}
+
+fn render_lines(graph: &Graph, root: Option<usize>, with_ids: bool) -> Result<String, TodosError>
+{
+    let top: Vec<usize> = match root
+    {
+        Some(root) if !with_ids => vec![graph.node(root)?.id],
+        Some(root) => graph.node(root)?.deps.iter().copied().collect(),
+        None => graph.nodes().iter().filter(|x| x.parents.is_empty()).map(|x| x.id).collect()
+    };
+
+    let mut out = String::new();
+    let mut seen = HashSet::new();
+    let mut stack: Vec<(usize, usize)> = top.into_iter().rev().map(|id| (id, 0)).collect();
+    while let Some((id, level)) = stack.pop()
+    {
+        let node = &graph.nodes()[id];
+        format_line(&mut out, level, Some(id).filter(|_| with_ids), &node.node_type, &node.description);
+        if seen.insert(id) || !with_ids
+        {
+            stack.extend(node.deps.iter().rev().map(|child| (*child, level + 1)));
+        }
+    }
+    Ok(out)
+}
+
+/// Makes the nodes under `root` (or the whole graph) match `entries`:
+/// lines without an id are added, nodes under `root` that no longer appear
+/// are removed (without their children, which are removed only if they are
+/// gone too), and links, descriptions and types are changed to match.
+///
+/// Everything happens in one transaction, so on error the graph is left as
+/// it was.
+pub fn apply(graph: &mut Graph, root: Option<usize>, entries: &[Entry]) -> Result<Summary, TodosError>
+{
+    let region = region(graph, root)?;
+
+    // the wanted description and type of each existing node, which must
+    // agree wherever the node appears
+    let mut edits: HashMap<usize, (usize, NodeType, String)> = HashMap::new();
+    for entry in entries
+    {
+        let id = match entry.id
+        {
+            Some(id) => id,
+            None => { continue; }
+        };
+        if !region.contains(&id)
+        {
+            return Err(TodosError::Invalid(format!(
+                "Line {}: node #{} is not part of the tree being edited.",
+                entry.line,
+                id
+            )));
+        }
+
+        let node = &graph.nodes()[id];
+        if entry.description.is_empty() || (entry.node_type == node.node_type && entry.description == node.description)
+        {
+            continue;
+        }
+        match edits.get(&id)
+        {
+            Some((line, node_type, description)) if *node_type != entry.node_type || *description != entry.description => {
+                return Err(TodosError::Invalid(format!(
+                    "Lines {} and {}: node #{} is changed differently in each.",
+                    line,
+                    entry.line,
+                    id
+                )));
+            },
+            Some(_) => {},
+            None => {
+                edits.insert(id, (entry.line, entry.node_type.clone(), entry.description.clone()));
+            }
+        }
+    }
+
+    graph.transaction(|graph| {
+        let mut summary = Summary::default();
+
+        // new nodes first, so every entry has an id to link
+        let mut ids: Vec<usize> = Vec::with_capacity(entries.len());
+        for entry in entries
+        {
+            let id = match entry.id
+            {
+                Some(id) => id,
+                None => {
+                    let parent = entry.parent.map(|x| ids[x]).or(root);
+                    let id = graph.add_node_to(entry.description.clone(), entry.node_type.clone(), parent)?;
+                    if parent.is_none()
+                    {
+                        // add_node_to puts parentless nodes under the
+                        // effective root, but this one belongs at the top
+                        if let Some(effective_root) = graph.effective_root()
+                        {
+                            graph.unlink(&effective_root, &id)?;
+                        }
+                    }
+                    summary.added += 1;
+                    id
+                }
+            };
+            ids.push(id);
+        }
+
+        for (id, (_, node_type, description)) in &edits
+        {
+            graph.relabel(*id, description.clone())?;
+            graph.retype(*id, node_type.clone())?;
+            summary.changed += 1;
+        }
+
+        // parents inside the edited tree, as the outline has them
+        let mut wanted: HashMap<usize, IndexSet<usize>> = HashMap::new();
+        for (entry, id) in entries.iter().zip(&ids)
+        {
+            let parents = wanted.entry(*id).or_default();
+            if let Some(parent) = entry.parent.map(|x| ids[x]).or(root)
+            {
+                parents.insert(parent);
+            }
+        }
+
+        // unlink everything first, so that swapping a parent and child
+        // doesn't trip the cycle check halfway through
+        let mut to_link = Vec::new();
+        let mut done = HashSet::new();
+        for (entry, id) in entries.iter().zip(&ids)
+        {
+            if entry.id.is_none() || !done.insert(*id)
+            {
+                continue;
+            }
+            let parents = graph.node(*id)?.parents.clone();
+            for parent in &parents
+            {
+                let inside = region.contains(parent) || Some(*parent) == root;
+                if inside && !wanted[id].contains(parent)
+                {
+                    graph.unlink(parent, id)?;
+                    summary.unlinked += 1;
+                }
+            }
+            for parent in &wanted[id]
+            {
+                if !parents.contains(parent)
+                {
+                    to_link.push((*parent, *id));
+                }
+            }
+        }
+        for (parent, child) in to_link
+        {
+            graph.link(&parent, &child)?;
+            summary.linked += 1;
+        }
+
+        let mentioned: HashSet<usize> = entries.iter().filter_map(|x| x.id).collect();
+        let removed: Vec<usize> = region.iter().copied().filter(|x| !mentioned.contains(x)).collect();
+        summary.removed = removed.len();
+        if !removed.is_empty()
+        {
+            graph.batch_remove(removed, false)?;
+        }
+
+        Ok(summary)
+    })
+}
+
+/// Adds every entry of an imported outline as a new node, top-level lines
+/// going under `under` (or the effective root). Ticked checkboxes are
+/// already done, so they are skipped along with everything under them.
+///
+/// The import happens in one transaction and the graph is validated before
+/// it is kept, so on error nothing is added. Returns the ids of the new
+/// nodes and how many entries were skipped.
+pub fn import(graph: &mut Graph, under: Option<usize>, entries: &[Entry]) -> Result<(Vec<usize>, usize), TodosError>
+{
+    if let Some(under) = under
+    {
+        graph.node(under)?;
+    }
+
+    graph.transaction(|graph| {
+        // the id of each entry, or None if it was skipped
+        let mut ids: Vec<Option<usize>> = Vec::with_capacity(entries.len());
+        let mut skipped = 0;
+        for entry in entries
+        {
+            if let Some(id) = entry.id
+            {
+                return Err(TodosError::Invalid(format!(
+                    "Line {}: imported outlines can't refer to existing nodes like #{}.",
+                    entry.line,
+                    id
+                )));
+            }
+
+            let parent = match entry.parent
+            {
+                Some(parent) => match ids[parent]
+                {
+                    Some(id) => Some(id),
+                    None => {
+                        ids.push(None);
+                        skipped += 1;
+                        continue;
+                    }
+                },
+                None => under
+            };
+            if entry.done
+            {
+                ids.push(None);
+                skipped += 1;
+                continue;
+            }
+
+            ids.push(Some(graph.add_node_to(entry.description.clone(), entry.node_type.clone(), parent)?));
+        }
+
+        graph.validate()?;
+        Ok((ids.into_iter().flatten().collect(), skipped))
+    })
+}
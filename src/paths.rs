@@ -0,0 +1,77 @@
+extern crate dirs;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Name of the todos file looked for in the current directory and its
+/// ancestors, so a repository can carry its own project todos.
+pub const PROJECT_FILE_NAME: &str = ".todos";
+
+/// Environment variable overriding the location of the todos file.
+pub const TODOS_FILE_VAR: &str = "TODOS_FILE";
+
+pub struct Paths
+{
+    pub todos_file: PathBuf,
+    pub config_file: PathBuf
+}
+
+impl Paths
+{
+    /// Works out where the todos file and config file live.
+    ///
+    /// The todos file is, in order of precedence: the `--file` argument,
+    /// `$TODOS_FILE`, a `.todos` file in the current directory or any of its
+    /// ancestors, the legacy `~/.todos/todos`, and finally `todos` in the
+    /// XDG data directory. The config file is the legacy
+    /// `~/.todos/config.toml` if present, otherwise `todos/config.toml` in the
+    /// XDG config directory.
+    pub fn resolve(file_override: Option<PathBuf>) -> Paths
+    {
+        let todos_file = file_override
+            .or_else(|| env::var_os(TODOS_FILE_VAR).map(PathBuf::from))
+            .or_else(|| env::current_dir().ok().and_then(|dir| find_project_file(&dir)))
+            .or_else(|| legacy_dir().map(|dir| dir.join("todos")).filter(|path| path.is_file()))
+            .unwrap_or_else(|| data_dir().join("todos"));
+
+        let config_file = legacy_dir()
+            .map(|dir| dir.join("config.toml"))
+            .filter(|path| path.is_file())
+            .unwrap_or_else(|| config_dir().join("config.toml"));
+
+        Paths { todos_file, config_file }
+    }
+}
+
+/// Walks up from `start` looking for a project todos file.
+pub fn find_project_file(start: &Path) -> Option<PathBuf>
+{
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Directory used by installs predating XDG support.
+fn legacy_dir() -> Option<PathBuf>
+{
+    dirs::home_dir().map(|home| home.join(".todos"))
+}
+
+/// `$XDG_DATA_HOME/todos`, or the platform equivalent.
+pub fn data_dir() -> PathBuf
+{
+    dirs::data_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+        .unwrap_or_default()
+        .join("todos")
+}
+
+/// `$XDG_CONFIG_HOME/todos`, or the platform equivalent.
+pub fn config_dir() -> PathBuf
+{
+    dirs::config_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        .unwrap_or_default()
+        .join("todos")
+}
@@ -0,0 +1,428 @@
+//! [JSON-RPC 2.0](https://www.jsonrpc.org/specification) over stdin and
+//! stdout, for editor plugins that keep one `todos rpc` process running
+//! rather than starting `todos` for every change.
+//!
+//! Each line of input is a request and each response is a line of output.
+//! Methods follow the CLI's commands (`add`, `complete`, `link`, `edit`,
+//! ...) with named params, plus queries (`nodes`, `leaves`, `search`,
+//! `ancestors`, `report_time`, ...). After a method changes the graph, its
+//! response is followed by a `changed` notification naming the method.
+//!
+//! A method that fails answers with the [`TodosError`]'s message and its
+//! [`TodosError::code`] as `data.error`.
+
+use crate::dates::{self, Recurrence};
+use crate::error::TodosError;
+use crate::formats::{self, Format};
+use crate::graph::Graph;
+use crate::node::{Node, NodeType};
+use crate::outline;
+
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, Write};
+
+/// Methods that change the graph, so are followed by a notification.
+const CHANGING: [&str; 16] = ["add", "complete", "link", "unlink", "move", "use", "edit", "repeat", "defer", "start", "stop", "note", "tag", "untag", "import", "edit_tree"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any error a [`Graph`] method returns.
+const TODOS_ERROR: i64 = -32000;
+
+pub struct RpcError
+{
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>
+}
+
+impl RpcError
+{
+    fn new(code: i64, message: impl ToString) -> RpcError
+    {
+        RpcError { code, message: message.to_string(), data: None }
+    }
+}
+
+impl From<TodosError> for RpcError
+{
+    fn from(error: TodosError) -> RpcError
+    {
+        RpcError
+        {
+            code: TODOS_ERROR,
+            message: error.to_string(),
+            data: Some(json!({ "error": error.code() }))
+        }
+    }
+}
+
+/// One line of input, read but not yet carried out.
+pub struct Request
+{
+    /// `None` for notifications, which get no response.
+    pub id: Option<Value>,
+    pub method: String,
+    params: Map<String, Value>
+}
+
+impl Request
+{
+    /// Reads a request, or gives the response to send for a malformed one.
+    pub fn parse(line: &str) -> Result<Request, Value>
+    {
+        let value: Value = serde_json::from_str(line)
+            .map_err(|error| response(Value::Null, Err(RpcError::new(PARSE_ERROR, error))))?;
+        let id = value.get("id").cloned();
+        let invalid = |message: &str| response(id.clone().unwrap_or(Value::Null), Err(RpcError::new(INVALID_REQUEST, message)));
+
+        if value.get("jsonrpc") != Some(&json!("2.0"))
+        {
+            return Err(invalid("`jsonrpc` must be \"2.0\"."));
+        }
+        let method = value.get("method").and_then(Value::as_str).ok_or_else(|| invalid("`method` must be a string."))?;
+        let params = match value.get("params")
+        {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(params)) => params.clone(),
+            Some(_) => { return Err(invalid("`params` must be an object of named params.")); }
+        };
+
+        Ok(Request { id, method: method.to_string(), params })
+    }
+
+    /// Carries out the request, giving its response (unless it is a
+    /// notification) and whether it changed the graph.
+    pub fn call(&self, graph: &mut Graph) -> (Option<Value>, bool)
+    {
+        let result = call(graph, &self.method, &Params(&self.params));
+        let changed = result.is_ok() && CHANGING.contains(&&self.method[..]);
+        (self.id.clone().map(|id| response(id, result)), changed)
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value
+{
+    let mut response = json!({ "jsonrpc": "2.0", "id": id });
+    match result
+    {
+        Ok(result) => { response["result"] = result; },
+        Err(error) => {
+            response["error"] = json!({ "code": error.code, "message": error.message });
+            if let Some(data) = error.data
+            {
+                response["error"]["data"] = data;
+            }
+        }
+    }
+    response
+}
+
+/// Named params, checked as they are taken.
+struct Params<'a>(&'a Map<String, Value>);
+
+impl Params<'_>
+{
+    fn get(&self, name: &str) -> Option<&Value>
+    {
+        self.0.get(name).filter(|x| !x.is_null())
+    }
+
+    fn invalid(name: &str, expected: &str) -> RpcError
+    {
+        RpcError::new(INVALID_PARAMS, format!("`{}` must be {}.", name, expected))
+    }
+
+    fn id(&self, name: &str) -> Result<usize, RpcError>
+    {
+        self.optional_id(name)?.ok_or_else(|| Params::invalid(name, "a node id"))
+    }
+
+    fn optional_id(&self, name: &str) -> Result<Option<usize>, RpcError>
+    {
+        match self.get(name)
+        {
+            Some(value) => value.as_u64().map(|x| Some(x as usize)).ok_or_else(|| Params::invalid(name, "a node id")),
+            None => Ok(None)
+        }
+    }
+
+    fn ids(&self, name: &str) -> Result<Vec<usize>, RpcError>
+    {
+        self.get(name)
+            .and_then(Value::as_array)
+            .and_then(|x| x.iter().map(|x| x.as_u64().map(|x| x as usize)).collect())
+            .ok_or_else(|| Params::invalid(name, "a list of node ids"))
+    }
+
+    fn string(&self, name: &str) -> Result<String, RpcError>
+    {
+        self.optional_string(name)?.ok_or_else(|| Params::invalid(name, "a string"))
+    }
+
+    fn optional_string(&self, name: &str) -> Result<Option<String>, RpcError>
+    {
+        match self.get(name)
+        {
+            Some(value) => value.as_str().map(|x| Some(x.to_string())).ok_or_else(|| Params::invalid(name, "a string")),
+            None => Ok(None)
+        }
+    }
+
+    fn strings(&self, name: &str) -> Result<Vec<String>, RpcError>
+    {
+        self.get(name)
+            .and_then(Value::as_array)
+            .and_then(|x| x.iter().map(|x| x.as_str().map(String::from)).collect())
+            .ok_or_else(|| Params::invalid(name, "a list of strings"))
+    }
+
+    fn flag(&self, name: &str) -> Result<bool, RpcError>
+    {
+        match self.get(name)
+        {
+            Some(value) => value.as_bool().ok_or_else(|| Params::invalid(name, "true or false")),
+            None => Ok(false)
+        }
+    }
+
+    fn node_type(&self) -> Result<NodeType, RpcError>
+    {
+        match self.optional_string("type")?
+        {
+            Some(node_type) => NodeType::from_string(&node_type).ok_or_else(|| Params::invalid("type", "goal, condition or task")),
+            None => Ok(NodeType::Task)
+        }
+    }
+
+    fn format(&self) -> Result<Format, RpcError>
+    {
+        Format::from_string(&self.string("format")?).ok_or_else(|| Params::invalid("format", "a format `todos export` knows"))
+    }
+}
+
+fn nodes(graph: &Graph, ids: &[usize]) -> Value
+{
+    ids.iter().map(|x| graph.nodes()[*x].to_json()).collect()
+}
+
+/// Drops the colors from a view meant for the terminal.
+fn plain(text: &str) -> String
+{
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next()
+    {
+        if c == '\x1B'
+        {
+            chars.by_ref().find(|x| *x == 'm');
+        }
+        else
+        {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn call(graph: &mut Graph, method: &str, params: &Params) -> Result<Value, RpcError>
+{
+    let value = match method
+    {
+        "add" => {
+            let description = params.string("description")?;
+            let node_type = params.node_type()?;
+            let id = match params.optional_id("above")?
+            {
+                Some(above) => graph.add_node_above(description, node_type, above)?,
+                None => graph.add_node_to(description, node_type, params.optional_id("to")?)?
+            };
+            graph.node(id)?.to_json()
+        },
+        "complete" => {
+            let again = graph.complete(params.ids("ids")?, chrono::Local::now().date_naive())?;
+            json!({ "recurring": nodes(graph, &again) })
+        },
+        "link" => {
+            graph.link(&params.id("parent")?, &params.id("child")?)?;
+            Value::Null
+        },
+        "unlink" => {
+            graph.unlink(&params.id("parent")?, &params.id("child")?)?;
+            Value::Null
+        },
+        "move" => {
+            let id = params.id("id")?;
+            match params.optional_id("above")?
+            {
+                Some(above) => graph.move_node_above(id, above)?,
+                None => graph.move_node(id, params.optional_id("to")?)?
+            }
+            Value::Null
+        },
+        "use" => {
+            graph.set_effective_root(params.optional_id("id")?)?;
+            json!({ "id": graph.effective_root() })
+        },
+        "repeat" => {
+            let id = params.id("id")?;
+            let recurrence = match params.optional_string("rule")?
+            {
+                Some(rule) => Some(Recurrence::parse(&rule).ok_or_else(|| Params::invalid("rule", "a rule like `weekly on mon` or `every 3 days`"))?),
+                None => None
+            };
+            graph.set_recurrence(id, recurrence)?;
+            graph.node(id)?.to_json()
+        },
+        "defer" => {
+            let id = params.id("id")?;
+            let until = match params.optional_string("until")?
+            {
+                Some(until) => Some(dates::parse_day(&until, chrono::Local::now().date_naive()).ok_or_else(|| Params::invalid("until", "a day like 2024-03-01 or friday"))?),
+                None => None
+            };
+            graph.defer(id, until)?;
+            graph.node(id)?.to_json()
+        },
+        "start" => {
+            let id = params.id("id")?;
+            graph.start_timer(id, chrono::Utc::now())?;
+            graph.node(id)?.to_json()
+        },
+        "stop" => {
+            let (id, _) = graph.stop_timer(chrono::Utc::now())?;
+            graph.node(id)?.to_json()
+        },
+        "edit" => {
+            let id = params.id("id")?;
+            let description = params.string("description")?.trim().to_string();
+            if description.is_empty()
+            {
+                return Err(TodosError::Invalid(String::from("Descriptions can't be empty; nothing was changed.")).into());
+            }
+            graph.relabel(id, description)?;
+            graph.node(id)?.to_json()
+        },
+        "note" => {
+            let id = params.id("id")?;
+            graph.set_notes(id, params.optional_string("text")?)?;
+            graph.node(id)?.to_json()
+        },
+        "tag" => {
+            let id = params.id("id")?;
+            graph.tag(id, params.strings("tags")?)?;
+            graph.node(id)?.to_json()
+        },
+        "untag" => {
+            let id = params.id("id")?;
+            graph.untag(id, &params.strings("tags")?)?;
+            graph.node(id)?.to_json()
+        },
+        "import" => {
+            let report = formats::import(graph, params.format()?, &params.string("content")?, params.optional_id("under")?)?;
+            json!({ "added": report.added, "skipped": report.skipped, "warnings": report.warnings })
+        },
+        "export" => {
+            let exported = formats::export(graph, params.format()?, params.optional_id("under")?)?;
+            json!({ "content": exported.content, "warnings": exported.warnings })
+        },
+        "outline" => Value::from(outline::render(graph, params.optional_id("root")?)?),
+        "edit_tree" => {
+            let entries = outline::parse(&params.string("outline")?)?;
+            let summary = outline::apply(graph, params.optional_id("root")?, &entries)?;
+            json!({
+                "added": summary.added,
+                "removed": summary.removed,
+                "changed": summary.changed,
+                "linked": summary.linked,
+                "unlinked": summary.unlinked
+            })
+        },
+        "show" => {
+            match params.optional_id("id")?
+            {
+                Some(id) => {
+                    let mut out = String::new();
+                    graph.render_show(&mut out, &id, 0, params.flag("overwhelm")?, Some(id))?;
+                    Value::from(plain(&out))
+                },
+                None => Value::from(plain(&graph.render_todos(params.flag("overwhelm")?)))
+            }
+        },
+        "details" => Value::from(plain(&graph.render_details(params.id("id")?)?)),
+        "report_time" => {
+            let since = match params.optional_string("since")?
+            {
+                Some(since) => Some(dates::parse_past_day(&since, chrono::Local::now().date_naive()).ok_or_else(|| Params::invalid("since", "a day like 2024-03-01 or monday"))?),
+                None => None
+            };
+            // from the start of the day, here
+            let start = since.and_then(|x| x.and_time(chrono::NaiveTime::MIN).and_local_timezone(chrono::Local).earliest());
+            Value::from(graph.render_time_report(start.map(|x| x.with_timezone(&chrono::Utc)), chrono::Utc::now()))
+        },
+        "node" => graph.node(params.id("id")?)?.to_json(),
+        "nodes" => graph.nodes().iter().map(Node::to_json).collect(),
+        "leaves" => nodes(graph, &graph.actionable_leaves(params.optional_id("under")?)?),
+        "deferred" => nodes(graph, &graph.deferred(chrono::Local::now().date_naive())),
+        "search" => nodes(graph, &graph.search(&params.string("query")?)),
+        "ancestors" => nodes(graph, &graph.ancestors(params.id("id")?)?),
+        "running" => graph.running().map(|x| graph.nodes()[x].to_json()).unwrap_or(Value::Null),
+        "root" => json!({ "id": graph.effective_root() }),
+        _ => { return Err(RpcError::new(METHOD_NOT_FOUND, format!("There is no method `{}`.", method))); }
+    };
+    Ok(value)
+}
+
+/// Answers one line of input without taking the todos file's lock; what
+/// `run` does for each line, under the lock.
+pub fn handle(graph: &mut Graph, line: &str) -> (Option<Value>, bool)
+{
+    match Request::parse(line)
+    {
+        Ok(request) => request.call(graph),
+        Err(response) => (Some(response), false)
+    }
+}
+
+/// Answers requests from `input` until it ends. Each request works on the
+/// latest todos file under its lock, so other processes can use it too.
+pub fn run(graph: &mut Graph, input: impl BufRead, mut output: impl Write) -> Result<(), TodosError>
+{
+    for line in input.lines()
+    {
+        let line = line.map_err(|error| TodosError::io("stdin", error))?;
+        if line.trim().is_empty()
+        {
+            continue;
+        }
+
+        let (response, changed) = match Request::parse(&line)
+        {
+            Ok(request) => {
+                let (response, changed) = match graph.locked(|graph| request.call(graph))
+                {
+                    Ok(answer) => answer,
+                    Err(error) => (request.id.clone().map(|id| response(id, Err(error.into()))), false)
+                };
+                (response, Some(request.method).filter(|_| changed))
+            },
+            Err(response) => (Some(response), None)
+        };
+
+        let mut out = String::new();
+        if let Some(response) = response
+        {
+            out.push_str(&format!("{}\n", response));
+        }
+        if let Some(method) = changed
+        {
+            out.push_str(&format!("{}\n", json!({ "jsonrpc": "2.0", "method": "changed", "params": { "method": method } })));
+        }
+        output.write_all(out.as_bytes())
+            .and_then(|_| output.flush())
+            .map_err(|error| TodosError::io("stdout", error))?;
+    }
+    Ok(())
+}
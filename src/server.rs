@@ -0,0 +1,291 @@
+//! A JSON API over HTTP, served on localhost by `todos serve` for scripts,
+//! editor plugins and the like.
+//!
+//! | Request                          | Does                                      |
+//! |----------------------------------|-------------------------------------------|
+//! | `GET /nodes`                     | every node                                |
+//! | `GET /nodes/{id}`                | one node                                  |
+//! | `POST /nodes`                    | adds `{"description", "type"?, "to"?}`    |
+//! | `PATCH /nodes/{id}`              | relabels it to `{"description"}`          |
+//! | `POST /nodes/{id}/complete`      | completes it, giving any that recur again |
+//! | `GET /edges`                     | every `{"parent", "child"}` link          |
+//! | `POST /edges`                    | links `{"parent", "child"}`               |
+//! | `DELETE /edges/{parent}/{child}` | unlinks them                              |
+//! | `GET /leaves`                    | the tasks `todos` shows as actionable     |
+//! | `GET /root`, `PUT /root`         | the effective root, `{"id"}` or null      |
+//!
+//! Failures are `{"error", "message"}`, with the [`TodosError::code`] and
+//! message of the error the [`Graph`] method returned.
+//!
+//! Requests must be addressed to localhost and, if they have a body, be
+//! `application/json`; web pages the user visits could otherwise change the
+//! graph from their browser.
+
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::{Node, NodeType};
+
+use serde_json::{json, Value};
+
+pub struct Response
+{
+    pub status: u16,
+    /// `Value::Null` for responses without a body.
+    pub body: Value
+}
+
+impl Response
+{
+    fn ok(body: Value) -> Response
+    {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, code: &str, message: impl ToString) -> Response
+    {
+        Response { status, body: json!({ "error": code, "message": message.to_string() }) }
+    }
+}
+
+impl From<TodosError> for Response
+{
+    fn from(error: TodosError) -> Response
+    {
+        let status = match error
+        {
+            TodosError::NotFound(_) => 404,
+            TodosError::Cycle(_) => 409,
+            TodosError::Invalid(_) => 400,
+            _ => 500
+        };
+        Response::error(status, error.code(), &error)
+    }
+}
+
+/// Turns away requests a browser might send on behalf of some other site:
+/// those for a host other than localhost (as with DNS rebinding), from a
+/// foreign `Origin`, or with a body that isn't `application/json`, which
+/// browsers won't send across sites without asking first.
+pub fn screen(host: Option<&str>, origin: Option<&str>, content_type: Option<&str>, body: &str) -> Option<Response>
+{
+    let local = |host: &str| {
+        let name = match host.rsplit_once(':')
+        {
+            Some((name, port)) if port.chars().all(|x| x.is_ascii_digit()) => name,
+            _ => host
+        };
+        ["localhost", "127.0.0.1", "[::1]"].contains(&&name.to_lowercase()[..])
+    };
+
+    if !host.map(local).unwrap_or(false)
+    {
+        return Some(Response::error(403, "forbidden", "Only requests for localhost are served."));
+    }
+    if let Some(origin) = origin
+    {
+        let from = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://")).unwrap_or("");
+        if !local(from)
+        {
+            return Some(Response::error(403, "forbidden", format!("Requests from {} aren't served.", origin)));
+        }
+    }
+    let json = content_type
+        .and_then(|x| x.split(';').next())
+        .map(|x| x.trim().eq_ignore_ascii_case("application/json"))
+        .unwrap_or(false);
+    if !body.trim().is_empty() && !json
+    {
+        return Some(Response::error(415, "unsupported_media_type", "Request bodies must be sent as `Content-Type: application/json`."));
+    }
+    None
+}
+
+/// Answers one request, changing `graph` if it asks to.
+pub fn handle(graph: &mut Graph, method: &str, path: &str, body: &str) -> Response
+{
+    let path = path.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+    let result = match (method, &segments[..])
+    {
+        ("GET", ["nodes"]) => Ok(Response::ok(graph.nodes().iter().map(Node::to_json).collect())),
+        ("GET", ["nodes", id]) => {
+            id_from(id).and_then(|id| graph.node(id)).map(|node| Response::ok(node.to_json()))
+        },
+        ("POST", ["nodes"]) => add(graph, body),
+        ("PATCH", ["nodes", id]) => {
+            id_from(id)
+                .and_then(|id| {
+                    let description = string_field(&parse(body)?, "description")?;
+                    graph.relabel(id, description)?;
+                    Ok(Response::ok(graph.node(id)?.to_json()))
+                })
+        },
+        ("POST", ["nodes", id, "complete"]) => {
+            id_from(id)
+                .and_then(|id| graph.complete(vec![id], chrono::Local::now().date_naive()))
+                .map(|again| Response::ok(json!({ "recurring": again.iter().map(|x| graph.nodes()[*x].to_json()).collect::<Vec<_>>() })))
+        },
+        ("GET", ["edges"]) => {
+            let edges: Vec<Value> = graph.nodes()
+                .iter()
+                .flat_map(|node| node.deps.iter().map(move |child| json!({ "parent": node.id, "child": child })))
+                .collect();
+            Ok(Response::ok(Value::from(edges)))
+        },
+        ("POST", ["edges"]) => {
+            parse(body).and_then(|body| {
+                let parent = id_field(&body, "parent")?;
+                let child = id_field(&body, "child")?;
+                graph.link(&parent, &child)?;
+                Ok(Response { status: 201, body: json!({ "parent": parent, "child": child }) })
+            })
+        },
+        ("DELETE", ["edges", parent, child]) => {
+            id_from(parent)
+                .and_then(|parent| graph.unlink(&parent, &id_from(child)?))
+                .map(|_| Response { status: 204, body: Value::Null })
+        },
+        ("GET", ["leaves"]) => {
+            graph.actionable_leaves(None)
+                .map(|leaves| Response::ok(leaves.iter().map(|x| graph.nodes()[*x].to_json()).collect()))
+        },
+        ("GET", ["root"]) => Ok(Response::ok(json!({ "id": graph.effective_root() }))),
+        ("PUT", ["root"]) => {
+            parse(body).and_then(|body| {
+                let id = match body.get("id")
+                {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(id_field(&body, "id")?)
+                };
+                graph.set_effective_root(id)?;
+                Ok(Response::ok(json!({ "id": id })))
+            })
+        },
+        (_, ["nodes"]) | (_, ["nodes", _]) | (_, ["nodes", _, "complete"]) | (_, ["edges"]) |
+        (_, ["edges", _, _]) | (_, ["leaves"]) | (_, ["root"]) => {
+            Ok(Response::error(405, "method_not_allowed", format!("{} isn't supported for {}.", method, path)))
+        },
+        _ => Ok(Response::error(404, "no_route", format!("There is nothing at {}.", path)))
+    };
+
+    match result
+    {
+        Ok(response) => response,
+        Err(error) => Response::from(error)
+    }
+}
+
+fn add(graph: &mut Graph, body: &str) -> Result<Response, TodosError>
+{
+    let body = parse(body)?;
+    let description = string_field(&body, "description")?;
+    let node_type = match body.get("type")
+    {
+        None | Some(Value::Null) => NodeType::Task,
+        Some(value) => value.as_str().and_then(NodeType::from_string).ok_or_else(|| {
+            TodosError::Invalid(String::from("`type` must be goal, condition or task."))
+        })?
+    };
+    let to = match body.get("to")
+    {
+        None | Some(Value::Null) => None,
+        Some(_) => Some(id_field(&body, "to")?)
+    };
+
+    let id = graph.add_node_to(description, node_type, to)?;
+    Ok(Response { status: 201, body: graph.node(id)?.to_json() })
+}
+
+fn parse(body: &str) -> Result<Value, TodosError>
+{
+    let value: Value = serde_json::from_str(body)
+        .map_err(|error| TodosError::Invalid(format!("The request body isn't JSON: {}", error)))?;
+    if !value.is_object()
+    {
+        return Err(TodosError::Invalid(String::from("The request body must be a JSON object.")));
+    }
+    Ok(value)
+}
+
+fn id_from(segment: &str) -> Result<usize, TodosError>
+{
+    segment.parse().map_err(|_| TodosError::Invalid(format!("{} isn't a node id.", segment)))
+}
+
+fn id_field(body: &Value, name: &str) -> Result<usize, TodosError>
+{
+    body.get(name)
+        .and_then(Value::as_u64)
+        .map(|x| x as usize)
+        .ok_or_else(|| TodosError::Invalid(format!("`{}` must be a node id.", name)))
+}
+
+fn string_field(body: &Value, name: &str) -> Result<String, TodosError>
+{
+    match body.get(name).and_then(Value::as_str).map(str::trim)
+    {
+        Some(value) if !value.is_empty() => Ok(value.to_string()),
+        _ => Err(TodosError::Invalid(format!("`{}` must be a non-empty string.", name)))
+    }
+}
+
+/// Serves the API on `127.0.0.1:port` until the process is stopped. With a
+/// `token`, requests must carry an `Authorization: Bearer <token>` header.
+///
+/// Each request works on the latest todos file under its lock, so the CLI
+/// can be used alongside.
+pub fn serve(graph: &mut Graph, port: u16, token: Option<&str>) -> Result<(), TodosError>
+{
+    let server = tiny_http::Server::http(("127.0.0.1", port))
+        .map_err(|error| TodosError::Invalid(format!("Unable to listen on port {}: {}", port, error)))?;
+    println!("Serving todos on http://127.0.0.1:{}/. Press Ctrl-C to stop.", port);
+
+    for mut request in server.incoming_requests()
+    {
+        let authorized = match token
+        {
+            Some(token) => request.headers()
+                .iter()
+                .any(|x| x.field.equiv("Authorization") && x.value.as_str() == format!("Bearer {}", token)),
+            None => true
+        };
+
+        let header = |name: &'static str| {
+            request.headers().iter().find(|x| x.field.equiv(name)).map(|x| x.value.as_str().to_string())
+        };
+        let (host, origin, content_type) = (header("Host"), header("Origin"), header("Content-Type"));
+
+        let mut body = String::new();
+        let response = if !authorized
+        {
+            Response::error(401, "unauthorized", "A valid `Authorization: Bearer <token>` header is required.")
+        }
+        else if let Err(error) = request.as_reader().read_to_string(&mut body)
+        {
+            Response::error(400, "invalid", format!("The request body couldn't be read: {}", error))
+        }
+        else if let Some(rejection) = screen(host.as_deref(), origin.as_deref(), content_type.as_deref(), &body)
+        {
+            rejection
+        }
+        else
+        {
+            let method = request.method().as_str().to_uppercase();
+            let url = request.url().to_string();
+            match graph.locked(|graph| handle(graph, &method, &url, &body))
+            {
+                Ok(response) => response,
+                Err(error) => Response::from(error)
+            }
+        };
+
+        let content = if response.body.is_null() { String::new() } else { response.body.to_string() };
+        let header = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+        let reply = tiny_http::Response::from_string(content)
+            .with_status_code(response.status)
+            .with_header(header);
+        // a client hanging up isn't the server's problem
+        let _ = request.respond(reply);
+    }
+    Ok(())
+}
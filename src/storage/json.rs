@@ -0,0 +1,84 @@
+use super::{Changes, Storage};
+use crate::error::TodosError;
+use crate::graph::{Graph, SCHEMA_VERSION};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Keeps the whole graph in a single JSON file, rewritten on every save.
+pub struct JsonStorage
+{
+    path: PathBuf
+}
+
+impl JsonStorage
+{
+    pub fn new(path: PathBuf) -> JsonStorage
+    {
+        JsonStorage { path }
+    }
+
+    /// Upgrades a todos file written by an older version of todos to the
+    /// current `SCHEMA_VERSION`, one version at a time.
+    fn migrate(&self, value: &mut serde_json::Value) -> Result<(), TodosError>
+    {
+        let object = value.as_object_mut().ok_or_else(|| TodosError::parse(&self.path, "not a JSON object"))?;
+        let mut version = object.get("version").and_then(|x| x.as_u64()).unwrap_or(0);
+
+        if version > SCHEMA_VERSION
+        {
+            return Err(TodosError::UnsupportedVersion {
+                path: self.path.clone(),
+                found: version,
+                supported: SCHEMA_VERSION
+            });
+        }
+
+        if version == 0
+        {
+            // files created by the old install script were just `{"nodes":[]}`
+            object.entry("effective_root").or_insert(serde_json::Value::Null);
+            version = 1;
+        }
+
+        object.insert("version".to_string(), version.into());
+        Ok(())
+    }
+}
+
+impl Storage for JsonStorage
+{
+    fn load(&mut self) -> Result<Graph, TodosError>
+    {
+        let content = fs::read_to_string(&self.path).map_err(|error| TodosError::io(&self.path, error))?;
+        let mut value: serde_json::Value = serde_json::from_str(&content).map_err(|error| TodosError::parse(&self.path, error))?;
+
+        self.migrate(&mut value)?;
+
+        serde_json::from_value(value).map_err(|error| TodosError::parse(&self.path, error))
+    }
+
+    fn save(&mut self, graph: &Graph, changes: &Changes) -> Result<(), TodosError>
+    {
+        // leave the file alone, so its modification time means something
+        // to `todos watch`
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
+
+        // write next to the real file and swap it in, so a crash mid-write
+        // never leaves a truncated todos file behind
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp = self.path.with_file_name(temp_name);
+
+        fs::write(&temp, serde_json::to_string(graph).unwrap()).map_err(|error| TodosError::io(&temp, error))?;
+        fs::rename(&temp, &self.path).map_err(|error| TodosError::io(&self.path, error))
+    }
+
+    fn location(&self) -> &Path
+    {
+        &self.path
+    }
+}
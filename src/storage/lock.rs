@@ -0,0 +1,121 @@
+use crate::error::TodosError;
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// How long to wait for another process to finish with a todos file.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// A lock untouched for this long was left behind by a process that died.
+const STALE: Duration = Duration::from_secs(30);
+/// How often a held lock is touched, so it never looks stale.
+const REFRESH: Duration = Duration::from_secs(5);
+
+/// Keeps other `todos` processes (the CLI, `todos serve`, ...) from changing
+/// a todos file at the same time, by holding `<file>.lock` until dropped.
+///
+/// The lock is advisory: it only works between processes that take it,
+/// which every command that changes the graph does.
+pub struct Lock
+{
+    path: PathBuf,
+    stop: Option<Sender<()>>,
+    refresher: Option<JoinHandle<()>>
+}
+
+impl Lock
+{
+    /// Takes the lock for `todos_file`, waiting for whoever holds it.
+    pub fn acquire(todos_file: &Path) -> Result<Lock, TodosError>
+    {
+        let mut path = todos_file.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let started = SystemTime::now();
+        loop
+        {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path)
+            {
+                Ok(mut file) => {
+                    // only for people wondering who holds it
+                    let _ = write!(file, "{}", std::process::id());
+                    let (stop, stopped) = mpsc::channel();
+                    let refresher = thread::spawn(move || {
+                        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(REFRESH)
+                        {
+                            let _ = file.set_modified(SystemTime::now());
+                        }
+                    });
+                    return Ok(Lock { path, stop: Some(stop), refresher: Some(refresher) });
+                },
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {},
+                Err(error) => { return Err(TodosError::io(&path, error)); }
+            }
+
+            if is_stale(&path)
+            {
+                take_over(&path);
+                continue;
+            }
+            if started.elapsed().map(|x| x > TIMEOUT).unwrap_or(true)
+            {
+                return Err(TodosError::Invalid(format!(
+                    "{} is being changed by another todos process. If none is running, remove {}.",
+                    todos_file.display(),
+                    path.display()
+                )));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+fn is_stale(path: &Path) -> bool
+{
+    fs::metadata(path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.elapsed().ok())
+        .map(|x| x > STALE)
+        .unwrap_or(false)
+}
+
+/// Removes a stale lock. Another waiter may have found it stale too, and
+/// already replaced it with a lock of its own, so the lock is first moved
+/// out of the way and only removed if what was moved is still stale.
+fn take_over(path: &Path)
+{
+    let mut moved = path.as_os_str().to_owned();
+    moved.push(format!(".stale-{}", uuid::Uuid::new_v4().simple()));
+    let moved = PathBuf::from(moved);
+
+    if fs::rename(path, &moved).is_err()
+    {
+        // gone already
+        return;
+    }
+    if !is_stale(&moved)
+    {
+        // someone's live lock: put it back, unless yet another waiter has
+        // taken the lock in the meantime
+        let _ = fs::hard_link(&moved, path);
+    }
+    let _ = fs::remove_file(&moved);
+}
+
+impl Drop for Lock
+{
+    fn drop(&mut self)
+    {
+        drop(self.stop.take());
+        if let Some(refresher) = self.refresher.take()
+        {
+            let _ = refresher.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
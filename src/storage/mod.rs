@@ -0,0 +1,180 @@
+//! Where a [`Graph`](crate::Graph) is kept between runs.
+//!
+//! A graph is loaded from and saved through a [`Storage`] backend. The JSON
+//! file backend is the default; the SQLite backend (behind the `sqlite`
+//! feature) keeps nodes and links as rows and only rewrites what changed.
+//! Which backend a todos file uses is detected from its contents, so the
+//! same path works either way.
+//!
+//! Processes sharing a todos file take its [`Lock`] while they change it.
+
+mod json;
+mod lock;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::json::JsonStorage;
+pub use self::lock::Lock;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStorage;
+
+use crate::config::Config;
+use crate::error::TodosError;
+use crate::graph::Graph;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Every SQLite database starts with these bytes.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+pub trait Storage
+{
+    /// Reads the whole graph. The returned graph has no storage or config
+    /// attached yet; `Graph::load` takes care of that.
+    fn load(&mut self) -> Result<Graph, TodosError>;
+
+    /// Persists `graph`. `changes` describes what changed since the graph
+    /// was loaded or last saved, for backends that can save incrementally.
+    fn save(&mut self, graph: &Graph, changes: &Changes) -> Result<(), TodosError>;
+
+    fn location(&self) -> &Path;
+}
+
+/// What has changed in a graph since it was last loaded or saved.
+#[derive(Clone, Default)]
+pub struct Changes
+{
+    /// Everything may have changed, so the graph should be written in full.
+    pub all: bool,
+    /// Nodes whose fields or links changed, including newly added nodes.
+    /// Removed nodes are implied by the graph having fewer nodes than before.
+    pub nodes: BTreeSet<usize>,
+    pub effective_root: bool
+}
+
+impl Changes
+{
+    pub fn is_empty(&self) -> bool
+    {
+        !self.all && self.nodes.is_empty() && !self.effective_root
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend
+{
+    Json,
+    Sqlite
+}
+
+impl fmt::Display for Backend
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite"
+        })
+    }
+}
+
+impl Backend
+{
+    pub fn from_string(s: &str) -> Option<Backend>
+    {
+        match s
+        {
+            "json" => Some(Backend::Json),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None
+        }
+    }
+
+    /// Works out which backend wrote the file at `path`. Files that don't
+    /// exist yet are treated as JSON.
+    pub fn detect(path: &Path) -> Result<Backend, TodosError>
+    {
+        let mut file = match fs::File::open(path)
+        {
+            Ok(file) => file,
+            Err(_) => { return Ok(Backend::Json); }
+        };
+
+        let mut header = Vec::new();
+        file.by_ref()
+            .take(SQLITE_HEADER.len() as u64)
+            .read_to_end(&mut header)
+            .map_err(|error| TodosError::io(path, error))?;
+
+        if header == SQLITE_HEADER
+        {
+            Ok(Backend::Sqlite)
+        }
+        else
+        {
+            Ok(Backend::Json)
+        }
+    }
+}
+
+/// Opens the todos file at `path` with the backend that wrote it.
+pub fn open(path: &Path) -> Result<Box<dyn Storage>, TodosError>
+{
+    create(path, Backend::detect(path)?)
+}
+
+/// Opens (or creates) a todos file at `path` with the given backend.
+pub fn create(path: &Path, backend: Backend) -> Result<Box<dyn Storage>, TodosError>
+{
+    match backend
+    {
+        Backend::Json => Ok(Box::new(JsonStorage::new(path.to_path_buf()))),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open(path.to_path_buf())?)),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => Err(TodosError::Invalid(
+            "This build of todos doesn't support SQLite; rebuild it with the `sqlite` feature.".to_string()
+        ))
+    }
+}
+
+/// Converts the todos file at `path` to another backend in place. The
+/// original is kept next to it with `.bak` appended, and its path returned.
+pub fn migrate(path: &Path, to: Backend, config: Config) -> Result<PathBuf, TodosError>
+{
+    let from = Backend::detect(path)?;
+    if from == to
+    {
+        return Err(TodosError::Invalid(format!("{} already uses the {} backend.", path.display(), to)));
+    }
+
+    let mut graph = Graph::load(open(path)?, config)?;
+
+    let converted = append_to_file_name(path, ".migrating");
+    let backup = append_to_file_name(path, ".bak");
+
+    // start from scratch in case an earlier attempt was interrupted
+    if converted.exists()
+    {
+        fs::remove_file(&converted).map_err(|error| TodosError::io(&converted, error))?;
+    }
+    graph.set_storage(create(&converted, to)?);
+    graph.save()?;
+    drop(graph);
+
+    fs::rename(path, &backup).map_err(|error| TodosError::io(path, error))?;
+    fs::rename(&converted, path).map_err(|error| TodosError::io(&converted, error))?;
+
+    Ok(backup)
+}
+
+fn append_to_file_name(path: &Path, suffix: &str) -> PathBuf
+{
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
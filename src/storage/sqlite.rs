@@ -0,0 +1,270 @@
+extern crate rusqlite;
+
+use super::{Changes, Storage};
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::Node;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+/// Version of the database layout written by this build, kept in
+/// `PRAGMA user_version` and bumped like
+/// [`SCHEMA_VERSION`](crate::graph::SCHEMA_VERSION), with a step in `migrate`.
+const DATABASE_VERSION: u64 = 2;
+
+/// Node fields that get a column of their own; everything else about a node
+/// lives in the `extra` column as a JSON object.
+const NODE_COLUMNS: [&str; 6] = ["id", "node_type", "description", "due_date", "deps", "parents"];
+
+/// Keeps the graph in an SQLite database with a row per node and per link,
+/// so saving only touches the rows that changed.
+pub struct SqliteStorage
+{
+    path: PathBuf,
+    connection: Connection
+}
+
+impl SqliteStorage
+{
+    /// Opens the database at `path`, creating it if it doesn't exist.
+    pub fn open(path: PathBuf) -> Result<SqliteStorage, TodosError>
+    {
+        let connection = Connection::open(&path).map_err(|error| database_error(&path, error))?;
+        let mut storage = SqliteStorage { path, connection };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Creates the tables in a new database, or upgrades a database written
+    /// by an older version of todos one version at a time.
+    fn migrate(&mut self) -> Result<(), TodosError>
+    {
+        let mut version: u64 = self.connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|error| database_error(&self.path, error))?;
+
+        if version > DATABASE_VERSION
+        {
+            return Err(TodosError::UnsupportedVersion {
+                path: self.path.clone(),
+                found: version,
+                supported: DATABASE_VERSION
+            });
+        }
+
+        if version == 0
+        {
+            self.connection.execute_batch("
+                CREATE TABLE nodes (
+                    id INTEGER PRIMARY KEY,
+                    node_type TEXT NOT NULL,
+                    description TEXT NOT NULL,
+                    due_date TEXT,
+                    extra TEXT NOT NULL DEFAULT '{}'
+                );
+                CREATE TABLE edges (
+                    parent INTEGER NOT NULL,
+                    child INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    PRIMARY KEY (parent, child)
+                );
+                CREATE INDEX edges_by_child ON edges (child);
+                CREATE TABLE meta (
+                    key TEXT PRIMARY KEY,
+                    value TEXT
+                );
+            ").map_err(|error| database_error(&self.path, error))?;
+            version = 1;
+        }
+        if version == 1
+        {
+            // where a link comes among the child's parents, which version 1
+            // read back in the order of the parents' ids
+            self.connection.execute_batch("
+                ALTER TABLE edges ADD COLUMN parent_position INTEGER NOT NULL DEFAULT 0;
+                UPDATE edges SET parent_position = parent;
+            ").map_err(|error| database_error(&self.path, error))?;
+            version = 2;
+        }
+
+        self.connection
+            .pragma_update(None, "user_version", version)
+            .map_err(|error| database_error(&self.path, error))
+    }
+
+    fn read(&self) -> rusqlite::Result<Result<Graph, TodosError>>
+    {
+        let effective_root: Option<String> = self.connection
+            .query_row("SELECT value FROM meta WHERE key = 'effective_root'", [], |row| row.get(0))
+            .optional()?
+            .flatten();
+
+        let mut nodes = Vec::new();
+        let mut statement = self.connection.prepare("SELECT id, node_type, description, due_date, extra FROM nodes ORDER BY id")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            let extra: String = row.get(4)?;
+            let mut object = match serde_json::from_str::<serde_json::Value>(&extra)
+            {
+                Ok(serde_json::Value::Object(object)) => object,
+                _ => { return Ok(Err(TodosError::parse(&self.path, format!("extra data of node {} is not a JSON object", row.get::<_, i64>(0)?)))); }
+            };
+            object.insert("id".to_string(), row.get::<_, i64>(0)?.into());
+            object.insert("node_type".to_string(), row.get::<_, String>(1)?.into());
+            object.insert("description".to_string(), row.get::<_, String>(2)?.into());
+            object.insert("due_date".to_string(), row.get::<_, Option<String>>(3)?.into());
+            object.insert("deps".to_string(), serde_json::Value::Array(vec![]));
+            object.insert("parents".to_string(), serde_json::Value::Array(vec![]));
+
+            match serde_json::from_value::<Node>(serde_json::Value::Object(object))
+            {
+                Ok(node) => nodes.push(node),
+                Err(error) => { return Ok(Err(TodosError::parse(&self.path, error))); }
+            }
+        }
+
+        let mut statement = self.connection.prepare("SELECT parent, child FROM edges ORDER BY parent, position")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            let parent: usize = row.get(0)?;
+            let child: usize = row.get(1)?;
+            if parent >= nodes.len() || child >= nodes.len()
+            {
+                return Ok(Err(TodosError::DanglingLink { node: parent, missing: child.max(parent) }));
+            }
+            nodes[parent].deps.insert(child);
+        }
+
+        // the same links again, in the order of each child's parents
+        let mut statement = self.connection.prepare("SELECT parent, child FROM edges ORDER BY child, parent_position, parent")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            let parent: usize = row.get(0)?;
+            let child: usize = row.get(1)?;
+            nodes[child].parents.insert(parent);
+        }
+
+        let effective_root = match effective_root.map(|x| x.parse::<usize>())
+        {
+            Some(Ok(root)) => Some(root),
+            Some(Err(error)) => { return Ok(Err(TodosError::parse(&self.path, error))); },
+            None => None
+        };
+
+        Ok(Ok(Graph::from_parts(effective_root, nodes)))
+    }
+
+    fn write(&mut self, graph: &Graph, changes: &Changes) -> rusqlite::Result<()>
+    {
+        let transaction = self.connection.transaction()?;
+        let nodes = graph.nodes();
+
+        if changes.all
+        {
+            transaction.execute("DELETE FROM nodes", [])?;
+            transaction.execute("DELETE FROM edges", [])?;
+        }
+        else
+        {
+            // ids are always 0..len, so anything past the end was removed
+            transaction.execute("DELETE FROM nodes WHERE id >= ?1", params![nodes.len()])?;
+            transaction.execute("DELETE FROM edges WHERE parent >= ?1 OR child >= ?1", params![nodes.len()])?;
+        }
+
+        {
+            let mut upsert = transaction.prepare("
+                INSERT OR REPLACE INTO nodes (id, node_type, description, due_date, extra)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ")?;
+            let mut clear_edges = transaction.prepare("DELETE FROM edges WHERE parent = ?1")?;
+            let mut insert_edge = transaction.prepare("
+                INSERT INTO edges (parent, child, position, parent_position) VALUES (?1, ?2, ?3, ?4)
+            ")?;
+            let mut move_edge = transaction.prepare("UPDATE edges SET parent_position = ?3 WHERE parent = ?1 AND child = ?2")?;
+
+            let changed: Box<dyn Iterator<Item = &Node>> = if changes.all
+            {
+                Box::new(nodes.iter())
+            }
+            else
+            {
+                Box::new(changes.nodes.iter().filter_map(|id| nodes.get(*id)))
+            };
+
+            for node in changed
+            {
+                let mut object = match serde_json::to_value(node).unwrap()
+                {
+                    serde_json::Value::Object(object) => object,
+                    _ => unreachable!("nodes serialize to objects")
+                };
+                for column in NODE_COLUMNS.iter()
+                {
+                    object.remove(*column);
+                }
+
+                upsert.execute(params![
+                    node.id,
+                    serde_json::to_value(&node.node_type).unwrap().as_str(),
+                    node.description,
+                    node.due_date,
+                    serde_json::Value::Object(object).to_string()
+                ])?;
+
+                // a node owns the links to its children
+                clear_edges.execute(params![node.id])?;
+                for (position, child) in node.deps.iter().enumerate()
+                {
+                    let parent_position = nodes[*child].parents.iter().position(|x| *x == node.id);
+                    insert_edge.execute(params![node.id, child, position, parent_position])?;
+                }
+                // and the links from its parents may have moved among them
+                for (parent_position, parent) in node.parents.iter().enumerate()
+                {
+                    move_edge.execute(params![parent, node.id, parent_position])?;
+                }
+            }
+        }
+
+        if changes.all || changes.effective_root
+        {
+            transaction.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('effective_root', ?1)",
+                params![graph.effective_root().map(|x| x.to_string())]
+            )?;
+        }
+
+        transaction.commit()
+    }
+}
+
+fn database_error(path: &Path, error: rusqlite::Error) -> TodosError
+{
+    TodosError::Database { path: path.to_path_buf(), message: error.to_string() }
+}
+
+impl Storage for SqliteStorage
+{
+    fn load(&mut self) -> Result<Graph, TodosError>
+    {
+        self.read().map_err(|error| database_error(&self.path, error))?
+    }
+
+    fn save(&mut self, graph: &Graph, changes: &Changes) -> Result<(), TodosError>
+    {
+        if changes.is_empty()
+        {
+            return Ok(());
+        }
+        self.write(graph, changes).map_err(|error| database_error(&self.path, error))
+    }
+
+    fn location(&self) -> &Path
+    {
+        &self.path
+    }
+}
@@ -0,0 +1,602 @@
+//! `todos tui`: a full-screen, navigable tree of the graph.
+//!
+//! [`App`] holds what is on screen and turns keys into changes made through
+//! the [`Graph`] API, saving after each one, and [`run`] connects it to the
+//! terminal. Keeping the two apart means the interface can be driven
+//! without a terminal, as the tests do.
+
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::NodeType;
+use crate::outline;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// The keys the interface responds to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key
+{
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Esc
+}
+
+/// A line of the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row
+{
+    pub id: usize,
+    pub level: usize,
+    /// Whether the node has children, shown or not.
+    pub children: bool,
+    pub expanded: bool
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Mode
+{
+    Normal,
+    /// Typing a description for a new node under a node (or at the top).
+    Add { under: Option<usize>, text: String },
+    Relabel { id: usize, text: String },
+    /// Picking the second node of a link, or of a link to remove.
+    Link { parent: usize },
+    Unlink { parent: usize },
+    /// Completing a node with children removes them too, so it is confirmed.
+    Complete { id: usize }
+}
+
+const HELP: &str = "j/k move  h/l fold  a add  r relabel  c complete  L link  U unlink  o overwhelm  b backlog  u use  R root  q quit";
+
+/// How many rows page up and page down move.
+const PAGE: usize = 10;
+
+pub struct App
+{
+    overwhelm: bool,
+    /// Nodes whose children are shown when they would be hidden, or hidden
+    /// when they would be shown.
+    toggled: HashSet<usize>,
+    /// The index of the selected row.
+    selected: usize,
+    mode: Mode,
+    status: String,
+    quit: bool
+}
+
+impl Default for App
+{
+    fn default() -> App
+    {
+        App::new()
+    }
+}
+
+impl App
+{
+    pub fn new() -> App
+    {
+        App
+        {
+            overwhelm: false,
+            toggled: HashSet::new(),
+            selected: 0,
+            mode: Mode::Normal,
+            status: String::new(),
+            quit: false
+        }
+    }
+
+    /// Whether the user asked to leave.
+    pub fn quit(&self) -> bool
+    {
+        self.quit
+    }
+
+    /// The message shown at the bottom, like the result of the last change.
+    pub fn status(&self) -> &str
+    {
+        &self.status
+    }
+
+    /// Forgets what refers to nodes by id, for when another process changed
+    /// the graph: removing a node gives its id to another one.
+    pub fn graph_changed(&mut self)
+    {
+        self.toggled.clear();
+        if self.mode != Mode::Normal
+        {
+            self.mode = Mode::Normal;
+            self.status = String::from("The todos file was changed elsewhere; please start over.");
+        }
+    }
+
+    /// The node on the selected row.
+    pub fn selected(&self, graph: &Graph) -> Option<usize>
+    {
+        self.rows(graph).get(self.selected).map(|x| x.id)
+    }
+
+    /// The tree as shown: from the effective root (or every node without
+    /// parents), leaving out nodes `todos` leaves out unless overwhelmed,
+    /// and the children of collapsed nodes.
+    pub fn rows(&self, graph: &Graph) -> Vec<Row>
+    {
+        let mut rows = Vec::new();
+        match graph.effective_root()
+        {
+            Some(root) if graph.node(root).is_ok() => self.add_rows(graph, &mut rows, root, 0, Some(root)),
+            Some(_) => {},
+            None => {
+                for node in graph.nodes().iter().filter(|x| x.parents.is_empty())
+                {
+                    self.add_rows(graph, &mut rows, node.id, 0, None);
+                }
+            }
+        }
+        rows
+    }
+
+    fn add_rows(&self, graph: &Graph, rows: &mut Vec<Row>, id: usize, mut level: usize, start: Option<usize>)
+    {
+        let node = &graph.nodes()[id];
+        if !self.overwhelm && start != Some(id) && node.is_deferred(chrono::Local::now().date_naive())
+        {
+            return;
+        }
+        let shown = self.overwhelm || node.node_type == NodeType::Goal || node.deps.is_empty();
+        let expanded = !shown || self.expanded(graph, id, start);
+        if shown
+        {
+            rows.push(Row { id, level, children: !node.deps.is_empty(), expanded });
+            level += 1;
+        }
+        if expanded
+        {
+            for dep in &node.deps
+            {
+                self.add_rows(graph, rows, *dep, level, start);
+            }
+        }
+    }
+
+    fn expanded(&self, graph: &Graph, id: usize, start: Option<usize>) -> bool
+    {
+        // the backlog starts out collapsed, as `todos` hides it
+        let collapsed = !self.overwhelm && is_backlog(graph, id) && start != Some(id);
+        collapsed == self.toggled.contains(&id)
+    }
+
+    /// Selects the first row showing `id`, if there is one.
+    fn select_node(&mut self, graph: &Graph, id: usize)
+    {
+        if let Some(row) = self.rows(graph).iter().position(|x| x.id == id)
+        {
+            self.selected = row;
+        }
+    }
+
+    pub fn handle_key(&mut self, graph: &mut Graph, key: Key)
+    {
+        let rows = self.rows(graph);
+        let current = rows.get(self.selected).cloned();
+
+        match std::mem::replace(&mut self.mode, Mode::Normal)
+        {
+            Mode::Normal => self.normal_key(graph, key, &rows, current),
+            Mode::Add { under, mut text } => {
+                match key
+                {
+                    Key::Enter => self.add(graph, under, &text),
+                    Key::Esc => { self.status.clear(); },
+                    Key::Backspace => {
+                        text.pop();
+                        self.mode = Mode::Add { under, text };
+                    },
+                    Key::Char(c) => {
+                        text.push(c);
+                        self.mode = Mode::Add { under, text };
+                    },
+                    _ => { self.mode = Mode::Add { under, text }; }
+                }
+            },
+            Mode::Relabel { id, mut text } => {
+                match key
+                {
+                    Key::Enter if !text.trim().is_empty() => {
+                        let result = graph.relabel(id, text.trim().to_string());
+                        self.finish(graph, result, "Relabelled.");
+                    },
+                    Key::Enter => { self.status = String::from("The description can't be empty."); },
+                    Key::Esc => { self.status.clear(); },
+                    Key::Backspace => {
+                        text.pop();
+                        self.mode = Mode::Relabel { id, text };
+                    },
+                    Key::Char(c) => {
+                        text.push(c);
+                        self.mode = Mode::Relabel { id, text };
+                    },
+                    _ => { self.mode = Mode::Relabel { id, text }; }
+                }
+            },
+            Mode::Link { .. } | Mode::Unlink { .. } if key == Key::Esc => { self.status.clear(); },
+            Mode::Link { parent } if key == Key::Enter => {
+                if let Some(row) = current
+                {
+                    let result = graph.link(&parent, &row.id);
+                    self.finish(graph, result, &format!("Linked {} to {}.", parent, row.id));
+                }
+            },
+            Mode::Unlink { parent } if key == Key::Enter => {
+                if let Some(row) = current
+                {
+                    let result = graph.unlink(&parent, &row.id);
+                    self.finish(graph, result, &format!("Unlinked {} from {}.", row.id, parent));
+                }
+            },
+            // moving around to pick the other node
+            mode @ (Mode::Link { .. } | Mode::Unlink { .. }) => {
+                self.move_key(key, &rows);
+                self.mode = mode;
+            },
+            Mode::Complete { id } => {
+                if key == Key::Char('y')
+                {
+                    self.complete(graph, id);
+                }
+                else
+                {
+                    self.status = String::from("Nothing was completed.");
+                }
+            }
+        }
+
+        let count = self.rows(graph).len();
+        self.selected = self.selected.min(count.saturating_sub(1));
+    }
+
+    fn normal_key(&mut self, graph: &mut Graph, key: Key, rows: &[Row], current: Option<Row>)
+    {
+        if self.move_key(key, rows)
+        {
+            return;
+        }
+        let id = current.as_ref().map(|x| x.id);
+
+        match (key, current)
+        {
+            (Key::Char('q'), _) | (Key::Esc, _) => { self.quit = true; },
+            (Key::Right, Some(row)) | (Key::Char('l'), Some(row)) => {
+                if row.children && !row.expanded
+                {
+                    self.toggled_flip(row.id);
+                }
+                else if row.children
+                {
+                    self.selected += 1;
+                }
+            },
+            (Key::Left, Some(row)) | (Key::Char('h'), Some(row)) => {
+                if row.children && row.expanded
+                {
+                    self.toggled_flip(row.id);
+                }
+                else if let Some(parent) = rows[..self.selected].iter().rposition(|x| x.level < row.level)
+                {
+                    self.selected = parent;
+                }
+            },
+            (Key::Enter, Some(row)) | (Key::Char(' '), Some(row)) if row.children => { self.toggled_flip(row.id); },
+            (Key::Char('a'), _) => {
+                self.mode = Mode::Add { under: id.or(graph.effective_root()), text: String::new() };
+            },
+            (Key::Char('A'), _) => {
+                self.mode = Mode::Add { under: graph.effective_root(), text: String::new() };
+            },
+            (Key::Char('r'), Some(row)) | (Key::Char('e'), Some(row)) => {
+                self.mode = Mode::Relabel { id: row.id, text: graph.nodes()[row.id].description.clone() };
+            },
+            (Key::Char('c'), Some(row)) | (Key::Char('x'), Some(row)) => {
+                if row.children
+                {
+                    self.mode = Mode::Complete { id: row.id };
+                }
+                else
+                {
+                    self.complete(graph, row.id);
+                }
+            },
+            (Key::Char('L'), Some(row)) => {
+                self.mode = Mode::Link { parent: row.id };
+                self.status.clear();
+            },
+            (Key::Char('U'), Some(row)) => {
+                self.mode = Mode::Unlink { parent: row.id };
+                self.status.clear();
+            },
+            (Key::Char('o'), _) => {
+                self.overwhelm = !self.overwhelm;
+                self.status = String::from(if self.overwhelm { "Showing everything." } else { "Showing goals and leaves." });
+                if let Some(id) = id
+                {
+                    self.select_node(graph, id);
+                }
+            },
+            (Key::Char('b'), _) => {
+                let start = graph.effective_root();
+                let backlogs: Vec<usize> = graph.nodes().iter().map(|x| x.id).filter(|x| is_backlog(graph, *x)).collect();
+                let expand = backlogs.iter().any(|x| !self.expanded(graph, *x, start));
+                for backlog in backlogs
+                {
+                    if self.expanded(graph, backlog, start) != expand
+                    {
+                        self.toggled_flip(backlog);
+                    }
+                }
+                self.status = String::from(if expand { "Showing the backlog." } else { "Hiding the backlog." });
+            },
+            (Key::Char('u'), Some(row)) => {
+                let result = graph.set_effective_root(Some(row.id));
+                self.finish(graph, result, "Showing the selected node only. Press R to see everything.");
+                self.selected = 0;
+            },
+            (Key::Char('R'), _) => {
+                let result = graph.set_effective_root(None);
+                self.finish(graph, result, "Showing everything.");
+                if let Some(id) = id
+                {
+                    self.select_node(graph, id);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Moves the selection. Returns whether `key` was a move.
+    fn move_key(&mut self, key: Key, rows: &[Row]) -> bool
+    {
+        let last = rows.len().saturating_sub(1);
+        self.selected = match key
+        {
+            Key::Up | Key::Char('k') => self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => (self.selected + 1).min(last),
+            Key::PageUp => self.selected.saturating_sub(PAGE),
+            Key::PageDown => (self.selected + PAGE).min(last),
+            Key::Home | Key::Char('g') => 0,
+            Key::End | Key::Char('G') => last,
+            _ => { return false; }
+        };
+        true
+    }
+
+    fn toggled_flip(&mut self, id: usize)
+    {
+        if !self.toggled.remove(&id)
+        {
+            self.toggled.insert(id);
+        }
+    }
+
+    /// Adds a node from a line like those of an outline, so `goal:` and
+    /// `?` make goals and conditions.
+    fn add(&mut self, graph: &mut Graph, under: Option<usize>, text: &str)
+    {
+        let entry = match outline::parse_plain(text)
+        {
+            Ok(entries) if entries.len() == 1 && !entries[0].description.is_empty() => entries.into_iter().next().unwrap(),
+            Ok(_) => {
+                self.status = String::from("Type a description, optionally starting with goal: or ?.");
+                return;
+            },
+            Err(error) => {
+                self.status = error.to_string();
+                return;
+            }
+        };
+
+        match graph.add_node_to(entry.description, entry.node_type, under)
+        {
+            Ok(id) => {
+                let start = graph.effective_root();
+                if let Some(under) = under.filter(|x| !self.expanded(graph, *x, start))
+                {
+                    self.toggled_flip(under);
+                }
+                self.finish(graph, Ok(()), "Ha! Your workload just got a little bigger.");
+                self.select_node(graph, id);
+            },
+            Err(error) => { self.status = error.to_string(); }
+        }
+    }
+
+    fn complete(&mut self, graph: &mut Graph, id: usize)
+    {
+        let result = graph.complete(vec![id], chrono::Local::now().date_naive()).map(|_| ());
+        // ids move around when nodes are removed
+        self.toggled.clear();
+        self.finish(graph, result, "Thank god, you managed to complete something.");
+    }
+
+    /// Saves after a change, and reports how it went.
+    fn finish(&mut self, graph: &mut Graph, result: Result<(), TodosError>, message: &str)
+    {
+        self.status = match result.and_then(|_| graph.save())
+        {
+            Ok(()) => message.to_string(),
+            Err(error) => error.to_string()
+        };
+    }
+
+    /// The screen, `height` lines of at most `width` characters: the tree,
+    /// scrolled to keep the selection in view, then a status line and a
+    /// line of help or input.
+    pub fn render(&self, graph: &Graph, width: usize, height: usize) -> Vec<String>
+    {
+        let rows = self.rows(graph);
+        let visible = height.saturating_sub(2);
+        let first = (self.selected + 1).saturating_sub(visible);
+        let config = graph.config();
+
+        let mut lines = Vec::new();
+        if rows.is_empty()
+        {
+            lines.push(String::from(match graph.effective_root()
+            {
+                Some(_) if graph.effective_root().and_then(|x| graph.node(x).ok()).is_none() => "The effective root was deleted; press R to see everything.",
+                _ => "Nothing to do. Press a to add something."
+            }));
+        }
+        for (i, row) in rows.iter().enumerate().skip(first).take(visible)
+        {
+            let node = &graph.nodes()[row.id];
+            let fold = match (row.children, row.expanded)
+            {
+                (false, _) => "  ",
+                (true, true) => "▾ ",
+                (true, false) => "▸ "
+            };
+            let color = match node.node_type
+            {
+                NodeType::Goal => &config.goal_color,
+                NodeType::Condition => &config.condition_color,
+                NodeType::Task => &config.task_color
+            };
+            let picked = match self.mode
+            {
+                Mode::Link { parent } | Mode::Unlink { parent } => parent == row.id,
+                _ => false
+            };
+            let text = cut(&format!("{}{}{}", "  ".repeat(row.level), fold, node.label()), width);
+            // reverse video for the selection, underlined for a picked node
+            let highlight = if i == self.selected { ";7" } else if picked { ";4" } else { "" };
+            lines.push(format!("\x1B[{}{}m{}\x1B[0m", color, highlight, text));
+        }
+        while lines.len() < visible
+        {
+            lines.push(String::new());
+        }
+
+        let selected = rows.get(self.selected).map(|x| graph.nodes()[x.id].description.clone()).unwrap_or_default();
+        let (status, prompt) = match &self.mode
+        {
+            Mode::Normal => (self.status.clone(), String::from(HELP)),
+            Mode::Add { text, .. } => (String::from("Add (goal: or ? for goals and conditions), Enter to save, Esc to cancel"), format!("> {}", text)),
+            Mode::Relabel { text, .. } => (String::from("Relabel, Enter to save, Esc to cancel"), format!("> {}", text)),
+            Mode::Link { parent } => (
+                format!("Link {} to the node to pick: move to it and press Enter, or Esc to cancel.", parent),
+                format!("Depends on: {}", selected)
+            ),
+            Mode::Unlink { parent } => (
+                format!("Unlink a child of {}: move to it and press Enter, or Esc to cancel.", parent),
+                format!("Unlink: {}", selected)
+            ),
+            Mode::Complete { id } => (
+                format!("Complete {} and everything under it? (y/n)", id),
+                String::new()
+            )
+        };
+        lines.push(format!("\x1B[1m{}\x1B[0m", cut(&status, width)));
+        lines.push(cut(&prompt, width));
+        lines
+    }
+}
+
+/// Whether `id` is a backlog goal, whose children `todos` hides.
+fn is_backlog(graph: &Graph, id: usize) -> bool
+{
+    let config = graph.config();
+    let node = &graph.nodes()[id];
+    config.hide_backlog_items && node.node_type == NodeType::Goal && node.description == config.backlog_name
+}
+
+fn cut(text: &str, width: usize) -> String
+{
+    text.chars().take(width).collect()
+}
+
+/// Runs the interface until the user quits, restoring the terminal after.
+pub fn run(graph: &mut Graph) -> Result<(), TodosError>
+{
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().map_err(terminal_error)?;
+    let result = execute!(out, terminal::EnterAlternateScreen, cursor::Hide)
+        .and_then(|_| event_loop(graph, &mut out));
+
+    // put the terminal back even if drawing failed
+    let restored = execute!(out, cursor::Show, terminal::LeaveAlternateScreen)
+        .and(terminal::disable_raw_mode());
+    result.and(restored).map_err(terminal_error)
+}
+
+fn terminal_error(error: io::Error) -> TodosError
+{
+    TodosError::Invalid(format!("The terminal couldn't be used: {}", error))
+}
+
+fn event_loop(graph: &mut Graph, out: &mut io::Stdout) -> io::Result<()>
+{
+    let mut app = App::new();
+    while !app.quit()
+    {
+        let (width, height) = terminal::size()?;
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        for (i, line) in app.render(graph, width as usize, height as usize).iter().enumerate()
+        {
+            queue!(out, cursor::MoveTo(0, i as u16))?;
+            write!(out, "{}", line)?;
+        }
+        out.flush()?;
+
+        if let Event::Key(event) = event::read()?
+        {
+            if event.kind == KeyEventKind::Release
+            {
+                continue;
+            }
+            let key = match event.code
+            {
+                KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => { return Ok(()); },
+                KeyCode::Char(c) => Key::Char(c),
+                KeyCode::Up => Key::Up,
+                KeyCode::Down => Key::Down,
+                KeyCode::Left => Key::Left,
+                KeyCode::Right => Key::Right,
+                KeyCode::PageUp => Key::PageUp,
+                KeyCode::PageDown => Key::PageDown,
+                KeyCode::Home => Key::Home,
+                KeyCode::End => Key::End,
+                KeyCode::Enter => Key::Enter,
+                KeyCode::Backspace => Key::Backspace,
+                KeyCode::Esc => Key::Esc,
+                _ => { continue; }
+            };
+            // pick up changes made elsewhere, such as through `todos serve`
+            let before = serde_json::to_value(&*graph).ok();
+            let handled = graph.locked(|graph| {
+                if serde_json::to_value(&*graph).ok() != before
+                {
+                    app.graph_changed();
+                }
+                app.handle_key(graph, key)
+            });
+            if let Err(error) = handled
+            {
+                app.status = error.to_string();
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! `todos watch`: the view `todos` prints, redrawn whenever another process
+//! changes the todos file, with what was added and completed since the
+//! last redraw marked.
+//!
+//! The file's modification time is polled rather than watched, which works
+//! the same everywhere and is cheap at the rate todos files change.
+
+use crate::error::TodosError;
+use crate::graph::Graph;
+use crate::node::{Node, NodeType};
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the todos file is checked for changes.
+const POLL: Duration = Duration::from_millis(500);
+
+/// What changed between two versions of a graph.
+#[derive(Debug, Default, PartialEq)]
+pub struct Diff
+{
+    /// Ids, in the newer graph, of the nodes that are new.
+    pub added: Vec<usize>,
+    /// Descriptions of the nodes that are gone.
+    pub completed: Vec<String>
+}
+
+/// Compares two versions of a graph. Ids change as nodes are completed, so
+/// nodes are matched by type and description; a relabeled node counts as
+/// completed and added again.
+pub fn diff(before: &Graph, after: &Graph) -> Diff
+{
+    let mut remaining: HashMap<(&str, &NodeType), usize> = HashMap::new();
+    for node in before.nodes()
+    {
+        *remaining.entry((&node.description[..], &node.node_type)).or_insert(0) += 1;
+    }
+
+    let mut diff = Diff::default();
+    for node in after.nodes()
+    {
+        match remaining.get_mut(&(&node.description[..], &node.node_type))
+        {
+            Some(count) if *count > 0 => { *count -= 1; },
+            _ => diff.added.push(node.id)
+        }
+    }
+    for node in before.nodes()
+    {
+        let count = remaining.get_mut(&(&node.description[..], &node.node_type)).unwrap();
+        if *count > 0
+        {
+            *count -= 1;
+            diff.completed.push(node.description.clone());
+        }
+    }
+    diff
+}
+
+/// The view `todos` (or `todos under <id>`) prints, with the nodes in
+/// `diff.added` marked new and those completed listed below.
+pub fn render(graph: &Graph, under: Option<usize>, overwhelm: bool, diff: &Diff) -> Result<String, TodosError>
+{
+    let mut out = String::new();
+    let mut visit = |node: &Node, level: u128| {
+        node.render(&mut out, &graph.config().goal_color, &graph.config().condition_color, &graph.config().task_color, level);
+        if diff.added.contains(&node.id)
+        {
+            out.pop();
+            out.push_str(" \x1B[1;32m(new)\x1B[00m\n");
+        }
+    };
+
+    match under.or(graph.effective_root())
+    {
+        Some(start) if under.is_some() || graph.node(start).is_ok() => {
+            graph.walk_show(&start, 0, overwhelm, Some(start), &mut visit)?;
+        },
+        // let `todos` explain the deleted effective root
+        Some(_) => out.push_str(&graph.render_todos(overwhelm)),
+        None => {
+            for node in graph.nodes().iter().filter(|x| x.parents.is_empty())
+            {
+                graph.walk_show(&node.id, 0, overwhelm, None, &mut visit)?;
+            }
+        }
+    }
+
+    if !diff.completed.is_empty()
+    {
+        out.push_str("\nCompleted:\n");
+        for description in &diff.completed
+        {
+            out.push_str(&format!("  \x1B[9m{}\x1B[00m\n", description));
+        }
+    }
+    Ok(out)
+}
+
+fn modified(graph: &Graph) -> Option<SystemTime>
+{
+    let path = graph.storage()?.location();
+    std::fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// Redraws the view every time the todos file changes, until the process is
+/// stopped.
+pub fn run(graph: &mut Graph, under: Option<usize>, overwhelm: bool) -> Result<(), TodosError>
+{
+    let location = match graph.storage()
+    {
+        Some(storage) => storage.location().display().to_string(),
+        None => { return Err(TodosError::Invalid(String::from("Only a todos file can be watched."))); }
+    };
+
+    let mut diff = Diff::default();
+    let mut problem = None;
+    let mut last = modified(graph);
+    loop
+    {
+        let view = render(graph, under, overwhelm, &diff).unwrap_or_else(|error| format!("{}\n", error));
+        let mut out = io::stdout();
+        // clear the screen and start at the top
+        write!(
+            out,
+            "\x1B[2J\x1B[H\x1B[2mWatching {}, updated {}. Press Ctrl-C to stop.\x1B[00m\n\n{}{}",
+            location,
+            chrono::Local::now().format("%H:%M:%S"),
+            view,
+            problem.take().map(|x| format!("\n{}\n", x)).unwrap_or_default()
+        )
+            .and_then(|_| out.flush())
+            .map_err(|error| TodosError::io("stdout", error))?;
+
+        while modified(graph) == last
+        {
+            thread::sleep(POLL);
+        }
+        last = modified(graph);
+
+        let before = Graph::from_parts(graph.effective_root(), graph.nodes().to_vec());
+        match graph.reload()
+        {
+            Ok(()) => { diff = self::diff(&before, graph); },
+            Err(error) => { problem = Some(error.to_string()); }
+        }
+    }
+}
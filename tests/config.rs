@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::PathBuf;
+use todos::config::{self, Config, CONFIG_VERSION};
+use todos::TodosError;
+
+#[test]
+fn newer_config_files_are_read_but_never_rewritten()
+{
+    let path: PathBuf = std::env::temp_dir().join(format!("todos-test-{}-config.toml", std::process::id()));
+    let content = format!("version = {}\nbacklog_name = \"someday\"\nsparkles = true\n", CONFIG_VERSION + 1);
+    fs::write(&path, &content).unwrap();
+
+    // the settings this build knows still apply
+    assert_eq!(config::read_config_file(path.clone()).backlog_name, "someday");
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    let changed = config::set_config_value(&path, "backlog_name", "later");
+    assert!(matches!(changed, Err(TodosError::UnsupportedVersion { found, .. }) if found == CONFIG_VERSION as u64 + 1));
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    fs::remove_file(&path).unwrap();
+}
+
+fn temp_config(name: &str, content: &str) -> PathBuf
+{
+    let path = std::env::temp_dir().join(format!("todos-test-{}-{}.toml", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn missing_settings_fall_back_one_by_one()
+{
+    let path = temp_config("partial", "version = 1\nbacklog_name = \"someday\"\ngoal_color = \"31\"\n");
+
+    let (config, warnings) = config::read_config(&path);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(config.backlog_name, "someday");
+    assert_eq!(config.goal_color, "31");
+    assert_eq!(config.hide_backlog_items, Config::default().hide_backlog_items);
+    assert_eq!(config.task_color, Config::default().task_color);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unknown_and_mistyped_settings_are_reported_where_they_are()
+{
+    let content = "version = 1\nbacklog_name = \"later\"\nsparkles = true\nhide_backlog_items = \"no\"\n  task_color = 7\n";
+    let path = temp_config("mistyped", content);
+
+    let (config, warnings) = config::read_config(&path);
+    assert_eq!(warnings, vec![
+        "`hide_backlog_items` at line 4, column 22 should be of type boolean, not string. Using the default, true.",
+        "unknown setting `sparkles` at line 3, column 12.",
+        "`task_color` at line 5, column 16 should be of type string, not integer. Using the default, \"0;39\"."
+    ]);
+    // the rest still applies, and nothing is rewritten
+    assert_eq!(config.backlog_name, "later");
+    assert!(config.hide_backlog_items);
+    assert_eq!(config.task_color, "0;39");
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn config_set_checks_the_setting_and_its_type()
+{
+    let content = "version = 1\nbacklog_name = \"later\"\n";
+    let path = temp_config("set", content);
+
+    let changed = config::set_config_value(&path, "hide_backlog_items", "nope");
+    assert!(matches!(changed, Err(TodosError::InvalidSetting { key, .. }) if key == "hide_backlog_items"));
+    let changed = config::set_config_value(&path, "sparkles", "true");
+    assert!(matches!(changed, Err(TodosError::UnknownSetting(key)) if key == "sparkles"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    config::set_config_value(&path, "hide_backlog_items", "false").unwrap();
+    let (config, warnings) = config::read_config(&path);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert!(!config.hide_backlog_items);
+    assert_eq!(config.backlog_name, "later");
+
+    fs::remove_file(&path).unwrap();
+}
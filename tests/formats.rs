@@ -0,0 +1,614 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use todos::dates::Recurrence;
+use todos::formats::{self, Format};
+use todos::{Config, Graph, NodeType, TodosError};
+
+fn sample() -> Graph
+{
+    let mut graph = Graph::new(Config::default());
+    let mut add = |description: &str, node_type: NodeType, to: Option<usize>| {
+        graph.add_node_to(description.to_string(), node_type, to).unwrap()
+    };
+
+    let house = add("house", NodeType::Goal, None);
+    let paint = add("paint the fence", NodeType::Task, Some(house));
+    add("buy paint", NodeType::Task, Some(paint));
+    add("dry weather", NodeType::Condition, Some(paint));
+    let garden = add("garden", NodeType::Goal, None);
+    let weed = add("x marks the spot", NodeType::Task, Some(garden));
+
+    graph.link(&house, &weed).unwrap();
+    graph.set_notes(paint, Some("Two coats.\nThe *blue* one, 100%.".to_string())).unwrap();
+    graph.tag(paint, vec!["+home".to_string(), "@store".to_string(), "weekend plans".to_string()]).unwrap();
+    graph.set_priority(paint, Some('A')).unwrap();
+    graph.set_due_date(weed, Some("2024-06-01".to_string())).unwrap();
+    graph
+}
+
+/// `graph` with the history of recurring nodes, one deferred, and time
+/// tracked with the timer still running.
+fn with_history(mut graph: Graph) -> Graph
+{
+    graph.set_recurrence(2, Recurrence::parse("weekly on mon, thu")).unwrap();
+    graph.set_completed(2, vec!["2024-05-02".to_string(), "2024-05-06".to_string()]).unwrap();
+    graph.set_recurrence(4, Recurrence::parse("daily")).unwrap();
+    graph.set_recurrence(5, Recurrence::parse("every 3 days")).unwrap();
+    graph.defer(5, NaiveDate::from_ymd_opt(2024, 5, 10)).unwrap();
+    graph.start_timer(2, at("2024-05-02T09:00:00Z")).unwrap();
+    graph.stop_timer(at("2024-05-02T09:45:00Z")).unwrap();
+    graph.start_timer(1, at("2024-05-03T10:00:00Z")).unwrap();
+    graph
+}
+
+fn at(moment: &str) -> DateTime<Utc>
+{
+    DateTime::parse_from_rfc3339(moment).unwrap().with_timezone(&Utc)
+}
+
+/// The graph as JSON, with parents sorted: formats only record each node's
+/// deps, so the order parents were linked in is lost.
+fn normalized(graph: &Graph) -> serde_json::Value
+{
+    let mut value = serde_json::to_value(graph).unwrap();
+    for node in value["nodes"].as_array_mut().unwrap()
+    {
+        node["parents"].as_array_mut().unwrap().sort_by_key(|x| x.as_u64());
+    }
+    value
+}
+
+/// Exports `graph` and imports the result into an empty graph.
+fn round_trip(graph: &mut Graph, format: Format) -> Graph
+{
+    let exported = formats::export(graph, format, None).unwrap().content;
+    let mut copy = Graph::new(Config::default());
+    let report = formats::import(&mut copy, format, &exported, None).unwrap();
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    copy
+}
+
+#[test]
+fn todotxt_round_trip_is_lossless()
+{
+    let mut graph = with_history(sample());
+    let copy = round_trip(&mut graph, Format::TodoTxt);
+    assert_eq!(normalized(&copy), normalized(&graph));
+    let exported = formats::export(&mut graph, Format::TodoTxt, None).unwrap().content;
+    assert!(exported.contains("\
+buy paint rec:weekly%20on%20mon%2C%20thu done:2024-05-02,2024-05-06 time:2024-05-02T09:00:00Z/2024-05-02T09:45:00Z id:2"
+    ), "{}", exported);
+    assert!(exported.contains(" rec:+1d ") && exported.ends_with(" rec:3d t:2024-05-10\n"));
+    assert!(exported.contains(" time:2024-05-03T10:00:00Z id:1"), "{}", exported);
+
+    // a second running timer is left out
+    let report = formats::import(&mut graph, Format::TodoTxt, "weed time:2024-05-04T10:00:00Z\n", None).unwrap();
+    assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+    assert!(graph.node(report.added[0]).unwrap().time.is_empty());
+    assert_eq!(graph.running(), Some(1));
+
+    // descriptions that look like todo.txt syntax
+    for description in [
+        "fix +1 bug  at  id:9 site",
+        "call @mom about due:friday",
+        " dep:3 and\ttabs\nand lines ",
+        "(B) 2024-01-01 x",
+        "100% sure, 50%25 off, meet at 10:30"
+    ]
+    {
+        graph.relabel(1, description.to_string()).unwrap();
+        let copy = round_trip(&mut graph, Format::TodoTxt);
+        assert_eq!(normalized(&copy), normalized(&graph), "{}", description);
+    }
+}
+
+#[test]
+fn todotxt_export()
+{
+    let mut graph = sample();
+    graph.set_due_date(5, None).unwrap();
+
+    assert_eq!(formats::export(&mut graph, Format::TodoTxt, Some(1)).unwrap().content, "\
+(A) paint the fence +home @store tag:weekend%20plans note:Two%20coats.%0AThe%20*blue*%20one%2C%20100%25. id:1 dep:2,3
+buy paint id:2
+dry weather type:condition id:3
+");
+    assert!(formats::export(&mut graph, Format::TodoTxt, None).unwrap().content.ends_with("type:goal id:4 dep:5\nid:5 x marks the spot\n"));
+}
+
+#[test]
+fn todotxt_import_maps_onto_nodes()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::TodoTxt, "\
+(A) 2024-01-05 Call mom @phone +family due:2024-02-01
+
+x 2024-01-04 2024-01-01 old thing id:9
+Plan trip +travel id:t1 dep:t2,t3,9 type:goal
+book flights id:t2 pri:B
+x rays id:t3 dep:t2 note:bring%20card
+meet at 10:30 dep:nope
+", None).unwrap();
+
+    assert_eq!(report.added, vec![0, 1, 2, 3]);
+    assert_eq!(report.skipped, 2);
+    assert_eq!(report.warnings.len(), 2);
+
+    let call = graph.node(0).unwrap();
+    assert_eq!(call.description, "Call mom");
+    assert_eq!(call.priority, Some('A'));
+    assert_eq!(call.tags, vec!["@phone", "+family"]);
+    assert_eq!(call.due_date.as_deref(), Some("2024-02-01"));
+
+    let trip = graph.node(1).unwrap();
+    assert_eq!(trip.node_type, NodeType::Goal);
+    assert_eq!(trip.deps.iter().copied().collect::<Vec<_>>(), vec![2]);
+    assert!(trip.parents.is_empty());
+    assert_eq!(graph.node(2).unwrap().priority, Some('B'));
+    assert_eq!(graph.node(3).unwrap().description, "meet at 10:30");
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn todotxt_import_goes_under_a_node_and_is_all_or_nothing()
+{
+    let mut graph = sample();
+    let report = formats::import(&mut graph, Format::TodoTxt, "a id:1 dep:2\nb id:2\n", Some(4)).unwrap();
+    assert_eq!(graph.node(report.added[0]).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![4]);
+    assert_eq!(graph.node(report.added[1]).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![report.added[0]]);
+
+    let before = serde_json::to_string(&graph).unwrap();
+    let cycle = formats::import(&mut graph, Format::TodoTxt, "a id:1 dep:2\nb id:2 dep:1\n", None);
+    assert!(matches!(cycle, Err(TodosError::Cycle(_))));
+    let duplicate = formats::import(&mut graph, Format::TodoTxt, "a id:1\nb id:1\n", None);
+    assert!(matches!(duplicate, Err(TodosError::Invalid(_))));
+    let empty = formats::import(&mut graph, Format::TodoTxt, "(A) +project\n", None);
+    assert!(matches!(empty, Err(TodosError::Invalid(_))));
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+}
+
+#[test]
+fn outline_export_lists_shared_nodes_under_each_parent()
+{
+    let mut graph = sample();
+    assert_eq!(formats::export(&mut graph, Format::Outline, None).unwrap().content, "\
+- goal: house
+  - paint the fence
+    - buy paint
+    - ? dry weather
+  - x marks the spot
+- goal: garden
+  - x marks the spot
+");
+    assert_eq!(formats::export(&mut graph, Format::Outline, Some(1)).unwrap().content, "- paint the fence\n  - buy paint\n  - ? dry weather\n");
+}
+
+#[test]
+fn taskwarrior_round_trip_keeps_everything_but_spaced_tags()
+{
+    let mut graph = with_history(sample());
+    graph.untag(1, &["weekend plans".to_string()]).unwrap();
+
+    let first = formats::export(&mut graph, Format::Taskwarrior, None).unwrap().content;
+    assert!(graph.nodes().iter().all(|x| x.uuid.is_some()));
+    // UUIDs are kept, so exporting again gives the same tasks
+    assert_eq!(formats::export(&mut graph, Format::Taskwarrior, None).unwrap().content, first);
+
+    let copy = round_trip(&mut graph, Format::Taskwarrior);
+    assert_eq!(normalized(&copy), normalized(&graph));
+}
+
+#[test]
+fn taskwarrior_export()
+{
+    let mut graph = sample();
+    graph.set_priority(2, Some('D')).unwrap();
+    graph.set_due_date(3, Some("when it stops raining".to_string())).unwrap();
+
+    let exported = formats::export(&mut graph, Format::Taskwarrior, None).unwrap();
+    assert_eq!(exported.warnings.len(), 3);
+
+    let tasks: Vec<serde_json::Value> = serde_json::from_str(&exported.content).unwrap();
+    assert_eq!(tasks.len(), 6);
+    let paint = &tasks[1];
+    assert_eq!(paint["description"], "paint the fence");
+    assert_eq!(paint["priority"], "H");
+    assert_eq!(paint["project"], "house");
+    assert_eq!(paint["tags"], serde_json::json!(["+home", "@store"]));
+    assert_eq!(paint["annotations"][0]["description"], "Two coats.\nThe *blue* one, 100%.");
+    assert_eq!(paint["depends"], serde_json::json!([tasks[2]["uuid"], tasks[3]["uuid"]]));
+    assert_eq!(tasks[0]["todostype"], "goal");
+    assert_eq!(tasks[3]["todostype"], "condition");
+    assert_eq!(tasks[2]["priority"], "L");
+    assert_eq!(tasks[5]["due"], "20240601T000000Z");
+}
+
+#[test]
+fn taskwarrior_import_turns_projects_into_goals()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::Taskwarrior, r#"[
+        {"id":1,"description":"Buy seeds","status":"pending","uuid":"u1","project":"home.garden","tags":["shopping"],"priority":"M","due":"20240301T000000Z","urgency":8.1},
+        {"id":2,"description":"Plant seeds","status":"pending","uuid":"u2","project":"home.garden","depends":"u1,u9","scheduled":"20240302T000000Z","annotations":[{"entry":"20240101T100000Z","description":"use the trays"}]},
+        {"id":0,"description":"Old","status":"completed","uuid":"u9","end":"20240101T100000Z"},
+        {"id":3,"description":"Landlord OKs shed","status":"waiting","wait":"20240401T000000Z","uuid":"u3","project":"home","due":"20240215T153000Z"},
+        {"id":4,"description":"weekly review","status":"recurring","recur":"weekly","uuid":"u4"}
+    ]"#, None).unwrap();
+
+    assert_eq!(report.skipped, 2);
+    // the recurring template and scheduled
+    assert_eq!(report.warnings.len(), 2, "{:?}", report.warnings);
+
+    let find = |description: &str| graph.nodes().iter().find(|x| x.description == description).unwrap();
+    let names = |links: &todos::node::Links| links.iter().map(|x| graph.nodes()[*x].description.clone()).collect::<Vec<_>>();
+
+    let home = find("home");
+    assert_eq!(home.node_type, NodeType::Goal);
+    assert!(home.parents.is_empty());
+    assert_eq!(names(&home.deps), vec!["garden", "Landlord OKs shed"]);
+    assert_eq!(names(&find("garden").deps), vec!["Plant seeds"]);
+
+    let plant = find("Plant seeds");
+    assert_eq!(names(&plant.deps), vec!["Buy seeds"]);
+    assert_eq!(plant.notes.as_deref(), Some("use the trays"));
+    assert_eq!(plant.uuid.as_deref(), Some("u2"));
+
+    let buy = find("Buy seeds");
+    assert_eq!(names(&buy.parents), vec!["Plant seeds"]);
+    assert_eq!(buy.due_date.as_deref(), Some("2024-03-01"));
+    assert_eq!(buy.priority, Some('B'));
+    assert_eq!(buy.tags, vec!["shopping"]);
+
+    let shed = find("Landlord OKs shed");
+    assert_eq!(shed.node_type, NodeType::Condition);
+    assert_eq!(shed.due_date.as_deref(), Some("2024-02-15T15:30:00Z"));
+    assert_eq!(shed.deferred_until.as_deref(), Some("2024-04-01"));
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn taskwarrior_import_accepts_one_task_per_line()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::Taskwarrior, "\
+{\"description\":\"a\",\"uuid\":\"u1\",\"depends\":[\"u2\"]}
+{\"description\":\"b\",\"uuid\":\"u2\"}
+", None).unwrap();
+    assert_eq!(report.added.len(), 2);
+    assert_eq!(graph.node(0).unwrap().deps.iter().copied().collect::<Vec<_>>(), vec![1]);
+
+    assert!(matches!(formats::import(&mut graph, Format::Taskwarrior, "[{\"uuid\":\"u3\"}]", None), Err(TodosError::Invalid(_))));
+    assert!(matches!(formats::import(&mut graph, Format::Taskwarrior, "{not json", None), Err(TodosError::Invalid(_))));
+}
+
+#[test]
+fn ics_export_lists_actionable_leaves()
+{
+    let mut graph = sample();
+    graph.set_priority(2, Some('K')).unwrap();
+    let backlog = graph.add_node_to("backlog".to_string(), NodeType::Goal, None).unwrap();
+    graph.add_node_to("learn the banjo".to_string(), NodeType::Task, Some(backlog)).unwrap();
+
+    let exported = formats::export(&mut graph, Format::Ics, None).unwrap();
+    assert_eq!(exported.warnings.len(), 1);
+    let content = exported.content;
+    assert!(content.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(content.ends_with("END:VTODO\r\nEND:VCALENDAR\r\n"));
+    assert!(content.lines().all(|x| x.len() <= 76));
+
+    // buy paint and the weeding, but not the condition or the backlog
+    assert_eq!(content.matches("BEGIN:VTODO").count(), 2);
+    assert!(content.contains("SUMMARY:buy paint\r\nSTATUS:NEEDS-ACTION\r\nPRIORITY:9\r\nCATEGORIES:house\r\n"));
+    assert!(content.contains(&format!("RELATED-TO;RELTYPE=PARENT:{}\r\n", graph.node(1).unwrap().uuid.as_ref().unwrap())));
+    assert!(content.contains("DUE;VALUE=DATE:20240601\r\nCATEGORIES:garden,house\r\n"));
+    assert!(!content.contains("banjo"));
+}
+
+#[test]
+fn ics_repeats_with_rrules()
+{
+    let mut graph = with_history(sample());
+    graph.set_recurrence(2, Recurrence::parse("monthly on day 30")).unwrap();
+    let exported = formats::export(&mut graph, Format::Ics, None).unwrap();
+    // every 3 days after completion, and the days buy paint was done on and
+    // its tracked time
+    assert_eq!(exported.warnings.len(), 3, "{:?}", exported.warnings);
+    assert!(exported.content.contains("RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30;BYSETPOS=-1\r\n"));
+    assert!(exported.content.contains("DTSTART;VALUE=DATE:20240510\r\n"));
+
+    let mut copy = Graph::new(Config::default());
+    let report = formats::import(&mut copy, Format::Ics, &exported.content.replace("BYMONTHDAY=28,29,30", "BYMONTHDAY=28,29,30,31"), None).unwrap();
+    assert!(report.warnings.iter().all(|x| !x.contains("RRULE")), "{:?}", report.warnings);
+    let paint = copy.nodes().iter().find(|x| x.description == "buy paint").unwrap();
+    assert_eq!(paint.recurrence, Recurrence::parse("monthly on day 31"));
+    let spot = copy.nodes().iter().find(|x| x.description == "x marks the spot").unwrap();
+    assert_eq!(spot.deferred_until.as_deref(), Some("2024-05-10"));
+
+    for (rule, recurrence) in [
+        ("FREQ=DAILY", Some("daily")),
+        ("FREQ=WEEKLY;BYDAY=TH,MO", Some("weekly on mon, thu")),
+        ("FREQ=MONTHLY;BYMONTHDAY=-1", Some("monthly on day 31")),
+        ("FREQ=WEEKLY;INTERVAL=2", None),
+        ("FREQ=YEARLY", None)
+    ]
+    {
+        let content = format!("BEGIN:VTODO\nUID:a\nSUMMARY:a\nRRULE:{}\nEND:VTODO\n", rule);
+        let mut graph = Graph::new(Config::default());
+        let report = formats::import(&mut graph, Format::Ics, &content, None).unwrap();
+        assert_eq!(graph.node(0).unwrap().recurrence, recurrence.and_then(Recurrence::parse), "{}", rule);
+        assert_eq!(report.warnings.len(), if recurrence.is_some() { 0 } else { 1 }, "{}", rule);
+    }
+}
+
+#[test]
+fn ics_import_turns_categories_into_goals()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::Ics, "\
+BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTODO\r
+RELATED-TO:pack\r
+UID:tickets\r
+SUMMARY:Book tickets\\, both ways\r
+DUE;TZID=Europe/Berlin:20240301T090000\r
+PRIORITY:2\r
+CATEGORIES:Trip\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+END:VALARM\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:pack\r
+SUMMARY:Pack the bags for a trip that is long enough that this line has to \r
+ be folded\r
+DESCRIPTION:Sunscreen\\nHat\r
+CATEGORIES:Trip,Summer\r
+LOCATION:home\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:old\r
+SUMMARY:Renew passport\r
+STATUS:COMPLETED\r
+RELATED-TO;RELTYPE=PARENT:gone\r
+END:VTODO\r
+END:VCALENDAR\r
+", None).unwrap();
+
+    assert_eq!(report.skipped, 1);
+    // the local due time and the location
+    assert_eq!(report.warnings.len(), 2, "{:?}", report.warnings);
+
+    let find = |description: &str| graph.nodes().iter().find(|x| x.description == description).unwrap();
+    let names = |links: &todos::node::Links| links.iter().map(|x| graph.nodes()[*x].description.clone()).collect::<Vec<_>>();
+
+    let pack = find("Pack the bags for a trip that is long enough that this line has to be folded");
+    assert_eq!(pack.notes.as_deref(), Some("Sunscreen\nHat"));
+    assert_eq!(names(&pack.deps), vec!["Book tickets, both ways"]);
+    assert_eq!(names(&pack.parents), vec!["Summer", "Trip"]);
+
+    let tickets = find("Book tickets, both ways");
+    assert_eq!(tickets.due_date.as_deref(), Some("2024-03-01"));
+    assert_eq!(tickets.priority, Some('B'));
+    assert_eq!(tickets.uuid.as_deref(), Some("tickets"));
+    assert_eq!(find("Trip").node_type, NodeType::Goal);
+    assert!(graph.validate().is_ok());
+
+    assert!(matches!(formats::import(&mut graph, Format::Ics, "BEGIN:VTODO\nSUMMARY:x\n", None), Err(TodosError::Invalid(_))));
+}
+
+#[test]
+fn org_round_trip_keeps_the_dag()
+{
+    let mut graph = with_history(sample());
+    graph.untag(1, &["weekend plans".to_string(), "+home".to_string()]).unwrap();
+    graph.tag(1, vec!["home".to_string()]).unwrap();
+    graph.set_due_date(3, Some("2024-06-01T12:30:00Z".to_string())).unwrap();
+
+    // nodes are added in the order of the outline, so ids can differ
+    let mut copy = round_trip(&mut graph, Format::Org);
+    let original = formats::export(&mut graph, Format::Org, None).unwrap();
+    assert_eq!(formats::export(&mut copy, Format::Org, None).unwrap(), original);
+    assert!(original.content.contains("DEADLINE: <2024-06-01"));
+    assert!(original.content.contains("\
+*** TODO buy paint
+    :PROPERTIES:
+    :REPEAT: weekly on mon, thu
+    :END:
+    :LOGBOOK:
+    CLOCK: ["), "{}", original.content);
+    // clocks are in local time
+    assert!(original.content.contains("\
+ =>  0:45
+    - State \"DONE\"       from \"TODO\"       [2024-05-06 Mon]
+    - State \"DONE\"       from \"TODO\"       [2024-05-02 Thu]
+    :END:
+"), "{}", original.content);
+    assert!(original.warnings.is_empty(), "{:?}", original.warnings);
+}
+
+#[test]
+fn org_export()
+{
+    let mut graph = sample();
+    let exported = formats::export(&mut graph, Format::Org, None).unwrap();
+    // the spaced tag and the one starting with +
+    assert_eq!(exported.warnings.len(), 2);
+
+    let uuid = graph.node(5).unwrap().uuid.clone().unwrap();
+    assert_eq!(exported.content, format!("\
+* house
+** TODO [#A] paint the fence :@store:
+   Two coats.
+   The *blue* one, 100%.
+*** TODO buy paint
+*** WAITING dry weather
+** TODO x marks the spot
+   DEADLINE: <2024-06-01 Sat>
+   :PROPERTIES:
+   :ID: {}
+   :END:
+* garden
+** TODO [[id:{}][x marks the spot]]
+", uuid, uuid));
+}
+
+#[test]
+fn org_import_reads_keywords_deadlines_and_links()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::Org, "\
+#+TITLE: Moving
+#+TODO: TODO WAIT | DONE
+* Move house :home:
+  SCHEDULED: <2024-03-01 Fri> DEADLINE: <2024-03-31 Sun>
+** WAIT [#B] Lease signed
+   :PROPERTIES:
+   :ID: lease
+   :EFFORT: 1h
+   :END:
+** DONE Find a flat
+*** TODO Call agents
+** TODO Book a van
+   DEADLINE: <2024-03-15 Fri +1w>
+   :LOGBOOK:
+   CLOCK: [2024-02-01 Thu 10:00]--[2024-02-01 Thu 11:00] =>  1:00
+   :END:
+   Ask about
+     straps.
+* Pay the deposit
+** [[id:lease][Lease signed]]
+", None).unwrap();
+
+    assert_eq!(report.skipped, 2);
+    // EFFORT and the repeater
+    assert_eq!(report.warnings.len(), 2, "{:?}", report.warnings);
+
+    let find = |description: &str| graph.nodes().iter().find(|x| x.description == description).unwrap();
+    let names = |links: &todos::node::Links| links.iter().map(|x| graph.nodes()[*x].description.clone()).collect::<Vec<_>>();
+
+    let house = find("Move house");
+    assert_eq!(house.node_type, NodeType::Goal);
+    assert_eq!(house.tags, vec!["home"]);
+    assert_eq!(house.due_date.as_deref(), Some("2024-03-31"));
+    assert_eq!(house.deferred_until.as_deref(), Some("2024-03-01"));
+    assert_eq!(names(&house.deps), vec!["Lease signed", "Book a van"]);
+
+    let lease = find("Lease signed");
+    assert_eq!(lease.node_type, NodeType::Condition);
+    assert_eq!(lease.priority, Some('B'));
+    assert_eq!(lease.uuid.as_deref(), Some("lease"));
+    assert_eq!(names(&lease.parents), vec!["Move house", "Pay the deposit"]);
+
+    let van = find("Book a van");
+    assert_eq!(van.due_date.as_deref(), Some("2024-03-15"));
+    assert_eq!(van.notes.as_deref(), Some("Ask about\n  straps."));
+    assert_eq!(van.time.len(), 1);
+    assert_eq!(van.time[0].length(None, Utc::now()), Duration::hours(1));
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn csv_round_trip_is_lossless()
+{
+    let mut graph = with_history(sample());
+    let copy = round_trip(&mut graph, Format::Csv);
+    assert_eq!(normalized(&copy), normalized(&graph));
+}
+
+#[test]
+fn csv_export_has_depth_and_status()
+{
+    let mut graph = sample();
+    let content = formats::export(&mut graph, Format::Csv, None).unwrap().content;
+    let lines: Vec<&str> = content.split("\r\n").collect();
+    assert_eq!(lines[0], "id,type,description,parents,deps,depth,due,status,priority,tags,notes,recurrence,completed,deferred,time");
+    assert_eq!(lines[1], "0,goal,house,,1 5,0,,blocked,,,,,,,");
+    assert_eq!(lines[2], "1,task,paint the fence,0,2 3,1,,blocked,A,'+home;@store;weekend plans,\"Two coats.\nThe *blue* one, 100%.\",,,,");
+    assert_eq!(lines[5], "4,goal,garden,,5,0,,open,,,,,,,");
+    assert_eq!(lines[6], "5,task,x marks the spot,4 0,,1,2024-06-01,leaf,,,,,,,");
+
+    let mut graph = with_history(graph);
+    let content = formats::export(&mut graph, Format::Csv, None).unwrap().content;
+    assert!(content.contains("\r\n2,task,buy paint,1,,2,,leaf,,,,\"weekly on mon, thu\",2024-05-02;2024-05-06,,2024-05-02T09:00:00Z/2024-05-02T09:45:00Z\r\n"), "{}", content);
+    assert!(content.ends_with(",every 3 days after completion,,2024-05-10,\r\n"), "{}", content);
+
+    // under a node, depths start there and links leading out are left out
+    let content = formats::export(&mut graph, Format::Csv, Some(1)).unwrap().content;
+    assert!(content.contains("\r\n1,task,paint the fence,,2 3,0,"));
+}
+
+#[test]
+fn csv_keeps_formulas_from_running()
+{
+    let mut graph = Graph::new(Config::default());
+    for description in ["=HYPERLINK(\"http://example.com\")", "'=quoted", "-5 degrees", "@home", "it's fine", "'plain"]
+    {
+        graph.add_node_to(description.to_string(), NodeType::Task, None).unwrap();
+    }
+    graph.set_notes(0, Some(String::from("+1 more"))).unwrap();
+
+    let content = formats::export(&mut graph, Format::Csv, None).unwrap().content;
+    let lines: Vec<&str> = content.split("\r\n").collect();
+    assert_eq!(lines[1], "0,task,\"'=HYPERLINK(\"\"http://example.com\"\")\",,,0,,leaf,,,'+1 more,,,,");
+    assert!(lines[2].starts_with("1,task,''=quoted,"));
+    assert!(lines[3].starts_with("2,task,'-5 degrees,"));
+    assert!(lines[4].starts_with("3,task,'@home,"));
+    assert!(lines[5].starts_with("4,task,it's fine,"));
+    assert!(lines[6].starts_with("5,task,'plain,"));
+
+    let copy = round_trip(&mut graph, Format::Csv);
+    assert_eq!(normalized(&copy), normalized(&graph));
+}
+
+#[test]
+fn csv_import_finds_columns_by_header()
+{
+    let mut graph = Graph::new(Config::default());
+    let report = formats::import(&mut graph, Format::Csv, "\
+Description,ID,Parents,Type,Owner
+Launch,l,,goal,Kim
+\"Write the \"\"launch\"\" post\",p,l,,Ann
+Reviewed,r,p,condition,
+", None).unwrap();
+    assert_eq!(report.added.len(), 3);
+    assert_eq!(report.warnings, vec!["Left out the columns owner, which todos doesn't keep."]);
+    assert_eq!(graph.node(1).unwrap().description, "Write the \"launch\" post");
+    assert_eq!(graph.node(1).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(graph.node(2).unwrap().node_type, NodeType::Condition);
+
+    // a cycle is rejected before anything is added
+    let result = formats::import(&mut graph, Format::Csv, "id,description,deps\na,A,b\nb,B,a\n", None);
+    assert!(matches!(result, Err(TodosError::Cycle(_))), "{:?}", result);
+    assert_eq!(graph.nodes().len(), 3);
+}
+
+#[test]
+fn html_report_has_the_tree_and_the_dag()
+{
+    let mut graph = sample();
+    graph.add_node_to("backlog".to_string(), NodeType::Goal, Some(4)).unwrap();
+    graph.add_node_to("a <pond>".to_string(), NodeType::Task, Some(6)).unwrap();
+    let html = formats::export(&mut graph, Format::Html, None).unwrap().content;
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<p class=\"summary\">8 nodes, 4 leaves, 2 blocked."));
+    // the config's colors, as CSS
+    assert!(html.contains(".goal, svg .goal { font-weight: bold; color: #3b78ff; }"));
+    assert!(html.contains("<li id=\"node-0\"><details open><summary><span class=\"goal\">house</span> <span class=\"badge blocked\">blocked</span>"));
+    assert!(html.contains("<li id=\"node-2\"><span class=\"task\">buy paint</span> <span class=\"badge leaf\">leaf</span></li>"));
+    // shared nodes are written once, and the backlog starts closed
+    assert!(html.contains("<li><a class=\"task\" href=\"#node-5\">x marks the spot</a>"));
+    assert!(html.contains("<li id=\"node-6\"><details><summary>"));
+    assert!(html.contains("a &lt;pond&gt;"));
+
+    assert_eq!(html.matches("<rect ").count(), 8);
+    assert_eq!(html.matches("class=\"edge\"").count(), 7);
+
+    // from the effective root
+    graph.set_effective_root(Some(4)).unwrap();
+    let html = formats::export(&mut graph, Format::Html, None).unwrap().content;
+    assert!(html.contains("<title>todos: garden</title>"));
+    assert_eq!(html.matches("<rect ").count(), 4);
+
+    assert!(matches!(formats::import(&mut graph, Format::Html, &html, None), Err(TodosError::Invalid(_))));
+}
@@ -0,0 +1,371 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use todos::storage::Lock;
+use todos::{Config, Graph, NodeType, TodosError};
+
+fn add(graph: &mut Graph, description: &str, node_type: NodeType, to: Option<usize>) -> usize
+{
+    graph.add_node_to(description.to_string(), node_type, to).unwrap()
+}
+
+fn descriptions(graph: &Graph, ids: impl IntoIterator<Item = usize>) -> Vec<String>
+{
+    ids.into_iter().map(|id| graph.node(id).unwrap().description.clone()).collect()
+}
+
+/// Loads a todos file with the given contents, bypassing every check the
+/// graph API makes.
+fn load_json(name: &str, content: &str) -> Result<Graph, TodosError>
+{
+    let path: PathBuf = std::env::temp_dir().join(format!("todos-test-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    let graph = Graph::open(&path, Config::default());
+    fs::remove_file(&path).unwrap();
+    graph
+}
+
+#[test]
+fn validate_accepts_a_well_formed_file()
+{
+    let graph = load_json("well-formed", r#"{"version":1,"effective_root":null,"nodes":[
+        {"id":0,"description":"a","node_type":"Goal","due_date":null,"deps":[1],"parents":[]},
+        {"id":1,"description":"b","node_type":"Task","due_date":null,"deps":[],"parents":[0]}
+    ]}"#).unwrap();
+
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn validate_rejects_one_sided_links()
+{
+    let graph = load_json("one-sided", r#"{"version":1,"effective_root":null,"nodes":[
+        {"id":0,"description":"a","node_type":"Goal","due_date":null,"deps":[1],"parents":[]},
+        {"id":1,"description":"b","node_type":"Task","due_date":null,"deps":[],"parents":[]}
+    ]}"#).unwrap();
+
+    match graph.validate()
+    {
+        Err(TodosError::OneSidedLink { parent: 0, child: 1 }) => {},
+        other => panic!("expected a one-sided link, got {:?}", other)
+    }
+}
+
+#[test]
+fn validate_rejects_dangling_links_and_bad_ids()
+{
+    let dangling = load_json("dangling", r#"{"version":1,"effective_root":null,"nodes":[
+        {"id":0,"description":"a","node_type":"Goal","due_date":null,"deps":[7],"parents":[]}
+    ]}"#).unwrap();
+    assert!(matches!(dangling.validate(), Err(TodosError::DanglingLink { node: 0, missing: 7 })));
+
+    let misnumbered = load_json("misnumbered", r#"{"version":1,"effective_root":null,"nodes":[
+        {"id":3,"description":"a","node_type":"Goal","due_date":null,"deps":[],"parents":[]}
+    ]}"#).unwrap();
+    assert!(matches!(misnumbered.validate(), Err(TodosError::IdMismatch { index: 0, id: 3 })));
+}
+
+#[test]
+fn check_topology_names_the_cycle()
+{
+    let graph = load_json("cycle", r#"{"version":1,"effective_root":null,"nodes":[
+        {"id":0,"description":"top","node_type":"Goal","due_date":null,"deps":[1],"parents":[]},
+        {"id":1,"description":"a","node_type":"Task","due_date":null,"deps":[2],"parents":[0,3]},
+        {"id":2,"description":"b","node_type":"Task","due_date":null,"deps":[3],"parents":[1]},
+        {"id":3,"description":"c","node_type":"Task","due_date":null,"deps":[1],"parents":[2]}
+    ]}"#).unwrap();
+
+    match graph.check_topology()
+    {
+        Err(TodosError::Cycle(nodes)) => {
+            let ids: Vec<usize> = nodes.iter().map(|x| x.id).collect();
+            assert_eq!(ids.first(), ids.last());
+            let mut members = ids[1..].to_vec();
+            members.sort();
+            assert_eq!(members, vec![1, 2, 3]);
+        },
+        other => panic!("expected a cycle, got {:?}", other)
+    }
+}
+
+#[test]
+fn files_without_a_version_are_migrated()
+{
+    let graph = load_json("unversioned", r#"{"nodes":[]}"#).unwrap();
+    assert_eq!(graph.nodes().len(), 0);
+    assert_eq!(graph.effective_root(), None);
+
+    let future = load_json("future", r#"{"version":999,"effective_root":null,"nodes":[]}"#);
+    assert!(matches!(future, Err(TodosError::UnsupportedVersion { found: 999, .. })));
+}
+
+#[test]
+fn link_rejects_cycles_without_changing_anything()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Task, Some(a));
+    let c = add(&mut graph, "c", NodeType::Task, Some(b));
+
+    match graph.link(&c, &a)
+    {
+        Err(TodosError::Cycle(nodes)) => {
+            assert_eq!(nodes.iter().map(|x| x.id).collect::<Vec<_>>(), vec![c, a, b, c]);
+        },
+        other => panic!("expected a cycle, got {:?}", other)
+    }
+    assert!(graph.node(c).unwrap().deps.is_empty());
+    assert!(graph.node(a).unwrap().parents.is_empty());
+
+    assert!(matches!(graph.link(&b, &b), Err(TodosError::Cycle(_))));
+    assert!(matches!(graph.link(&a, &9), Err(TodosError::NotFound(9))));
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn add_node_above_takes_over_the_parents()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Goal, None);
+    let c = add(&mut graph, "c", NodeType::Task, Some(a));
+    graph.link(&b, &c).unwrap();
+
+    let d = graph.add_node_above("d".to_string(), NodeType::Task, c).unwrap();
+
+    assert_eq!(graph.node(d).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(graph.node(d).unwrap().deps.iter().copied().collect::<Vec<_>>(), vec![c]);
+    assert_eq!(graph.node(c).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![d]);
+    assert_eq!(graph.node(a).unwrap().deps.iter().copied().collect::<Vec<_>>(), vec![d]);
+    assert!(graph.validate().is_ok());
+
+    assert!(matches!(graph.add_node_above("e".to_string(), NodeType::Task, 42), Err(TodosError::NotFound(42))));
+    assert_eq!(graph.nodes().len(), 4);
+}
+
+#[test]
+fn batch_remove_keeps_ids_contiguous()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Task, Some(a));
+    add(&mut graph, "c", NodeType::Task, Some(b));
+    let d = add(&mut graph, "d", NodeType::Goal, None);
+    let e = add(&mut graph, "e", NodeType::Task, Some(d));
+    let f = add(&mut graph, "f", NodeType::Task, Some(e));
+    graph.link(&a, &f).unwrap();
+
+    // completing b completes c too
+    graph.batch_remove(vec![b], true).unwrap();
+
+    assert!(graph.validate().is_ok());
+    assert_eq!(graph.nodes().len(), 4);
+    for (i, node) in graph.nodes().iter().enumerate()
+    {
+        assert_eq!(node.id, i);
+    }
+
+    // the nodes that took over the freed ids kept their links
+    let by_name = |name: &str| graph.nodes().iter().find(|x| x.description == name).unwrap().id;
+    let (a, d, e, f) = (by_name("a"), by_name("d"), by_name("e"), by_name("f"));
+    assert_eq!(descriptions(&graph, graph.node(a).unwrap().deps.iter().copied()), vec!["f"]);
+    assert_eq!(descriptions(&graph, graph.node(d).unwrap().deps.iter().copied()), vec!["e"]);
+    assert_eq!(descriptions(&graph, graph.node(e).unwrap().deps.iter().copied()), vec!["f"]);
+    let mut parents = descriptions(&graph, graph.node(f).unwrap().parents.iter().copied());
+    parents.sort();
+    assert_eq!(parents, vec!["a", "e"]);
+}
+
+#[test]
+fn batch_remove_is_all_or_nothing()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Task, None);
+    add(&mut graph, "b", NodeType::Task, None);
+
+    assert!(matches!(graph.batch_remove(vec![a, 5], false), Err(TodosError::NotFound(5))));
+    assert_eq!(graph.nodes().len(), 2);
+}
+
+#[test]
+fn removing_the_effective_root_goes_back_to_root()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Goal, None);
+    let c = add(&mut graph, "c", NodeType::Goal, None);
+
+    graph.set_effective_root(Some(c)).unwrap();
+    graph.remove_node(a, false).unwrap();
+    assert_eq!(graph.node(graph.effective_root().unwrap()).unwrap().description, "c");
+
+    graph.set_effective_root(Some(b)).unwrap();
+    graph.remove_node(b, false).unwrap();
+    assert_eq!(graph.effective_root(), None);
+}
+
+#[test]
+fn move_node_rolls_back_on_cycles()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Goal, None);
+    let c = add(&mut graph, "c", NodeType::Task, Some(a));
+    graph.link(&b, &c).unwrap();
+    let d = add(&mut graph, "d", NodeType::Task, Some(c));
+
+    assert!(matches!(graph.move_node(c, Some(d)), Err(TodosError::Cycle(_))));
+    let mut parents: Vec<usize> = graph.node(c).unwrap().parents.iter().copied().collect();
+    parents.sort();
+    assert_eq!(parents, vec![a, b]);
+    assert!(graph.validate().is_ok());
+
+    graph.move_node(c, Some(b)).unwrap();
+    assert_eq!(graph.node(c).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![b]);
+    assert!(graph.node(a).unwrap().deps.is_empty());
+
+    graph.move_node(c, None).unwrap();
+    assert!(graph.node(c).unwrap().parents.is_empty());
+}
+
+#[test]
+fn move_node_above_inserts_between()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Task, Some(a));
+    let c = add(&mut graph, "c", NodeType::Goal, None);
+
+    graph.move_node_above(c, b).unwrap();
+
+    assert_eq!(graph.node(a).unwrap().deps.iter().copied().collect::<Vec<_>>(), vec![c]);
+    assert_eq!(graph.node(c).unwrap().deps.iter().copied().collect::<Vec<_>>(), vec![b]);
+    assert!(matches!(graph.move_node_above(c, c), Err(TodosError::Invalid(_))));
+    assert!(matches!(graph.move_node_above(a, b), Err(TodosError::Cycle(_))));
+    assert_eq!(graph.node(b).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![c]);
+}
+
+#[test]
+fn transactions_undo_additions_and_removals()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Goal, None);
+    let b = add(&mut graph, "b", NodeType::Task, Some(a));
+    add(&mut graph, "c", NodeType::Task, Some(b));
+    let before = serde_json::to_string(&graph).unwrap();
+
+    let result: Result<(), TodosError> = graph.transaction(|graph| {
+        graph.add_node_to("d".to_string(), NodeType::Task, Some(a))?;
+        graph.remove_node(a, false)?;
+        graph.relabel(b, "renamed".to_string())?;
+        Err(TodosError::Invalid("changed my mind".to_string()))
+    });
+
+    assert!(result.is_err());
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+}
+
+#[test]
+fn notes_and_tags()
+{
+    let mut graph = Graph::new(Config::default());
+    let a = add(&mut graph, "a", NodeType::Task, None);
+
+    graph.set_notes(a, Some("details".to_string())).unwrap();
+    graph.tag(a, vec!["x".to_string(), "y".to_string(), "x".to_string()]).unwrap();
+    assert_eq!(graph.node(a).unwrap().notes.as_deref(), Some("details"));
+    assert_eq!(graph.node(a).unwrap().tags, vec!["x", "y"]);
+
+    graph.set_notes(a, Some(" \n".to_string())).unwrap();
+    graph.untag(a, &["x".to_string()]).unwrap();
+    assert_eq!(graph.node(a).unwrap().notes, None);
+    assert_eq!(graph.node(a).unwrap().tags, vec!["y"]);
+
+    assert!(matches!(graph.set_notes(3, None), Err(TodosError::NotFound(3))));
+}
+
+#[test]
+fn locked_changes_start_from_the_latest_file()
+{
+    let path: PathBuf = std::env::temp_dir().join(format!("todos-test-{}-locked", std::process::id()));
+    let mut first = Graph::new(Config::default());
+    first.set_storage(Box::new(todos::storage::JsonStorage::new(path.clone())));
+    first.save().unwrap();
+    let mut second = Graph::open(&path, Config::default()).unwrap();
+
+    first.locked(|graph| graph.add_node_to("a".to_string(), NodeType::Goal, None)).unwrap().unwrap();
+    let b = second.locked(|graph| graph.add_node_to("b".to_string(), NodeType::Task, Some(0))).unwrap().unwrap();
+    assert_eq!(b, 1);
+
+    first.reload().unwrap();
+    assert_eq!(descriptions(&first, first.node(0).unwrap().deps.clone()), vec!["b"]);
+    assert!(!path.with_file_name(format!("todos-test-{}-locked.lock", std::process::id())).exists());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn one_waiter_at_a_time_takes_over_a_stale_lock()
+{
+    let path: PathBuf = std::env::temp_dir().join(format!("todos-test-{}-stale", std::process::id()));
+    let lock_path = path.with_file_name(format!("todos-test-{}-stale.lock", std::process::id()));
+    let holders = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..20
+    {
+        // left behind by a process that died a minute ago
+        fs::File::create(&lock_path).unwrap().set_modified(SystemTime::now() - Duration::from_secs(60)).unwrap();
+
+        let start = Arc::new(Barrier::new(2));
+        let waiters: Vec<_> = (0..2).map(|_| {
+            let (path, holders, start) = (path.clone(), holders.clone(), start.clone());
+            thread::spawn(move || {
+                start.wait();
+                let _lock = Lock::acquire(&path).unwrap();
+                assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0, "two waiters hold the lock");
+                thread::sleep(Duration::from_millis(10));
+                holders.fetch_sub(1, Ordering::SeqCst);
+            })
+        }).collect();
+        for waiter in waiters
+        {
+            waiter.join().unwrap();
+        }
+        assert!(!lock_path.exists());
+    }
+
+    // nothing is left behind from moving stale locks out of the way
+    let prefix = format!("todos-test-{}-stale.lock", std::process::id());
+    assert!(!fs::read_dir(std::env::temp_dir()).unwrap().any(|x| x.unwrap().file_name().to_string_lossy().starts_with(&prefix)));
+}
+
+#[test]
+fn deferred_nodes_are_hidden_until_their_day()
+{
+    let mut graph = Graph::new(Config::default());
+    let home = add(&mut graph, "home", NodeType::Goal, None);
+    let paint = add(&mut graph, "paint", NodeType::Task, Some(home));
+    add(&mut graph, "buy paint", NodeType::Task, Some(paint));
+    let sweep = add(&mut graph, "sweep", NodeType::Task, Some(home));
+    let today = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let shown = |graph: &Graph, overwhelm: bool| graph.render_todos(overwhelm).lines().count();
+
+    graph.defer(paint, todos::dates::parse_day("2999-01-01", today)).unwrap();
+    graph.defer(sweep, todos::dates::parse_day("friday", today)).unwrap();
+    assert_eq!(graph.node(sweep).unwrap().deferred_until.as_deref(), Some("2024-03-08"));
+    assert_eq!(graph.deferred(today), vec![sweep, paint]);
+
+    // sweep's day has long passed
+    assert_eq!(shown(&graph, false), 2);
+    assert_eq!(graph.actionable_leaves(None).unwrap(), vec![sweep]);
+    assert_eq!(shown(&graph, true), 4);
+    let mut under = String::new();
+    graph.render_show(&mut under, &paint, 0, false, Some(paint)).unwrap();
+    assert_eq!(under.lines().count(), 1);
+
+    graph.defer(paint, None).unwrap();
+    assert_eq!(shown(&graph, false), 3);
+    assert!(graph.render_deferred(chrono::NaiveDate::from_ymd_opt(2024, 3, 7).unwrap()).starts_with("2024-03-08  "));
+}
@@ -0,0 +1,113 @@
+//! Randomized operation sequences checking that the graph stays a DAG with
+//! contiguous ids and double-sided links after every step, and that failed
+//! operations leave it untouched.
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use todos::{Config, Graph, NodeType};
+
+#[derive(Clone, Debug)]
+enum Op
+{
+    Add(NodeType, Option<Index>),
+    AddAbove(NodeType, Index),
+    Link(Index, Index),
+    Unlink(Index, Index),
+    Complete(Vec<Index>, bool),
+    Move(Index, Option<Index>),
+    MoveAbove(Index, Index),
+    Use(Option<Index>)
+}
+
+fn node_type() -> impl Strategy<Value = NodeType>
+{
+    prop_oneof![Just(NodeType::Goal), Just(NodeType::Task), Just(NodeType::Condition)]
+}
+
+fn op() -> impl Strategy<Value = Op>
+{
+    prop_oneof![
+        4 => (node_type(), any::<Option<Index>>()).prop_map(|(t, to)| Op::Add(t, to)),
+        1 => (node_type(), any::<Index>()).prop_map(|(t, above)| Op::AddAbove(t, above)),
+        4 => (any::<Index>(), any::<Index>()).prop_map(|(a, b)| Op::Link(a, b)),
+        2 => (any::<Index>(), any::<Index>()).prop_map(|(a, b)| Op::Unlink(a, b)),
+        2 => (prop::collection::vec(any::<Index>(), 1..3), any::<bool>()).prop_map(|(ids, recurse)| Op::Complete(ids, recurse)),
+        2 => (any::<Index>(), any::<Option<Index>>()).prop_map(|(id, to)| Op::Move(id, to)),
+        1 => (any::<Index>(), any::<Index>()).prop_map(|(id, above)| Op::MoveAbove(id, above)),
+        1 => any::<Option<Index>>().prop_map(Op::Use)
+    ]
+}
+
+/// Applies `op`, picking ids among the nodes currently in the graph.
+fn apply(graph: &mut Graph, op: &Op, counter: usize) -> Result<(), todos::TodosError>
+{
+    let len = graph.nodes().len();
+    let pick = |index: &Index| index.index(len);
+    let description = format!("node {}", counter);
+
+    if len == 0
+    {
+        return match op
+        {
+            Op::Add(node_type, _) => graph.add_node_to(description, node_type.clone(), None).map(|_| ()),
+            _ => Ok(())
+        };
+    }
+
+    match op
+    {
+        Op::Add(node_type, to) => graph.add_node_to(description, node_type.clone(), to.as_ref().map(pick)).map(|_| ()),
+        Op::AddAbove(node_type, above) => graph.add_node_above(description, node_type.clone(), pick(above)).map(|_| ()),
+        Op::Link(a, b) => graph.link(&pick(a), &pick(b)),
+        Op::Unlink(a, b) => graph.unlink(&pick(a), &pick(b)),
+        Op::Complete(ids, recurse) => graph.batch_remove(ids.iter().map(pick).collect(), *recurse),
+        Op::Move(id, to) => graph.move_node(pick(id), to.as_ref().map(pick)),
+        Op::MoveAbove(id, above) => graph.move_node_above(pick(id), pick(above)),
+        Op::Use(root) => graph.set_effective_root(root.as_ref().map(pick))
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn invariants_hold_after_every_operation(ops in prop::collection::vec(op(), 1..60))
+    {
+        let mut graph = Graph::new(Config::default());
+
+        for (i, op) in ops.iter().enumerate()
+        {
+            let before = serde_json::to_string(&graph).unwrap();
+
+            let result = apply(&mut graph, op, i);
+
+            prop_assert!(graph.validate().is_ok(), "{:?} broke the graph: {:?}", op, graph.validate());
+            if let Some(root) = graph.effective_root()
+            {
+                prop_assert!(root < graph.nodes().len(), "{:?} left a dangling effective root", op);
+            }
+            if result.is_err()
+            {
+                prop_assert_eq!(&serde_json::to_string(&graph).unwrap(), &before, "failed {:?} changed the graph", op);
+            }
+        }
+    }
+
+    #[test]
+    fn link_succeeds_exactly_when_no_path_leads_back(ops in prop::collection::vec(op(), 1..40), a in any::<Index>(), b in any::<Index>())
+    {
+        let mut graph = Graph::new(Config::default());
+        for (i, op) in ops.iter().enumerate()
+        {
+            let _ = apply(&mut graph, op, i);
+        }
+        prop_assume!(!graph.nodes().is_empty());
+
+        let parent = a.index(graph.nodes().len());
+        let child = b.index(graph.nodes().len());
+        let closes_a_cycle = parent == child || graph.find_path(child, parent).is_some();
+
+        prop_assert_eq!(graph.link(&parent, &child).is_ok(), !closes_a_cycle);
+        prop_assert!(graph.check_topology().is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use todos::outline::{self, Entry, Summary};
+use todos::{Config, Graph, NodeType, TodosError};
+
+fn sample() -> Graph
+{
+    let mut graph = Graph::new(Config::default());
+    let mut add = |description: &str, node_type: NodeType, to: Option<usize>| {
+        graph.add_node_to(description.to_string(), node_type, to).unwrap()
+    };
+
+    let house = add("house", NodeType::Goal, None);
+    let paint = add("paint the fence", NodeType::Task, Some(house));
+    add("buy paint", NodeType::Task, Some(paint));
+    add("dry weather", NodeType::Condition, Some(paint));
+    let garden = add("garden", NodeType::Goal, None);
+    let weed = add("pull weeds", NodeType::Task, Some(garden));
+
+    graph.link(&house, &weed).unwrap();
+    graph
+}
+
+fn edit(graph: &mut Graph, root: Option<usize>, text: &str) -> Result<Summary, TodosError>
+{
+    let entries = outline::parse(text)?;
+    outline::apply(graph, root, &entries)
+}
+
+#[test]
+fn render_lists_shared_children_once()
+{
+    let graph = sample();
+
+    assert_eq!(outline::render(&graph, None).unwrap(), "\
+- #0 goal: house
+  - #1 paint the fence
+    - #2 buy paint
+    - #3 ? dry weather
+  - #5 pull weeds
+- #4 goal: garden
+  - #5 pull weeds
+");
+    assert_eq!(outline::render(&graph, Some(1)).unwrap(), "- #2 buy paint\n- #3 ? dry weather\n");
+}
+
+#[test]
+fn parse_understands_bullets_prefixes_and_indentation()
+{
+    let entries = outline::parse("\
+// a comment
+* goal: big
+    - indented task
+\t? waiting
+  condition: also waiting
+- #7
+plain
+").unwrap();
+
+    let summary: Vec<(Option<usize>, NodeType, &str, Option<usize>)> = entries
+        .iter()
+        .map(|x| (x.id, x.node_type.clone(), &x.description[..], x.parent))
+        .collect();
+    assert_eq!(summary, vec![
+        (None, NodeType::Goal, "big", None),
+        (None, NodeType::Task, "indented task", Some(0)),
+        (None, NodeType::Condition, "waiting", Some(0)),
+        (None, NodeType::Condition, "also waiting", Some(0)),
+        (Some(7), NodeType::Task, "", None),
+        (None, NodeType::Task, "plain", None)
+    ]);
+    assert_eq!(entries[1], Entry
+    {
+        line: 3,
+        id: None,
+        node_type: NodeType::Task,
+        description: String::from("indented task"),
+        parent: Some(0),
+        done: false
+    });
+
+    assert!(matches!(outline::parse("- goal:\n"), Err(TodosError::Invalid(_))));
+}
+
+#[test]
+fn applying_the_rendered_outline_changes_nothing()
+{
+    let mut graph = sample();
+    let before = serde_json::to_string(&graph).unwrap();
+
+    let text = outline::render(&graph, None).unwrap();
+    assert_eq!(edit(&mut graph, None, &text).unwrap(), Summary::default());
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+
+    let text = outline::render(&graph, Some(0)).unwrap();
+    assert_eq!(edit(&mut graph, Some(0), &text).unwrap(), Summary::default());
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+}
+
+#[test]
+fn tasks_that_look_like_prefixes_read_back_as_tasks()
+{
+    let tricky = ["goal: none", "condition: none", "? why", "task: write", "[x] marks", "[ ] box", "// not a comment", "#12 bug", "#hashtag"];
+    let mut graph = Graph::new(Config::default());
+    let goal = graph.add_node_to(String::from("? goal"), NodeType::Goal, None).unwrap();
+    for description in tricky
+    {
+        graph.add_node_to(description.to_string(), NodeType::Task, Some(goal)).unwrap();
+    }
+
+    let text = outline::render(&graph, None).unwrap();
+    assert!(text.contains("- #3 task: ? why\n") && text.contains("- #9 #hashtag\n"), "{}", text);
+    let before = serde_json::to_string(&graph).unwrap();
+    assert_eq!(edit(&mut graph, None, &text).unwrap(), Summary::default());
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+
+    for entries in [outline::parse(&text).unwrap(), outline::parse_plain(&outline::render_plain(&graph, None).unwrap()).unwrap()]
+    {
+        let read: Vec<(NodeType, &str, bool)> = entries.iter().map(|x| (x.node_type.clone(), &x.description[..], x.done)).collect();
+        let mut expected = vec![(NodeType::Goal, "? goal", false)];
+        expected.extend(tricky.iter().map(|x| (NodeType::Task, *x, false)));
+        assert_eq!(read, expected);
+    }
+}
+
+#[test]
+fn apply_restructures_a_subtree()
+{
+    let mut graph = sample();
+
+    // rename and retype 3, drop 2, add a task with a subtask, and move 1 under 5
+    let summary = edit(&mut graph, Some(0), "\
+- #5 pull weeds
+  - #1 paint the fence
+    - #3 sunny weather
+    - buy brushes
+      - go to the store
+").unwrap();
+
+    assert_eq!(summary, Summary { added: 2, removed: 1, changed: 1, linked: 1, unlinked: 1 });
+    assert!(graph.validate().is_ok());
+    assert_eq!(graph.nodes().len(), 7);
+
+    let find = |description: &str| graph.nodes().iter().find(|x| x.description == description).unwrap();
+    let descriptions = |ids: &todos::node::Links| ids.iter().map(|x| graph.nodes()[*x].description.clone()).collect::<Vec<_>>();
+
+    assert_eq!(descriptions(&find("house").deps), vec!["pull weeds"]);
+    // links from outside the edited tree are kept
+    assert_eq!(descriptions(&find("pull weeds").parents), vec!["garden", "house"]);
+    assert_eq!(descriptions(&find("paint the fence").parents), vec!["pull weeds"]);
+    assert_eq!(descriptions(&find("paint the fence").deps), vec!["sunny weather", "buy brushes"]);
+    assert_eq!(find("sunny weather").node_type, NodeType::Task);
+    assert_eq!(descriptions(&find("go to the store").parents), vec!["buy brushes"]);
+    assert!(graph.nodes().iter().all(|x| x.description != "buy paint"));
+}
+
+#[test]
+fn new_top_level_lines_stay_at_the_top()
+{
+    let mut graph = sample();
+    graph.set_effective_root(Some(4)).unwrap();
+
+    let mut text = outline::render(&graph, None).unwrap();
+    text.push_str("- goal: car\n");
+    edit(&mut graph, None, &text).unwrap();
+
+    let car = graph.nodes().iter().find(|x| x.description == "car").unwrap();
+    assert!(car.parents.is_empty());
+}
+
+#[test]
+fn failed_edits_leave_the_graph_alone()
+{
+    let mut graph = sample();
+    let before = serde_json::to_string(&graph).unwrap();
+
+    // 1 can't be both above and below 2
+    let cycle = edit(&mut graph, Some(0), "\
+- #1 paint the fence
+  - #2 buy paint
+    - #1
+  - added
+- #5 pull weeds
+");
+    assert!(matches!(cycle, Err(TodosError::Cycle(_))));
+
+    let outside = edit(&mut graph, Some(1), "- #2 buy paint\n- #4 garden\n");
+    assert!(matches!(outside, Err(TodosError::Invalid(_))));
+
+    let conflicting = edit(&mut graph, None, "- #0 goal: house\n  - #5 a\n- #4 goal: garden\n  - #5 b\n");
+    assert!(matches!(conflicting, Err(TodosError::Invalid(_))));
+
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+}
+
+#[test]
+fn import_adds_a_checklist_under_a_node()
+{
+    let mut graph = sample();
+    let entries = outline::parse_plain("\
+# Moving
+- [ ] goal: new flat
+  - [ ] ? landlord replies
+  - [x] sign lease
+    - [ ] read lease
+  - [ ] pack
+    1. books
+    2) #12 kitchen stuff
+## Later
+* [X] done already
+* tidy up
+").unwrap();
+
+    let (ids, skipped) = outline::import(&mut graph, Some(4), &entries).unwrap();
+
+    assert_eq!(skipped, 3);
+    assert!(graph.validate().is_ok());
+    let added: Vec<(NodeType, &str)> = ids.iter().map(|x| {
+        let node = graph.node(*x).unwrap();
+        (node.node_type.clone(), &node.description[..])
+    }).collect();
+    assert_eq!(added, vec![
+        (NodeType::Goal, "Moving"),
+        (NodeType::Goal, "new flat"),
+        (NodeType::Condition, "landlord replies"),
+        (NodeType::Task, "pack"),
+        (NodeType::Task, "books"),
+        (NodeType::Task, "#12 kitchen stuff"),
+        (NodeType::Goal, "Later"),
+        (NodeType::Task, "tidy up")
+    ]);
+
+    let parent = |id: usize| graph.node(id).unwrap().parents.iter().copied().collect::<Vec<_>>();
+    assert_eq!(parent(ids[0]), vec![4]);
+    assert_eq!(parent(ids[1]), vec![ids[0]]);
+    assert_eq!(parent(ids[5]), vec![ids[3]]);
+    assert_eq!(parent(ids[6]), vec![ids[0]]);
+    assert_eq!(parent(ids[7]), vec![ids[6]]);
+}
+
+#[test]
+fn import_goes_under_the_effective_root_and_is_all_or_nothing()
+{
+    let mut graph = sample();
+    graph.set_effective_root(Some(1)).unwrap();
+
+    let (ids, _) = outline::import(&mut graph, None, &outline::parse_plain("- a\n").unwrap()).unwrap();
+    assert_eq!(graph.node(ids[0]).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![1]);
+
+    let before = serde_json::to_string(&graph).unwrap();
+    assert!(matches!(outline::import(&mut graph, Some(42), &outline::parse_plain("- a\n").unwrap()), Err(TodosError::NotFound(42))));
+    assert!(matches!(outline::import(&mut graph, None, &outline::parse("- b\n- #3\n").unwrap()), Err(TodosError::Invalid(_))));
+    assert_eq!(serde_json::to_string(&graph).unwrap(), before);
+}
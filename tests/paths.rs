@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use todos::paths::{self, Paths};
+
+fn touch(path: &Path)
+{
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, "").unwrap();
+}
+
+// the only test in this file, as it changes the environment and the
+// current directory of the whole process
+#[test]
+fn todos_file_is_looked_for_in_order()
+{
+    let root = env::temp_dir().canonicalize().unwrap().join(format!("todos-test-{}-paths", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let deeper = root.join("project").join("sub").join("deeper");
+    fs::create_dir_all(&deeper).unwrap();
+    env::set_var("HOME", root.join("home"));
+    env::set_var("XDG_DATA_HOME", root.join("data"));
+    env::set_var("XDG_CONFIG_HOME", root.join("config"));
+    env::remove_var(paths::TODOS_FILE_VAR);
+    env::set_current_dir(&deeper).unwrap();
+
+    // with nothing around, the XDG directories
+    let found = Paths::resolve(None);
+    assert_eq!(found.todos_file, root.join("data").join("todos").join("todos"));
+    assert_eq!(found.config_file, root.join("config").join("todos").join("config.toml"));
+
+    // an install from before XDG support keeps its files
+    let legacy = root.join("home").join(".todos");
+    touch(&legacy.join("todos"));
+    touch(&legacy.join("config.toml"));
+    let found = Paths::resolve(None);
+    assert_eq!(found.todos_file, legacy.join("todos"));
+    assert_eq!(found.config_file, legacy.join("config.toml"));
+
+    // a project file above the current directory wins, the nearest one if
+    // there are several
+    touch(&root.join("project").join(".todos"));
+    assert_eq!(Paths::resolve(None).todos_file, root.join("project").join(".todos"));
+    touch(&root.join("project").join("sub").join(".todos"));
+    assert_eq!(Paths::resolve(None).todos_file, root.join("project").join("sub").join(".todos"));
+    assert_eq!(paths::find_project_file(&deeper), Some(root.join("project").join("sub").join(".todos")));
+    assert_eq!(paths::find_project_file(&root.join("project")), Some(root.join("project").join(".todos")));
+
+    // then $TODOS_FILE, then --file
+    env::set_var(paths::TODOS_FILE_VAR, root.join("from-env"));
+    assert_eq!(Paths::resolve(None).todos_file, root.join("from-env"));
+    let found = Paths::resolve(Some(root.join("from-flag")));
+    assert_eq!(found.todos_file, root.join("from-flag"));
+    assert_eq!(found.config_file, legacy.join("config.toml"));
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,92 @@
+use chrono::NaiveDate;
+use todos::dates::{Due, Recurrence};
+use todos::{Config, Graph, NodeType};
+
+fn day(s: &str) -> NaiveDate
+{
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+fn next(rule: &str, due: Option<&str>, today: &str) -> String
+{
+    Recurrence::parse(rule).unwrap().next(due.and_then(Due::parse), day(today)).to_string()
+}
+
+#[test]
+fn rules_read_back_what_they_write()
+{
+    for (rule, canonical) in [
+        ("daily", "daily"),
+        ("Every day", "daily"),
+        ("weekly", "weekly"),
+        ("weekly on Thu, mon", "weekly on mon, thu"),
+        ("every friday", "weekly on fri"),
+        ("monthly on day 31", "monthly on day 31"),
+        ("monthly on 1st", "monthly on day 1"),
+        ("every 3 days", "every 3 days after completion"),
+        ("every 1 day after completion", "every 1 day after completion")
+    ]
+    {
+        let recurrence = Recurrence::parse(rule).unwrap();
+        assert_eq!(recurrence.to_string(), canonical);
+        assert_eq!(Recurrence::parse(canonical), Some(recurrence));
+    }
+    for rule in ["", "hourly", "weekly on someday", "monthly on day 32", "every 0 days"]
+    {
+        assert_eq!(Recurrence::parse(rule), None, "{}", rule);
+    }
+}
+
+#[test]
+fn next_dates_follow_the_calendar_or_the_completion()
+{
+    // 2024-03-01 is a Friday
+    assert_eq!(next("daily", Some("2024-03-01"), "2024-03-01"), "2024-03-02");
+    // done early, it stays on schedule; done late, it isn't overdue again
+    assert_eq!(next("daily", Some("2024-03-05"), "2024-03-01"), "2024-03-06");
+    assert_eq!(next("weekly", Some("2024-02-02"), "2024-03-01"), "2024-03-08");
+    assert_eq!(next("weekly on mon, thu", Some("2024-03-01"), "2024-03-01"), "2024-03-04");
+    assert_eq!(next("weekly on mon, thu", None, "2024-03-04"), "2024-03-07");
+    // short months use their last day
+    assert_eq!(next("monthly on day 31", Some("2024-01-31"), "2024-01-31"), "2024-02-29");
+    assert_eq!(next("monthly on day 31", Some("2024-02-29"), "2024-02-29"), "2024-03-31");
+    assert_eq!(next("every 10 days", Some("2024-01-01"), "2024-03-01"), "2024-03-11");
+    // a time of day is kept
+    assert_eq!(next("daily", Some("2024-03-01T09:30:00Z"), "2024-02-20"), "2024-03-02T09:30:00Z");
+}
+
+#[test]
+fn completing_a_recurring_task_brings_it_back()
+{
+    let mut graph = Graph::new(Config::default());
+    let home = graph.add_node_to("home".to_string(), NodeType::Goal, None).unwrap();
+    let chores = graph.add_node_to("chores".to_string(), NodeType::Goal, None).unwrap();
+    let review = graph.add_node_to("weekly review".to_string(), NodeType::Task, Some(home)).unwrap();
+    graph.link(&chores, &review).unwrap();
+    let inbox = graph.add_node_to("empty inbox".to_string(), NodeType::Task, Some(review)).unwrap();
+    graph.set_due_date(inbox, Some("2024-02-29".to_string())).unwrap();
+    graph.add_node_to("plan".to_string(), NodeType::Task, Some(review)).unwrap();
+    graph.add_node_to("one-off".to_string(), NodeType::Task, Some(home)).unwrap();
+    graph.set_due_date(review, Some("2024-03-01".to_string())).unwrap();
+    graph.set_recurrence(review, Some(Recurrence::parse("weekly").unwrap())).unwrap();
+
+    let again = graph.complete(vec![review, 5], day("2024-03-01")).unwrap();
+    assert!(graph.validate().is_ok());
+    assert_eq!(graph.nodes().len(), 5);
+    assert_eq!(again.len(), 1);
+
+    let review = graph.node(again[0]).unwrap();
+    assert_eq!(review.description, "weekly review");
+    assert_eq!(review.due_date.as_deref(), Some("2024-03-08"));
+    assert_eq!(review.completed, vec!["2024-03-01"]);
+    assert_eq!(review.parents.iter().copied().collect::<Vec<_>>(), vec![home, chores]);
+    let below: Vec<(&str, Option<&str>)> = review.deps.iter()
+        .map(|x| graph.node(*x).unwrap())
+        .map(|x| (&x.description[..], x.due_date.as_deref()))
+        .collect();
+    assert_eq!(below, vec![("empty inbox", Some("2024-03-07")), ("plan", None)]);
+
+    // completed along with what it is under, it is gone for good
+    graph.complete(vec![home, again[0]], day("2024-03-08")).unwrap();
+    assert_eq!(graph.nodes().iter().map(|x| &x.description[..]).collect::<Vec<_>>(), vec!["chores"]);
+}
@@ -0,0 +1,112 @@
+//! Golden-output tests for the tree views. The expected output lives in
+//! `tests/golden`; run with `UPDATE_GOLDEN=1` to rewrite it after an
+//! intentional change to the rendering.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use todos::{Config, Graph, NodeType};
+
+fn check_golden(name: &str, actual: &str)
+{
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.txt", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some()
+    {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing golden file {}", path.display()));
+    assert!(
+        expected == actual,
+        "rendered output differs from {}\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+/// A small graph touching every rendering rule: goals, conditions, a task
+/// with two parents, a non-leaf task and a backlog.
+fn sample(config: Config) -> Graph
+{
+    let mut graph = Graph::new(config);
+    let mut add = |description: &str, node_type: NodeType, to: Option<usize>| {
+        graph.add_node_to(description.to_string(), node_type, to).unwrap()
+    };
+
+    let house = add("house", NodeType::Goal, None);
+    let paint = add("paint the fence", NodeType::Task, Some(house));
+    add("buy paint", NodeType::Task, Some(paint));
+    add("dry weather", NodeType::Condition, Some(paint));
+    let backlog = add("backlog", NodeType::Goal, None);
+    let garden = add("garden", NodeType::Goal, Some(backlog));
+    let weed = add("pull weeds", NodeType::Task, Some(garden));
+    add("mow the lawn", NodeType::Task, Some(backlog));
+
+    graph.link(&house, &weed).unwrap();
+    graph.set_notes(paint, Some("Two coats.\nThe *blue* one.".to_string())).unwrap();
+    graph.tag(paint, vec!["diy".to_string(), "+home".to_string()]).unwrap();
+    graph
+}
+
+#[test]
+fn default_view()
+{
+    check_golden("default", &sample(Config::default()).render_todos(false));
+}
+
+#[test]
+fn overwhelm_view()
+{
+    check_golden("overwhelm", &sample(Config::default()).render_todos(true));
+}
+
+#[test]
+fn backlog_shown_when_not_hidden()
+{
+    let config = Config { hide_backlog_items: false, ..Config::default() };
+    check_golden("backlog_shown", &sample(config).render_todos(false));
+}
+
+#[test]
+fn under_backlog()
+{
+    let graph = sample(Config::default());
+    let mut out = String::new();
+    graph.render_show(&mut out, &4, 0, false, Some(4)).unwrap();
+    check_golden("under_backlog", &out);
+}
+
+#[test]
+fn effective_root()
+{
+    let mut graph = sample(Config::default());
+    graph.set_effective_root(Some(1)).unwrap();
+    check_golden("effective_root", &graph.render_todos(false));
+}
+
+#[test]
+fn details()
+{
+    check_golden("details", &sample(Config::default()).render_details(1).unwrap());
+}
+
+#[test]
+fn deleted_effective_root()
+{
+    // the graph API never leaves a dangling effective root behind, but a file
+    // edited by hand can
+    let graph: Graph = serde_json::from_str(r#"{"version":1,"effective_root":3,"nodes":[]}"#).unwrap();
+    check_golden("deleted_effective_root", &graph.render_todos(false));
+}
+
+#[test]
+fn completing_the_effective_root_returns_to_root()
+{
+    let mut graph = sample(Config::default());
+    graph.set_effective_root(Some(1)).unwrap();
+    graph.remove_node(1, true).unwrap();
+    check_golden("after_completing_root", &graph.render_todos(false));
+}
@@ -0,0 +1,97 @@
+use serde_json::{json, Value};
+use todos::rpc::{handle, run};
+use todos::{Config, Graph};
+
+fn call(graph: &mut Graph, id: u64, method: &str, params: Value) -> Value
+{
+    let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    let (response, _) = handle(graph, &request.to_string());
+    let response = response.unwrap();
+    assert_eq!(response["id"], json!(id));
+    response
+}
+
+fn ids(result: &Value) -> Vec<u64>
+{
+    result.as_array().unwrap().iter().map(|x| x["id"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn methods_follow_the_commands_and_queries()
+{
+    let mut graph = Graph::new(Config::default());
+    call(&mut graph, 1, "add", json!({ "description": "house", "type": "goal" }));
+    call(&mut graph, 2, "add", json!({ "description": "paint", "to": 0 }));
+    call(&mut graph, 3, "add", json!({ "description": "buy paint", "to": 1 }));
+    let added = call(&mut graph, 4, "add", json!({ "description": "plan", "above": 2 }));
+    assert_eq!(added["result"]["parents"], json!([1]));
+
+    assert_eq!(ids(&call(&mut graph, 5, "ancestors", json!({ "id": 2 }))["result"]), vec![3, 1, 0]);
+    assert_eq!(ids(&call(&mut graph, 6, "leaves", Value::Null)["result"]), vec![2]);
+
+    call(&mut graph, 7, "tag", json!({ "id": 2, "tags": ["Shop"] }));
+    assert_eq!(ids(&call(&mut graph, 8, "search", json!({ "query": "shop" }))["result"]), vec![2]);
+    assert_eq!(ids(&call(&mut graph, 9, "search", json!({ "query": "PAINT" }))["result"]), vec![1, 2]);
+
+    let shown = call(&mut graph, 10, "show", json!({ "overwhelm": true }));
+    assert_eq!(shown["result"], "house goal (id 0):\n  1 (task): paint\n    3 (task): plan\n      2 (task): buy paint\n");
+
+    let summary = call(&mut graph, 11, "edit_tree", json!({ "root": 0, "outline": "#1 paint walls\n  dry weather?\n" }));
+    assert_eq!(summary["result"]["added"], 1);
+    assert_eq!(summary["result"]["removed"], 2);
+    assert_eq!(graph.nodes()[1].description, "paint walls");
+
+    call(&mut graph, 12, "complete", json!({ "ids": [1] }));
+    assert_eq!(graph.nodes().len(), 1);
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn errors_and_notifications()
+{
+    let mut graph = Graph::new(Config::default());
+    call(&mut graph, 1, "add", json!({ "description": "a", "type": "goal" }));
+    call(&mut graph, 2, "add", json!({ "description": "b", "to": 0 }));
+
+    let error = &call(&mut graph, 3, "link", json!({ "parent": 1, "child": 0 }))["error"];
+    assert_eq!(error["code"], -32000);
+    assert_eq!(error["data"]["error"], "cycle");
+    assert_eq!(call(&mut graph, 4, "node", json!({ "id": 5 }))["error"]["data"]["error"], "not_found");
+    assert_eq!(call(&mut graph, 5, "link", json!({ "parent": "a" }))["error"]["code"], -32602);
+    assert_eq!(call(&mut graph, 6, "tui", Value::Null)["error"]["code"], -32601);
+    assert_eq!(handle(&mut graph, "{").0.unwrap()["error"]["code"], -32700);
+    assert_eq!(handle(&mut graph, r#"{"id":7,"method":"nodes"}"#).0.unwrap()["error"]["code"], -32600);
+
+    // a notification gets no response, but changes are still announced
+    let input = concat!(
+        r#"{"jsonrpc":"2.0","method":"edit","params":{"id":1,"description":"c"}}"#, "\n",
+        "\n",
+        r#"{"jsonrpc":"2.0","id":8,"method":"node","params":{"id":1}}"#, "\n"
+    );
+    let mut output = Vec::new();
+    run(&mut graph, input.as_bytes(), &mut output).unwrap();
+    let lines: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|x| serde_json::from_str(x).unwrap()).collect();
+    assert_eq!(lines, vec![
+        json!({ "jsonrpc": "2.0", "method": "changed", "params": { "method": "edit" } }),
+        json!({ "jsonrpc": "2.0", "id": 8, "result": graph.nodes()[1].to_json() })
+    ]);
+    assert_eq!(graph.nodes()[1].description, "c");
+}
+
+#[test]
+fn report_time_answers_what_the_command_prints()
+{
+    let mut graph = Graph::new(Config::default());
+    call(&mut graph, 1, "add", json!({ "description": "garden", "type": "goal" }));
+    call(&mut graph, 2, "add", json!({ "description": "weed", "to": 0 }));
+    let started = chrono::Utc::now() - chrono::Duration::minutes(90);
+    graph.start_timer(1, started).unwrap();
+    graph.stop_timer(started + chrono::Duration::minutes(30)).unwrap();
+
+    let report = call(&mut graph, 3, "report_time", Value::Null);
+    assert_eq!(report["result"], graph.render_time_report(None, chrono::Utc::now()));
+    assert!(report["result"].as_str().unwrap().ends_with("     30m  in total\n"), "{}", report);
+
+    assert_eq!(call(&mut graph, 4, "report_time", json!({ "since": "2999-01-01" }))["result"], "");
+    assert_eq!(call(&mut graph, 5, "report_time", json!({ "since": "someday" }))["error"]["code"], -32602);
+}
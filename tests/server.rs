@@ -0,0 +1,81 @@
+#![cfg(feature = "server")]
+
+use serde_json::{json, Value};
+use todos::server::{handle, screen};
+use todos::{Config, Graph};
+
+fn request(graph: &mut Graph, method: &str, path: &str, body: Value) -> (u16, Value)
+{
+    let body = if body.is_null() { String::new() } else { body.to_string() };
+    let response = handle(graph, method, path, &body);
+    (response.status, response.body)
+}
+
+#[test]
+fn api_changes_the_graph()
+{
+    let mut graph = Graph::new(Config::default());
+
+    let (status, house) = request(&mut graph, "POST", "/nodes", json!({ "description": "house", "type": "goal" }));
+    assert_eq!(status, 201);
+    assert_eq!(house["type"], "goal");
+    request(&mut graph, "POST", "/nodes", json!({ "description": "paint", "to": 0 }));
+    request(&mut graph, "POST", "/nodes", json!({ "description": "dry weather", "type": "condition" }));
+
+    assert_eq!(request(&mut graph, "POST", "/edges", json!({ "parent": 1, "child": 2 })).0, 201);
+    assert_eq!(request(&mut graph, "GET", "/edges", Value::Null).1, json!([{ "parent": 0, "child": 1 }, { "parent": 1, "child": 2 }]));
+    assert_eq!(request(&mut graph, "DELETE", "/edges/1/2", Value::Null).0, 204);
+    assert_eq!(request(&mut graph, "GET", "/nodes/2", Value::Null).1["parents"], json!([]));
+
+    let (status, paint) = request(&mut graph, "PATCH", "/nodes/1", json!({ "description": "paint walls" }));
+    assert_eq!(status, 200);
+    assert_eq!(paint["description"], "paint walls");
+
+    let (_, leaves) = request(&mut graph, "GET", "/leaves?x=1", Value::Null);
+    assert_eq!(leaves.as_array().unwrap().iter().map(|x| x["id"].clone()).collect::<Vec<_>>(), vec![json!(1)]);
+
+    assert_eq!(request(&mut graph, "PUT", "/root", json!({ "id": 0 })).1, json!({ "id": 0 }));
+    assert_eq!(request(&mut graph, "GET", "/root", Value::Null).1, json!({ "id": 0 }));
+    assert_eq!(request(&mut graph, "PUT", "/root", json!({ "id": null })).1, json!({ "id": null }));
+
+    assert_eq!(request(&mut graph, "POST", "/nodes/0/complete", Value::Null), (200, json!({ "recurring": [] })));
+    assert_eq!(request(&mut graph, "GET", "/nodes", Value::Null).1.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn api_errors_are_the_graph_errors()
+{
+    let mut graph = Graph::new(Config::default());
+    request(&mut graph, "POST", "/nodes", json!({ "description": "a", "type": "goal" }));
+    request(&mut graph, "POST", "/nodes", json!({ "description": "b", "to": 0 }));
+
+    let (status, body) = request(&mut graph, "GET", "/nodes/9", Value::Null);
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "not_found");
+    assert_eq!(body["message"], "Node with id 9 not present in todos.");
+
+    let (status, body) = request(&mut graph, "POST", "/edges", json!({ "parent": 1, "child": 0 }));
+    assert_eq!(status, 409);
+    assert_eq!(body["error"], "cycle");
+    assert_eq!(request(&mut graph, "GET", "/edges", Value::Null).1.as_array().unwrap().len(), 1);
+
+    let (status, body) = request(&mut graph, "POST", "/nodes", json!({ "description": " " }));
+    assert_eq!((status, body["error"].clone()), (400, json!("invalid")));
+    assert_eq!(handle(&mut graph, "POST", "/nodes", "not json").status, 400);
+    assert_eq!(request(&mut graph, "DELETE", "/leaves", Value::Null).0, 405);
+    assert_eq!(request(&mut graph, "GET", "/elsewhere", Value::Null).0, 404);
+}
+
+#[test]
+fn only_local_json_requests_are_served()
+{
+    let json = Some("application/json; charset=utf-8");
+    assert!(screen(Some("127.0.0.1:7878"), None, json, "{}").is_none());
+    assert!(screen(Some("localhost"), Some("http://localhost:7878"), None, "").is_none());
+
+    // DNS rebinding, a foreign page, and a "simple" cross-site POST
+    assert_eq!(screen(Some("evil.example:7878"), None, json, "{}").unwrap().status, 403);
+    assert_eq!(screen(None, None, json, "{}").unwrap().status, 403);
+    assert_eq!(screen(Some("127.0.0.1:7878"), Some("https://evil.example"), json, "{}").unwrap().status, 403);
+    assert_eq!(screen(Some("127.0.0.1:7878"), None, Some("text/plain"), "{}").unwrap().status, 415);
+}
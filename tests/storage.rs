@@ -0,0 +1,157 @@
+#![cfg(feature = "sqlite")]
+
+use chrono::{DateTime, Duration, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+use todos::storage::{self, Backend};
+use todos::{Config, Graph, NodeType};
+
+fn temp_path(name: &str) -> PathBuf
+{
+    std::env::temp_dir().join(format!("todos-test-{}-{}", std::process::id(), name))
+}
+
+fn remove(paths: &[&Path])
+{
+    for path in paths
+    {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// A new, empty todos file at `path` with the given backend.
+fn create(path: &Path, backend: Backend) -> Graph
+{
+    remove(&[path]);
+    let mut graph = Graph::new(Config::default());
+    graph.set_storage(storage::create(path, backend).unwrap());
+    graph
+}
+
+/// Saves `graph` and checks that loading the file again gives the same graph.
+fn save_and_reload(graph: &mut Graph, path: &Path)
+{
+    graph.save().unwrap();
+    let reloaded = Graph::open(path, Config::default()).unwrap();
+    assert_eq!(serde_json::to_value(&reloaded).unwrap(), serde_json::to_value(&*graph).unwrap());
+    assert!(reloaded.validate().is_ok());
+}
+
+fn at(minutes: i64) -> DateTime<Utc>
+{
+    DateTime::parse_from_rfc3339("2024-03-01T09:00:00Z").unwrap().with_timezone(&Utc) + Duration::minutes(minutes)
+}
+
+/// house > (paint > (buy paint, dry weather), sweep), and garden > weed.
+fn fill(graph: &mut Graph)
+{
+    let house = graph.add_node_to(String::from("house"), NodeType::Goal, None).unwrap();
+    let paint = graph.add_node_to(String::from("paint"), NodeType::Task, Some(house)).unwrap();
+    graph.add_node_to(String::from("buy paint"), NodeType::Task, Some(paint)).unwrap();
+    graph.add_node_to(String::from("dry weather"), NodeType::Condition, Some(paint)).unwrap();
+    graph.add_node_to(String::from("sweep"), NodeType::Task, Some(house)).unwrap();
+    let garden = graph.add_node_to(String::from("garden"), NodeType::Goal, None).unwrap();
+    graph.add_node_to(String::from("weed"), NodeType::Task, Some(garden)).unwrap();
+    graph.set_due_date(paint, Some(String::from("2024-06-01"))).unwrap();
+    graph.set_notes(paint, Some(String::from("Two coats."))).unwrap();
+    graph.tag(paint, vec![String::from("+home")]).unwrap();
+}
+
+#[test]
+fn sqlite_keeps_every_change_across_saves()
+{
+    let path = temp_path("sqlite-changes");
+    let mut graph = create(&path, Backend::Sqlite);
+
+    fill(&mut graph);
+    save_and_reload(&mut graph, &path);
+
+    // weed goes under house too, then paint loses dry weather
+    graph.link(&0, &6).unwrap();
+    save_and_reload(&mut graph, &path);
+    graph.unlink(&1, &3).unwrap();
+    save_and_reload(&mut graph, &path);
+
+    graph.set_effective_root(Some(6)).unwrap();
+    save_and_reload(&mut graph, &path);
+
+    graph.start_timer(6, at(0)).unwrap();
+    save_and_reload(&mut graph, &path);
+    graph.stop_timer(at(30)).unwrap();
+    graph.start_timer(4, at(30)).unwrap();
+    save_and_reload(&mut graph, &path);
+
+    // removing a node from the middle moves the last one into its id, the
+    // effective root with it
+    graph.remove_node(3, false).unwrap();
+    assert_eq!(graph.effective_root(), Some(3));
+    save_and_reload(&mut graph, &path);
+    let reloaded = Graph::open(&path, Config::default()).unwrap();
+    assert_eq!(reloaded.nodes().len(), 6);
+    assert_eq!(reloaded.node(3).unwrap().description, "weed");
+    assert_eq!(reloaded.node(3).unwrap().time.len(), 1);
+    assert_eq!(reloaded.running(), Some(4));
+
+    graph.set_effective_root(None).unwrap();
+    save_and_reload(&mut graph, &path);
+    assert_eq!(Graph::open(&path, Config::default()).unwrap().effective_root(), None);
+
+    remove(&[&path]);
+}
+
+#[test]
+fn migrate_to_sqlite_and_back_keeps_the_graph()
+{
+    let path = temp_path("migrate");
+    let backup = temp_path("migrate.bak");
+    let mut graph = create(&path, Backend::Json);
+    fill(&mut graph);
+    graph.link(&0, &6).unwrap();
+    graph.set_effective_root(Some(1)).unwrap();
+    graph.start_timer(2, at(0)).unwrap();
+    graph.save().unwrap();
+    let original = serde_json::to_value(&graph).unwrap();
+    drop(graph);
+
+    assert_eq!(storage::migrate(&path, Backend::Sqlite, Config::default()).unwrap(), backup);
+    assert_eq!(Backend::detect(&path).unwrap(), Backend::Sqlite);
+    assert_eq!(serde_json::to_value(Graph::open(&path, Config::default()).unwrap()).unwrap(), original);
+
+    // migrating to the backend in use is refused
+    assert!(storage::migrate(&path, Backend::Sqlite, Config::default()).is_err());
+
+    storage::migrate(&path, Backend::Json, Config::default()).unwrap();
+    assert_eq!(Backend::detect(&path).unwrap(), Backend::Json);
+    assert_eq!(serde_json::to_value(Graph::open(&path, Config::default()).unwrap()).unwrap(), original);
+
+    remove(&[&path, &backup]);
+}
+
+#[test]
+fn version_1_databases_are_upgraded()
+{
+    let path = temp_path("sqlite-version-1");
+    remove(&[&path]);
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection.execute_batch("
+        CREATE TABLE nodes (id INTEGER PRIMARY KEY, node_type TEXT NOT NULL, description TEXT NOT NULL, due_date TEXT, extra TEXT NOT NULL DEFAULT '{}');
+        CREATE TABLE edges (parent INTEGER NOT NULL, child INTEGER NOT NULL, position INTEGER NOT NULL, PRIMARY KEY (parent, child));
+        CREATE INDEX edges_by_child ON edges (child);
+        CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT);
+        INSERT INTO nodes (id, node_type, description) VALUES (0, 'Goal', 'house'), (1, 'Goal', 'garden'), (2, 'Task', 'weed');
+        INSERT INTO edges VALUES (1, 2, 0), (0, 2, 0);
+        PRAGMA user_version = 1;
+    ").unwrap();
+    drop(connection);
+
+    // version 1 read parents in the order of their ids
+    let mut graph = Graph::open(&path, Config::default()).unwrap();
+    assert_eq!(graph.node(2).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![0, 1]);
+
+    graph.unlink(&0, &2).unwrap();
+    graph.link(&0, &2).unwrap();
+    save_and_reload(&mut graph, &path);
+    assert_eq!(graph.node(2).unwrap().parents.iter().copied().collect::<Vec<_>>(), vec![1, 0]);
+
+    remove(&[&path]);
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use todos::dates;
+use todos::{Config, Graph, NodeType};
+
+fn at(minutes: i64) -> DateTime<Utc>
+{
+    DateTime::parse_from_rfc3339("2024-03-01T09:00:00Z").unwrap().with_timezone(&Utc) + Duration::minutes(minutes)
+}
+
+#[test]
+fn timers_add_up_under_goals()
+{
+    let mut graph = Graph::new(Config::default());
+    let goal = graph.add_node_to(String::from("garden"), NodeType::Goal, None).unwrap();
+    let weed = graph.add_node_to(String::from("weed"), NodeType::Task, Some(goal)).unwrap();
+    let taxes = graph.add_node_to(String::from("taxes"), NodeType::Task, None).unwrap();
+
+    assert_eq!(graph.start_timer(weed, at(0)).unwrap(), None);
+    assert!(graph.start_timer(weed, at(1)).is_err());
+    // starting another node stops the first
+    assert_eq!(graph.start_timer(taxes, at(30)).unwrap(), Some(weed));
+    assert_eq!(graph.running(), Some(taxes));
+    assert!(graph.render_timer(at(40)).unwrap().contains("10m"));
+
+    assert_eq!(graph.stop_timer(at(45)).unwrap(), (taxes, Duration::minutes(15)));
+    assert!(graph.stop_timer(at(50)).is_err());
+    assert_eq!(graph.running(), None);
+
+    assert_eq!(graph.tracked(goal, None, at(60)).unwrap(), Duration::minutes(30));
+    assert_eq!(graph.tracked(goal, Some(at(20)), at(60)).unwrap(), Duration::minutes(10));
+
+    let report = graph.render_time_report(None, at(60));
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", report);
+    assert!(lines[0].starts_with("     30m  ") && lines[0].contains("garden"));
+    assert_eq!(lines[1], "     15m  not under any goal");
+    assert_eq!(lines[2], "     45m  in total");
+    assert_eq!(graph.render_time_report(Some(at(60)), at(60)), "");
+
+    let today = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap(); // a Wednesday
+    assert_eq!(dates::parse_past_day("monday", today), NaiveDate::from_ymd_opt(2024, 3, 4));
+    assert_eq!(dates::parse_past_day("last week", today), NaiveDate::from_ymd_opt(2024, 2, 26));
+    assert_eq!(dates::format_duration(Duration::minutes(125)), "2h 05m");
+}
+
+#[test]
+fn completed_nodes_leave_their_time_above()
+{
+    let mut graph = Graph::new(Config::default());
+    let goal = graph.add_node_to(String::from("garden"), NodeType::Goal, None).unwrap();
+    let beds = graph.add_node_to(String::from("beds"), NodeType::Condition, Some(goal)).unwrap();
+    let paths = graph.add_node_to(String::from("paths"), NodeType::Condition, Some(goal)).unwrap();
+    let weed = graph.add_node_to(String::from("weed"), NodeType::Task, Some(beds)).unwrap();
+    graph.link(&paths, &weed).unwrap();
+
+    graph.start_timer(weed, at(0)).unwrap();
+    graph.stop_timer(at(20)).unwrap();
+    graph.start_timer(beds, at(20)).unwrap();
+    graph.complete(vec![beds], NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).unwrap();
+
+    // the timer stopped with the node, and weed's time went to both paths
+    // and the goal, but counts once
+    assert_eq!(graph.running(), None);
+    let goal = graph.nodes().iter().position(|x| x.description == "garden").unwrap();
+    let paths = graph.nodes().iter().position(|x| x.description == "paths").unwrap();
+    assert_eq!(graph.nodes()[paths].time.len(), 1);
+    assert_eq!(graph.nodes()[goal].time.len(), 2);
+    assert!(graph.tracked(goal, None, Utc::now()).unwrap() >= Duration::minutes(20));
+    assert_eq!(graph.tracked(paths, None, Utc::now()).unwrap(), Duration::minutes(20));
+}
+
+#[test]
+fn timers_started_in_the_same_second_both_count()
+{
+    let mut graph = Graph::new(Config::default());
+    let goal = graph.add_node_to(String::from("garden"), NodeType::Goal, None).unwrap();
+    let weed = graph.add_node_to(String::from("weed"), NodeType::Task, Some(goal)).unwrap();
+    let water = graph.add_node_to(String::from("water"), NodeType::Task, Some(goal)).unwrap();
+
+    // the first gets an interval with no length, starting when the second does
+    graph.start_timer(weed, at(0)).unwrap();
+    graph.start_timer(water, at(0)).unwrap();
+    assert_eq!(graph.running(), Some(water));
+    graph.stop_timer(at(10)).unwrap();
+    assert_eq!(graph.tracked(goal, None, at(20)).unwrap(), Duration::minutes(10));
+    assert!(graph.render_time_report(None, at(20)).ends_with("     10m  in total\n"));
+
+    // and still both once handed up
+    graph.complete(vec![weed, water], NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).unwrap();
+    assert_eq!(graph.nodes()[0].time.len(), 2);
+    assert_eq!(graph.tracked(0, None, at(20)).unwrap(), Duration::minutes(10));
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "tui")]
+
+use todos::tui::{App, Key};
+use todos::{Config, Graph, NodeType};
+
+/// house > paint > (buy paint, dry weather), and a backlog goal with a task.
+fn sample() -> Graph
+{
+    let mut graph = Graph::new(Config::default());
+    let house = graph.add_node_to("house".to_string(), NodeType::Goal, None).unwrap();
+    let paint = graph.add_node_to("paint".to_string(), NodeType::Task, Some(house)).unwrap();
+    graph.add_node_to("buy paint".to_string(), NodeType::Task, Some(paint)).unwrap();
+    graph.add_node_to("dry weather".to_string(), NodeType::Condition, Some(paint)).unwrap();
+    let backlog = graph.add_node_to("backlog".to_string(), NodeType::Goal, None).unwrap();
+    graph.add_node_to("banjo".to_string(), NodeType::Task, Some(backlog)).unwrap();
+    graph
+}
+
+fn press(app: &mut App, graph: &mut Graph, keys: &[Key])
+{
+    for key in keys
+    {
+        app.handle_key(graph, *key);
+    }
+}
+
+fn typed(text: &str) -> Vec<Key>
+{
+    text.chars().map(Key::Char).collect()
+}
+
+fn shown(app: &App, graph: &Graph) -> Vec<String>
+{
+    app.rows(graph).iter().map(|x| format!("{}{}", "  ".repeat(x.level), graph.nodes()[x.id].description)).collect()
+}
+
+#[test]
+fn tree_hides_what_todos_hides_until_asked()
+{
+    let mut graph = sample();
+    let mut app = App::new();
+    assert_eq!(shown(&app, &graph), vec!["house", "  buy paint", "  dry weather", "backlog"]);
+
+    // overwhelm shows the task in between, and the backlog
+    press(&mut app, &mut graph, &[Key::Char('o')]);
+    assert_eq!(shown(&app, &graph), vec!["house", "  paint", "    buy paint", "    dry weather", "backlog", "  banjo"]);
+
+    press(&mut app, &mut graph, &[Key::Char('o'), Key::Char('b')]);
+    assert_eq!(shown(&app, &graph).len(), 5);
+
+    // collapse house, and move to its child after expanding it again
+    press(&mut app, &mut graph, &[Key::Home, Key::Left]);
+    assert_eq!(shown(&app, &graph), vec!["house", "backlog", "  banjo"]);
+    press(&mut app, &mut graph, &[Key::Right, Key::Right]);
+    assert_eq!(app.selected(&graph), Some(2));
+    press(&mut app, &mut graph, &[Key::Left]);
+    assert_eq!(app.selected(&graph), Some(0));
+
+    let screen = app.render(&graph, 30, 8);
+    assert_eq!(screen.len(), 8);
+    assert!(screen[0].contains("▾ house goal (id 0):"));
+    assert!(screen[0].contains(";7m"));
+
+    // using a node as the root changes the graph
+    press(&mut app, &mut graph, &[Key::Down, Key::Char('u')]);
+    assert_eq!(graph.effective_root(), Some(2));
+    assert_eq!(shown(&app, &graph), vec!["buy paint"]);
+    press(&mut app, &mut graph, &[Key::Char('R'), Key::Char('q')]);
+    assert_eq!(graph.effective_root(), None);
+    assert!(app.quit());
+}
+
+#[test]
+fn keys_change_the_graph()
+{
+    let mut graph = sample();
+    let mut app = App::new();
+
+    // add a goal under house, then a task under that
+    press(&mut app, &mut graph, &[Key::Char('a')]);
+    press(&mut app, &mut graph, &typed("goal: garden"));
+    press(&mut app, &mut graph, &[Key::Enter, Key::Char('a')]);
+    press(&mut app, &mut graph, &typed("weed"));
+    press(&mut app, &mut graph, &[Key::Enter]);
+    let garden = &graph.nodes()[6];
+    assert_eq!(garden.node_type, NodeType::Goal);
+    assert_eq!(garden.parents.iter().copied().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(graph.nodes()[7].parents.iter().copied().collect::<Vec<_>>(), vec![6]);
+    assert_eq!(app.selected(&graph), Some(7));
+
+    // relabel it
+    press(&mut app, &mut graph, &[Key::Char('r'), Key::Backspace, Key::Backspace, Key::Backspace]);
+    press(&mut app, &mut graph, &typed("ater"));
+    press(&mut app, &mut graph, &[Key::Enter]);
+    assert_eq!(graph.nodes()[7].description, "water");
+
+    // link buy paint under garden: pick the parent, move to the child
+    press(&mut app, &mut graph, &[Key::Home, Key::Down, Key::Down, Key::Down, Key::Char('L'), Key::Up, Key::Up, Key::Enter]);
+    assert!(graph.nodes()[6].deps.contains(&2), "{}", app.status());
+
+    // a link that would make a cycle is refused
+    press(&mut app, &mut graph, &[Key::End, Key::Up, Key::Char('L'), Key::Up, Key::Up, Key::Enter]);
+    assert!(app.status().contains("cycle"), "{}", app.status());
+
+    // unlink it again
+    press(&mut app, &mut graph, &[Key::Home, Key::Down, Key::Down, Key::Down, Key::Char('U'), Key::Down, Key::Down, Key::Enter]);
+    assert!(!graph.nodes()[6].deps.contains(&2), "{}", app.status());
+
+    // completing a leaf needs no confirmation; a subtree does
+    press(&mut app, &mut graph, &[Key::Home, Key::Down, Key::Char('c')]);
+    assert_eq!(graph.nodes().len(), 7);
+    press(&mut app, &mut graph, &[Key::Home, Key::Char('c'), Key::Char('n')]);
+    assert_eq!(graph.nodes().len(), 7);
+    press(&mut app, &mut graph, &[Key::Char('c'), Key::Char('y')]);
+    assert_eq!(graph.nodes().len(), 2);
+    assert!(graph.validate().is_ok());
+}
+
+#[test]
+fn changes_made_elsewhere_drop_what_was_picked_by_id()
+{
+    let mut graph = sample();
+    let mut app = App::new();
+
+    // pick house as a parent and move to backlog, then another process
+    // removes dry weather and banjo takes its id
+    press(&mut app, &mut graph, &[Key::Char('o'), Key::Home, Key::Char('L'), Key::End, Key::Up]);
+    graph.remove_node(3, false).unwrap();
+    app.graph_changed();
+    assert!(app.status().contains("changed elsewhere"), "{}", app.status());
+
+    let before = serde_json::to_value(&graph).unwrap();
+    press(&mut app, &mut graph, &[Key::Enter]);
+    assert_eq!(serde_json::to_value(&graph).unwrap(), before);
+}
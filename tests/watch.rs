@@ -0,0 +1,45 @@
+use todos::watch::{diff, render, Diff};
+use todos::{Config, Graph, NodeType};
+
+fn sample() -> Graph
+{
+    let mut graph = Graph::new(Config::default());
+    graph.add_node_to("house".to_string(), NodeType::Goal, None).unwrap();
+    graph.add_node_to("paint".to_string(), NodeType::Task, Some(0)).unwrap();
+    graph.add_node_to("sweep".to_string(), NodeType::Task, Some(0)).unwrap();
+    graph.add_node_to("sweep".to_string(), NodeType::Task, Some(0)).unwrap();
+    graph
+}
+
+#[test]
+fn diff_matches_nodes_whose_ids_moved()
+{
+    let before = sample();
+    let mut after = sample();
+    // the last sweep takes over paint's id
+    after.batch_remove(vec![1], false).unwrap();
+    assert_eq!(diff(&before, &after), Diff { added: vec![], completed: vec![String::from("paint")] });
+
+    after.add_node_to("sweep".to_string(), NodeType::Task, Some(0)).unwrap();
+    after.relabel(0, "home".to_string()).unwrap();
+    assert_eq!(diff(&before, &after), Diff { added: vec![0, 3], completed: vec![String::from("house"), String::from("paint")] });
+}
+
+#[test]
+fn render_marks_what_changed()
+{
+    let before = sample();
+    let mut after = sample();
+    after.batch_remove(vec![2], false).unwrap();
+    after.add_node_to("rake".to_string(), NodeType::Task, Some(0)).unwrap();
+
+    let view = render(&after, None, false, &diff(&before, &after)).unwrap();
+    assert!(view.contains("3 (task): rake\x1B[00m \x1B[1;32m(new)\x1B[00m\n"));
+    assert!(view.ends_with("\nCompleted:\n  \x1B[9msweep\x1B[00m\n"));
+
+    // unchanged, it is just what `todos` shows
+    assert_eq!(render(&after, None, false, &Diff::default()).unwrap(), after.render_todos(false));
+    let mut under = String::new();
+    after.render_show(&mut under, &0, 0, true, Some(0)).unwrap();
+    assert_eq!(render(&after, Some(0), true, &Diff::default()).unwrap(), under);
+}